@@ -0,0 +1,203 @@
+use commandy_macros::*;
+use libpep::distributed::protocol::{PEPSystemServer, Request, Response, ServerError};
+use libpep::distributed::systems::PEPSystemConfig;
+use std::io::Read;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use tiny_http::{Header, Method, Server};
+
+const DEFAULT_BIND: &str = "127.0.0.1:8080";
+const DEFAULT_MAX_REQUEST_SIZE: usize = 1024 * 1024;
+const DEFAULT_THREADS: usize = 4;
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Command, Debug, Default)]
+#[description(
+    "HTTP server for a PEP transcryptor, answering requests of the libpep protocol as JSON. The server has no authentication: anyone who can connect obtains session key shares and transcryption, so it must only be reachable through an authenticating proxy."
+)]
+#[program("peppy-server")]
+struct Options {
+    /// JSON configuration file with the secrets and blinding factor of this transcryptor
+    #[flag("-c", "--config", "file")]
+    config: String,
+    /// address to listen on (default 127.0.0.1:8080, use port 0 for any free port)
+    #[flag("-b", "--bind", "address")]
+    bind: Option<SocketAddr>,
+    /// allow listening on an address other than loopback, for use behind an authenticating proxy
+    #[flag("--allow-remote")]
+    allow_remote: bool,
+    /// maximum size of a request body in bytes (default 1 MiB)
+    #[flag("-m", "--max-request-size", "bytes")]
+    max_request_size: Option<usize>,
+    /// number of worker threads (default 4)
+    #[flag("-t", "--threads", "n")]
+    threads: Option<usize>,
+}
+
+fn json_header() -> Header {
+    Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..]).unwrap()
+}
+
+fn respond(request: tiny_http::Request, status: u16, body: String) {
+    let response = tiny_http::Response::from_string(body)
+        .with_status_code(status)
+        .with_header(json_header());
+    if let Err(e) = request.respond(response) {
+        eprintln!("Failed to send response: {}", e);
+    }
+}
+
+fn respond_protocol(request: tiny_http::Request, response: &Response) {
+    let status = match response {
+        Response::Error(ServerError::InvalidRequest(_)) => 400,
+        Response::Error(ServerError::Refused(_)) => 413,
+        Response::Error(ServerError::UnsupportedRequest(_)) => 501,
+        Response::Error(ServerError::Internal(_)) => 500,
+        _ => 200,
+    };
+    match serde_json::to_string(response) {
+        Ok(body) => respond(request, status, body),
+        Err(e) => respond(
+            request,
+            500,
+            format!(r#"{{"type":"error","value":{{"kind":"internal","message":"{e}"}}}}"#),
+        ),
+    }
+}
+
+fn read_body(
+    request: &mut tiny_http::Request,
+    max_request_size: usize,
+) -> Result<Vec<u8>, ServerError> {
+    let too_large =
+        || ServerError::Refused(format!("request body exceeds {} bytes", max_request_size));
+    if request.body_length().is_some_and(|l| l > max_request_size) {
+        return Err(too_large());
+    }
+    let mut body = Vec::new();
+    request
+        .as_reader()
+        .take(max_request_size as u64 + 1)
+        .read_to_end(&mut body)
+        .map_err(|e| ServerError::InvalidRequest(e.to_string()))?;
+    if body.len() > max_request_size {
+        return Err(too_large());
+    }
+    Ok(body)
+}
+
+fn handle(
+    mut request: tiny_http::Request,
+    server: &PEPSystemServer,
+    max_request_size: usize,
+    shutting_down: &AtomicBool,
+) {
+    match (request.method(), request.url()) {
+        (Method::Get, "/health") => respond(request, 200, r#"{"status":"ok"}"#.to_string()),
+        (Method::Get, "/ready") if shutting_down.load(Ordering::SeqCst) => {
+            respond(request, 503, r#"{"status":"shutting_down"}"#.to_string())
+        }
+        (Method::Get, "/ready") => respond(request, 200, r#"{"status":"ready"}"#.to_string()),
+        (Method::Post, "/v1/request") => {
+            let response = match read_body(&mut request, max_request_size) {
+                Ok(body) => match serde_json::from_slice::<Request>(&body) {
+                    Ok(r) => server.handle(r),
+                    Err(e) => Response::Error(ServerError::InvalidRequest(e.to_string())),
+                },
+                Err(e) => Response::Error(e),
+            };
+            respond_protocol(request, &response);
+        }
+        (_, "/health" | "/ready" | "/v1/request") => respond(
+            request,
+            405,
+            r#"{"status":"method_not_allowed"}"#.to_string(),
+        ),
+        _ => respond(request, 404, r#"{"status":"not_found"}"#.to_string()),
+    }
+}
+
+fn main() {
+    let options: Options = commandy::parse_args();
+    if options.config.is_empty() {
+        eprintln!("No configuration file given (use --config).");
+        std::process::exit(1);
+    }
+    let config = std::fs::read_to_string(&options.config).unwrap_or_else(|e| {
+        eprintln!(
+            "Could not read configuration file {}: {}",
+            options.config, e
+        );
+        std::process::exit(1);
+    });
+    let config: PEPSystemConfig = serde_json::from_str(&config).unwrap_or_else(|e| {
+        eprintln!("Invalid configuration file {}: {}", options.config, e);
+        std::process::exit(1);
+    });
+    let bind = options
+        .bind
+        .unwrap_or_else(|| DEFAULT_BIND.parse().unwrap());
+    // Requests are not authenticated, so they should only come from this host or a proxy.
+    if !bind.ip().is_loopback() && !options.allow_remote {
+        eprintln!(
+            "Refusing to listen on {} without authentication (use --allow-remote behind an authenticating proxy).",
+            bind
+        );
+        std::process::exit(1);
+    }
+    let max_request_size = options.max_request_size.unwrap_or(DEFAULT_MAX_REQUEST_SIZE);
+    let threads = options.threads.unwrap_or(DEFAULT_THREADS).max(1);
+
+    let http = Arc::new(Server::http(bind).unwrap_or_else(|e| {
+        eprintln!("Could not listen on {}: {}", bind, e);
+        std::process::exit(1);
+    }));
+    let server = Arc::new(PEPSystemServer::new(config.build()));
+    let shutting_down = Arc::new(AtomicBool::new(false));
+    {
+        let shutting_down = shutting_down.clone();
+        ctrlc::set_handler(move || shutting_down.store(true, Ordering::SeqCst)).unwrap_or_else(
+            |e| {
+                eprintln!("Could not install signal handler: {}", e);
+                std::process::exit(1);
+            },
+        );
+    }
+
+    eprint!("Listening on: ");
+    match http.server_addr().to_ip() {
+        Some(addr) => println!("{}", addr),
+        None => println!("{}", bind),
+    }
+
+    // Every worker stops accepting new connections once a shutdown is requested, but finishes
+    // the request it is handling.
+    let workers: Vec<_> = (0..threads)
+        .map(|_| {
+            let http = http.clone();
+            let server = server.clone();
+            let shutting_down = shutting_down.clone();
+            thread::spawn(move || {
+                while !shutting_down.load(Ordering::SeqCst) {
+                    match http.recv_timeout(POLL_INTERVAL) {
+                        Ok(Some(request)) => {
+                            handle(request, &server, max_request_size, &shutting_down)
+                        }
+                        Ok(None) => {}
+                        Err(e) => {
+                            eprintln!("Failed to receive request: {}", e);
+                            break;
+                        }
+                    }
+                }
+            })
+        })
+        .collect();
+    for worker in workers {
+        let _ = worker.join();
+    }
+    eprintln!("Shut down.");
+}
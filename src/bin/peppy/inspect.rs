@@ -0,0 +1,180 @@
+//! Identification and validation of encoded values of unknown type, for `peppy inspect`.
+
+use crate::origin::pkcs7_unpad;
+use base64::engine::general_purpose;
+use base64::Engine;
+use libpep::internal::arithmetic::{GroupElement, ScalarCanBeZero, ScalarNonZero, G};
+use libpep::low_level::elgamal::ELGAMAL_LENGTH;
+use serde_json::{json, Map, Value};
+
+/// The ways in which a string may encode bytes.
+fn decodings(value: &str) -> Vec<(&'static str, Vec<u8>)> {
+    let mut decodings = Vec::new();
+    if let Ok(bytes) = hex::decode(value) {
+        decodings.push(("hex", bytes));
+    }
+    if let Ok(bytes) = general_purpose::URL_SAFE.decode(value) {
+        decodings.push(("base64 (URL-safe)", bytes));
+    } else if let Ok(bytes) = general_purpose::STANDARD.decode(value) {
+        decodings.push(("base64", bytes));
+    }
+    decodings
+}
+
+/// The text of 16 bytes, if printable. Values shorter than 16 bytes are padded with PKCS#7 padding
+/// or zeros.
+fn text(bytes: &[u8]) -> Option<&str> {
+    let length = bytes.iter().rposition(|&b| b != 0).map_or(0, |i| i + 1);
+    let value = pkcs7_unpad(bytes).unwrap_or(&bytes[..length]);
+    std::str::from_utf8(value)
+        .ok()
+        .filter(|text| !text.chars().any(char::is_control))
+}
+
+/// The 16 bytes a lizard encoded group element was created from, and their text if printable.
+fn lizard(element: &GroupElement, fields: &mut Map<String, Value>) {
+    if let Some(bytes) = element.encode_lizard() {
+        fields.insert("lizard".to_string(), json!(hex::encode(bytes)));
+        if let Some(text) = text(&bytes) {
+            fields.insert("lizard_text".to_string(), json!(text));
+        }
+    }
+}
+
+fn group_element(element: &GroupElement, secret_key: Option<&ScalarNonZero>) -> Value {
+    let mut fields = Map::new();
+    fields.insert("type".to_string(), json!("group_element"));
+    fields.insert(
+        "could_be".to_string(),
+        json!("pseudonym, data point or public key"),
+    );
+    // Ristretto decoding only accepts canonical encodings.
+    fields.insert("canonical".to_string(), json!(true));
+    fields.insert(
+        "identity".to_string(),
+        json!(*element == GroupElement::identity()),
+    );
+    lizard(element, &mut fields);
+    if let Some(secret_key) = secret_key {
+        fields.insert(
+            "public_key_of_secret_key".to_string(),
+            json!(*element == secret_key * G),
+        );
+    }
+    Value::Object(fields)
+}
+
+fn scalar(bytes: &[u8]) -> Value {
+    let scalar = ScalarCanBeZero::decode_from_slice(bytes);
+    let mut fields = Map::new();
+    fields.insert("type".to_string(), json!("scalar"));
+    fields.insert(
+        "could_be".to_string(),
+        json!("secret key, blinding factor or session key share"),
+    );
+    // Scalar decoding only accepts values smaller than the group order.
+    fields.insert("canonical".to_string(), json!(scalar.is_some()));
+    if let Some(scalar) = scalar {
+        fields.insert("zero".to_string(), json!(scalar.is_zero()));
+    }
+    Value::Object(fields)
+}
+
+fn elgamal(bytes: &[u8], secret_key: Option<&ScalarNonZero>) -> Value {
+    let names = ["gb", "gc", "gy"];
+    let components: Vec<Option<GroupElement>> = bytes
+        .chunks(32)
+        .map(GroupElement::decode_from_slice)
+        .collect();
+    let mut fields = Map::new();
+    fields.insert("type".to_string(), json!("elgamal"));
+    fields.insert(
+        "could_be".to_string(),
+        json!(if components.len() == 3 {
+            "encrypted pseudonym or data point, with public key (elgamal3)"
+        } else {
+            "encrypted pseudonym or data point"
+        }),
+    );
+    fields.insert(
+        "supported_by_this_build".to_string(),
+        json!(bytes.len() == ELGAMAL_LENGTH),
+    );
+    let invalid: Vec<_> = components
+        .iter()
+        .zip(names)
+        .filter(|(x, _)| x.is_none())
+        .map(|(_, name)| name)
+        .collect();
+    fields.insert("valid".to_string(), json!(invalid.is_empty()));
+    if !invalid.is_empty() {
+        fields.insert("invalid_components".to_string(), json!(invalid));
+        return Value::Object(fields);
+    }
+    let components: Vec<GroupElement> = components.into_iter().flatten().collect();
+    // An identity gb means the message is not encrypted at all, an identity gy means it is
+    // encrypted for the identity as public key.
+    let degenerate: Vec<_> = components
+        .iter()
+        .zip(names)
+        .filter(|(x, _)| **x == GroupElement::identity())
+        .map(|(_, name)| name)
+        .collect();
+    fields.insert("degenerate".to_string(), json!(!degenerate.is_empty()));
+    if !degenerate.is_empty() {
+        fields.insert("identity_components".to_string(), json!(degenerate));
+    }
+    if let Some(secret_key) = secret_key {
+        if let Some(gy) = components.get(2) {
+            fields.insert(
+                "encrypted_for_secret_key".to_string(),
+                json!(*gy == secret_key * G),
+            );
+        }
+        let decrypted = components[1] - secret_key * components[0];
+        let mut plaintext = Map::new();
+        plaintext.insert("value".to_string(), json!(decrypted.encode_as_hex()));
+        lizard(&decrypted, &mut plaintext);
+        fields.insert("decrypted".to_string(), Value::Object(plaintext));
+    }
+    Value::Object(fields)
+}
+
+/// All interpretations of a hex or base64 encoded value, optionally checked against a secret key.
+/// Only encodings resulting in a known length (16, 32, 64 or 96 bytes) are reported.
+pub fn inspect(value: &str, secret_key: Option<&ScalarNonZero>) -> Vec<Value> {
+    decodings(value.trim())
+        .into_iter()
+        .filter_map(|(encoding, bytes)| {
+            let candidates = match bytes.len() {
+                16 => {
+                    let mut fields = Map::new();
+                    fields.insert("type".to_string(), json!("bytes"));
+                    fields.insert(
+                        "could_be".to_string(),
+                        json!("origin of a pseudonym or data point"),
+                    );
+                    if let Some(text) = text(&bytes) {
+                        fields.insert("text".to_string(), json!(text));
+                    }
+                    vec![Value::Object(fields)]
+                }
+                32 => {
+                    let mut candidates = Vec::new();
+                    if let Some(element) = GroupElement::decode_from_slice(&bytes) {
+                        candidates.push(group_element(&element, secret_key));
+                    }
+                    candidates.push(scalar(&bytes));
+                    candidates
+                }
+                64 | 96 => vec![elgamal(&bytes, secret_key)],
+                _ => return None,
+            };
+            Some(json!({
+                "encoding": encoding,
+                "length": bytes.len(),
+                "candidates": candidates,
+            }))
+        })
+        .collect()
+}
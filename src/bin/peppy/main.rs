@@ -0,0 +1,1448 @@
+mod inspect;
+mod origin;
+mod output;
+mod resolver;
+#[cfg(unix)]
+mod serve;
+mod syntax;
+mod table;
+
+use crate::inspect::inspect;
+use crate::origin::{
+    origin_from_pseudonyms, pkcs7_pad, pkcs7_unpad, pseudonyms_from_origin, Mode as OriginMode,
+    DEFAULT_LABEL,
+};
+use crate::output::{batch, fail, set_json_output, ErrorKind, Output};
+use crate::resolver::Resolver;
+#[cfg(unix)]
+use crate::serve::{serve, State};
+use crate::syntax::check_syntax;
+use crate::table::{data_points, process_table, Column, BLOCK_SEPARATOR};
+use commandy::{ArgumentParser, ParseArgumentAction};
+use commandy_macros::*;
+use libpep::distributed::key_blinding::{
+    make_distributed_global_keys, make_session_key, SafeScalar, SessionKeyShare,
+};
+#[cfg(unix)]
+use libpep::distributed::systems::PEPClient;
+use libpep::distributed::systems::PEPSystem;
+use libpep::high_level::contexts::{RekeyInfo, TranscryptionInfo};
+use libpep::high_level::data_types::{
+    DataPoint, Encryptable, Encrypted, EncryptedDataPoint, EncryptedPseudonym, Pseudonym,
+};
+#[cfg(feature = "separate-session-keys")]
+use libpep::high_level::keys::make_pseudonym_session_keys;
+use libpep::high_level::keys::{
+    make_global_keys, make_session_keys, GlobalPublicKey, GlobalSecretKey, PseudonymizationSecret,
+    PublicKey, SecretKey, SessionPublicKey, SessionSecretKey,
+};
+use libpep::high_level::ops::{decrypt, encrypt, encrypt_global, rekey, rerandomize, transcrypt};
+use libpep::internal::arithmetic::ScalarTraits;
+use rand_core::OsRng;
+use sha2::{Digest, Sha512};
+
+#[derive(Command, Debug, Default)]
+#[command("generate-global-keys")]
+#[description("Outputs a public global key and a secret global key (use once).")]
+struct GenerateGlobalKeys {}
+
+#[derive(Command, Debug, Default)]
+#[command("generate-session-keys")]
+#[description("Outputs a public session key and a secret session key, derived from a global secret key with an encryption secret and session context.")]
+struct GenerateSessionKeys {
+    /// the secret global key
+    #[flag("--global-secret-key", "key")]
+    global_secret_key: Option<String>,
+    /// the encryption secret
+    #[flag("--encryption-secret", "secret")]
+    encryption_secret: Option<String>,
+    /// the context of the session
+    #[flag("--session", "context")]
+    session: Option<String>,
+}
+
+#[derive(Command, Debug, Default)]
+#[command("random-pseudonym")]
+#[description("Create a random new pseudonym.")]
+struct RandomPseudonym {}
+
+#[derive(Command, Debug, Default)]
+#[command("pseudonym-from-origin")]
+#[description("Create a pseudonym from an existing identifier (or pseudonyms, for long identifiers in blocks mode).")]
+struct PseudonymFromOrigin {
+    /// pkcs7 (the default, at most 15 bytes), zero (at most 16 bytes, zero padded, trailing zeros collide), blocks (any length, a pseudonym for every 16 bytes, which links identifiers with a common prefix) or hash (SHA-512, cannot be converted back, recommended for long identifiers)
+    #[flag("-m", "--mode", "mode")]
+    mode: Option<String>,
+    /// domain-separation label for the hash mode
+    #[flag("-l", "--label", "label")]
+    label: Option<String>,
+    #[positional("origin", 1, 1)]
+    args: Vec<String>,
+}
+
+#[derive(Command, Debug, Default)]
+#[command("pseudonym-to-origin")]
+#[description("Try to convert a pseudonym (or the pseudonyms of a long identifier) back to its origin identifier.")]
+struct PseudonymToOrigin {
+    /// the mode the pseudonyms were created with: pkcs7 (the default), zero or blocks
+    #[flag("-m", "--mode", "mode")]
+    mode: Option<String>,
+    #[positional("pseudonym-hex", 1, 1024)]
+    args: Vec<String>,
+}
+
+#[derive(Command, Debug, Default)]
+#[command("random-data-point")]
+#[description("Create a random new data point.")]
+struct RandomDataPoint {}
+
+#[derive(Command, Debug, Default)]
+#[command("data-point-from-bytes")]
+#[description("Create a data point from 16 bytes (hex encoded).")]
+struct DataPointFromBytes {
+    #[positional("bytes-hex", 1, 1)]
+    args: Vec<String>,
+}
+
+#[derive(Command, Debug, Default)]
+#[command("data-point-from-string")]
+#[description("Create a data point from a string of at most 15 bytes, with PKCS#7 padding.")]
+struct DataPointFromString {
+    #[positional("string", 1, 1)]
+    args: Vec<String>,
+}
+
+#[derive(Command, Debug, Default)]
+#[command("data-point-from-hash")]
+#[description("Create a data point from the SHA-512 hash of a string (cannot be converted back).")]
+struct DataPointFromHash {
+    #[positional("string", 1, 1)]
+    args: Vec<String>,
+}
+
+#[derive(Command, Debug, Default)]
+#[command("data-point-to-string")]
+#[description("Try to convert a data point (or the data points of the blocks of a long value) back to the string it was created from.")]
+struct DataPointToString {
+    #[positional("data-point-hex", 1, 1024)]
+    args: Vec<String>,
+}
+
+#[cfg(not(feature = "separate-session-keys"))]
+#[derive(Command, Debug, Default)]
+#[command("encrypt")]
+#[description("Encrypt a pseudonym (or data point) with a session public key.")]
+struct Encrypt {
+    /// the message is a data point instead of a pseudonym
+    #[flag("-d", "--data-point")]
+    data_point: bool,
+    /// the public session key
+    #[flag("--public-key", "key")]
+    public_key: Option<String>,
+    #[positional("message-hex", 1, 1)]
+    args: Vec<String>,
+}
+
+#[cfg(feature = "separate-session-keys")]
+#[derive(Command, Debug, Default)]
+#[command("encrypt")]
+#[description("Encrypt a pseudonym (or data point) with a session public key.")]
+struct Encrypt {
+    /// the message is a data point instead of a pseudonym
+    #[flag("-d", "--data-point")]
+    data_point: bool,
+    /// the public session key for data points
+    #[flag("--public-key", "key")]
+    public_key: Option<String>,
+    /// the public session key for pseudonyms
+    #[flag("--pseudonym-public-key", "key")]
+    pseudonym_public_key: Option<String>,
+    #[positional("message-hex", 1, 1)]
+    args: Vec<String>,
+}
+
+#[derive(Command, Debug, Default)]
+#[command("encrypt-global")]
+#[description("Encrypt a pseudonym (or data point) with a global public key.")]
+struct EncryptGlobal {
+    /// the message is a data point instead of a pseudonym
+    #[flag("-d", "--data-point")]
+    data_point: bool,
+    /// the public global key
+    #[flag("--public-key", "key")]
+    public_key: Option<String>,
+    #[positional("message-hex", 1, 1)]
+    args: Vec<String>,
+}
+
+#[cfg(not(feature = "separate-session-keys"))]
+#[derive(Command, Debug, Default)]
+#[command("decrypt")]
+#[description("Decrypt a pseudonym (or data point) with a session secret key.")]
+struct Decrypt {
+    /// the message is a data point instead of a pseudonym
+    #[flag("-d", "--data-point")]
+    data_point: bool,
+    /// the secret session key
+    #[flag("--secret-key", "key")]
+    secret_key: Option<String>,
+    #[positional("ciphertext", 1, 1)]
+    args: Vec<String>,
+}
+
+#[cfg(feature = "separate-session-keys")]
+#[derive(Command, Debug, Default)]
+#[command("decrypt")]
+#[description("Decrypt a pseudonym (or data point) with a session secret key.")]
+struct Decrypt {
+    /// the message is a data point instead of a pseudonym
+    #[flag("-d", "--data-point")]
+    data_point: bool,
+    /// the secret session key for data points
+    #[flag("--secret-key", "key")]
+    secret_key: Option<String>,
+    /// the secret session key for pseudonyms
+    #[flag("--pseudonym-secret-key", "key")]
+    pseudonym_secret_key: Option<String>,
+    #[positional("ciphertext", 1, 1)]
+    args: Vec<String>,
+}
+
+#[cfg(not(feature = "elgamal3"))]
+#[derive(Command, Debug, Default)]
+#[command("rerandomize")]
+#[description("Rerandomize a ciphertext.")]
+struct Rerandomize {
+    /// the message is a data point instead of a pseudonym
+    #[flag("-d", "--data-point")]
+    data_point: bool,
+    /// the public key the ciphertext is encrypted for
+    #[flag("--public-key", "key")]
+    public_key: Option<String>,
+    #[positional("ciphertext", 1, 1)]
+    args: Vec<String>,
+}
+
+#[cfg(feature = "elgamal3")]
+#[derive(Command, Debug, Default)]
+#[command("rerandomize")]
+#[description("Rerandomize a ciphertext.")]
+struct Rerandomize {
+    /// the message is a data point instead of a pseudonym
+    #[flag("-d", "--data-point")]
+    data_point: bool,
+    #[positional("ciphertext", 1, 1)]
+    args: Vec<String>,
+}
+
+#[derive(Command, Debug, Default)]
+#[command("transcrypt")]
+#[description("Transcrypt a ciphertext from one context to another.")]
+struct Transcrypt {
+    /// the message is a data point instead of a pseudonym
+    #[flag("-d", "--data-point")]
+    data_point: bool,
+    /// the pseudonymization secret
+    #[flag("--pseudonymization-secret", "secret")]
+    pseudonymization_secret: Option<String>,
+    /// the encryption secret
+    #[flag("--encryption-secret", "secret")]
+    encryption_secret: Option<String>,
+    /// the pseudonymization context to transcrypt from
+    #[flag("--pseudonymization-from", "context")]
+    pseudonymization_from: Option<String>,
+    /// the pseudonymization context to transcrypt to
+    #[flag("--pseudonymization-to", "context")]
+    pseudonymization_to: Option<String>,
+    /// the session to transcrypt from
+    #[flag("--session-from", "context")]
+    session_from: Option<String>,
+    /// the session to transcrypt to
+    #[flag("--session-to", "context")]
+    session_to: Option<String>,
+    #[positional("ciphertext", 1, 1)]
+    args: Vec<String>,
+}
+
+#[derive(Command, Debug, Default)]
+#[command("transcrypt-from-global")]
+#[description("Transcrypt a ciphertext from global to a session encryption context.")]
+struct TranscryptFromGlobal {
+    /// the message is a data point instead of a pseudonym
+    #[flag("-d", "--data-point")]
+    data_point: bool,
+    /// the pseudonymization secret
+    #[flag("--pseudonymization-secret", "secret")]
+    pseudonymization_secret: Option<String>,
+    /// the encryption secret
+    #[flag("--encryption-secret", "secret")]
+    encryption_secret: Option<String>,
+    /// the pseudonymization context to transcrypt from
+    #[flag("--pseudonymization-from", "context")]
+    pseudonymization_from: Option<String>,
+    /// the pseudonymization context to transcrypt to
+    #[flag("--pseudonymization-to", "context")]
+    pseudonymization_to: Option<String>,
+    /// the session to transcrypt to
+    #[flag("--session-to", "context")]
+    session_to: Option<String>,
+    #[positional("ciphertext", 1, 1)]
+    args: Vec<String>,
+}
+
+#[derive(Command, Debug, Default)]
+#[command("transcrypt-to-global")]
+#[description("Transcrypt a ciphertext from a session to a global encryption context.")]
+struct TranscryptToGlobal {
+    /// the message is a data point instead of a pseudonym
+    #[flag("-d", "--data-point")]
+    data_point: bool,
+    /// the pseudonymization secret
+    #[flag("--pseudonymization-secret", "secret")]
+    pseudonymization_secret: Option<String>,
+    /// the encryption secret
+    #[flag("--encryption-secret", "secret")]
+    encryption_secret: Option<String>,
+    /// the pseudonymization context to transcrypt from
+    #[flag("--pseudonymization-from", "context")]
+    pseudonymization_from: Option<String>,
+    /// the pseudonymization context to transcrypt to
+    #[flag("--pseudonymization-to", "context")]
+    pseudonymization_to: Option<String>,
+    /// the session to transcrypt from
+    #[flag("--session-from", "context")]
+    session_from: Option<String>,
+    #[positional("ciphertext", 1, 1)]
+    args: Vec<String>,
+}
+
+#[derive(Command, Debug, Default)]
+#[command("rekey")]
+#[description("Rekey an encrypted data point from one session to another.")]
+struct Rekey {
+    /// the encryption secret
+    #[flag("--encryption-secret", "secret")]
+    encryption_secret: Option<String>,
+    /// the session to rekey from
+    #[flag("--session-from", "context")]
+    session_from: Option<String>,
+    /// the session to rekey to
+    #[flag("--session-to", "context")]
+    session_to: Option<String>,
+    #[positional("ciphertext", 1, 1)]
+    args: Vec<String>,
+}
+
+#[derive(Command, Debug, Default)]
+#[command("rekey-from-global")]
+#[description("Rekey an encrypted data point from global to a session encryption context.")]
+struct RekeyFromGlobal {
+    /// the encryption secret
+    #[flag("--encryption-secret", "secret")]
+    encryption_secret: Option<String>,
+    /// the session to rekey to
+    #[flag("--session-to", "context")]
+    session_to: Option<String>,
+    #[positional("ciphertext", 1, 1)]
+    args: Vec<String>,
+}
+
+#[derive(Command, Debug, Default)]
+#[command("rekey-to-global")]
+#[description("Rekey an encrypted data point from a session to a global encryption context.")]
+struct RekeyToGlobal {
+    /// the encryption secret
+    #[flag("--encryption-secret", "secret")]
+    encryption_secret: Option<String>,
+    /// the session to rekey from
+    #[flag("--session-from", "context")]
+    session_from: Option<String>,
+    #[positional("ciphertext", 1, 1)]
+    args: Vec<String>,
+}
+
+#[cfg(not(feature = "separate-session-keys"))]
+#[derive(Command, Debug, Default)]
+#[command("encrypt-batch")]
+#[description("Encrypt newline-delimited pseudonyms (or data points) with a session public key.")]
+struct EncryptBatch {
+    /// the messages are data points instead of pseudonyms
+    #[flag("-d", "--data-point")]
+    data_point: bool,
+    /// read the input from this file instead of stdin
+    #[flag("-i", "--input", "file")]
+    input: Option<String>,
+    /// shuffle the order of the output, so that it cannot be linked to the input
+    #[flag("-s", "--shuffle")]
+    shuffle: bool,
+    /// the public session key
+    #[flag("--public-key", "key")]
+    public_key: Option<String>,
+}
+
+#[cfg(feature = "separate-session-keys")]
+#[derive(Command, Debug, Default)]
+#[command("encrypt-batch")]
+#[description("Encrypt newline-delimited pseudonyms (or data points) with a session public key.")]
+struct EncryptBatch {
+    /// the messages are data points instead of pseudonyms
+    #[flag("-d", "--data-point")]
+    data_point: bool,
+    /// read the input from this file instead of stdin
+    #[flag("-i", "--input", "file")]
+    input: Option<String>,
+    /// shuffle the order of the output, so that it cannot be linked to the input
+    #[flag("-s", "--shuffle")]
+    shuffle: bool,
+    /// the public session key for data points
+    #[flag("--public-key", "key")]
+    public_key: Option<String>,
+    /// the public session key for pseudonyms
+    #[flag("--pseudonym-public-key", "key")]
+    pseudonym_public_key: Option<String>,
+}
+
+#[cfg(not(feature = "separate-session-keys"))]
+#[derive(Command, Debug, Default)]
+#[command("decrypt-batch")]
+#[description("Decrypt newline-delimited ciphertexts with a session secret key.")]
+struct DecryptBatch {
+    /// the messages are data points instead of pseudonyms
+    #[flag("-d", "--data-point")]
+    data_point: bool,
+    /// read the input from this file instead of stdin
+    #[flag("-i", "--input", "file")]
+    input: Option<String>,
+    /// shuffle the order of the output, so that it cannot be linked to the input
+    #[flag("-s", "--shuffle")]
+    shuffle: bool,
+    /// the secret session key
+    #[flag("--secret-key", "key")]
+    secret_key: Option<String>,
+}
+
+#[cfg(feature = "separate-session-keys")]
+#[derive(Command, Debug, Default)]
+#[command("decrypt-batch")]
+#[description("Decrypt newline-delimited ciphertexts with a session secret key.")]
+struct DecryptBatch {
+    /// the messages are data points instead of pseudonyms
+    #[flag("-d", "--data-point")]
+    data_point: bool,
+    /// read the input from this file instead of stdin
+    #[flag("-i", "--input", "file")]
+    input: Option<String>,
+    /// shuffle the order of the output, so that it cannot be linked to the input
+    #[flag("-s", "--shuffle")]
+    shuffle: bool,
+    /// the secret session key for data points
+    #[flag("--secret-key", "key")]
+    secret_key: Option<String>,
+    /// the secret session key for pseudonyms
+    #[flag("--pseudonym-secret-key", "key")]
+    pseudonym_secret_key: Option<String>,
+}
+
+#[cfg(not(feature = "elgamal3"))]
+#[derive(Command, Debug, Default)]
+#[command("rerandomize-batch")]
+#[description("Rerandomize newline-delimited ciphertexts.")]
+struct RerandomizeBatch {
+    /// read the input from this file instead of stdin
+    #[flag("-i", "--input", "file")]
+    input: Option<String>,
+    /// shuffle the order of the output, so that it cannot be linked to the input
+    #[flag("-s", "--shuffle")]
+    shuffle: bool,
+    /// the public key the ciphertexts are encrypted for
+    #[flag("--public-key", "key")]
+    public_key: Option<String>,
+}
+
+#[cfg(feature = "elgamal3")]
+#[derive(Command, Debug, Default)]
+#[command("rerandomize-batch")]
+#[description("Rerandomize newline-delimited ciphertexts.")]
+struct RerandomizeBatch {
+    /// read the input from this file instead of stdin
+    #[flag("-i", "--input", "file")]
+    input: Option<String>,
+    /// shuffle the order of the output, so that it cannot be linked to the input
+    #[flag("-s", "--shuffle")]
+    shuffle: bool,
+}
+
+#[derive(Command, Debug, Default)]
+#[command("transcrypt-batch")]
+#[description("Transcrypt newline-delimited ciphertexts from one context to another.")]
+struct TranscryptBatch {
+    /// the messages are data points instead of pseudonyms
+    #[flag("-d", "--data-point")]
+    data_point: bool,
+    /// read the input from this file instead of stdin
+    #[flag("-i", "--input", "file")]
+    input: Option<String>,
+    /// shuffle the order of the output, so that it cannot be linked to the input
+    #[flag("-s", "--shuffle")]
+    shuffle: bool,
+    /// the pseudonymization secret
+    #[flag("--pseudonymization-secret", "secret")]
+    pseudonymization_secret: Option<String>,
+    /// the encryption secret
+    #[flag("--encryption-secret", "secret")]
+    encryption_secret: Option<String>,
+    /// the pseudonymization context to transcrypt from
+    #[flag("--pseudonymization-from", "context")]
+    pseudonymization_from: Option<String>,
+    /// the pseudonymization context to transcrypt to
+    #[flag("--pseudonymization-to", "context")]
+    pseudonymization_to: Option<String>,
+    /// the session to transcrypt from
+    #[flag("--session-from", "context")]
+    session_from: Option<String>,
+    /// the session to transcrypt to
+    #[flag("--session-to", "context")]
+    session_to: Option<String>,
+}
+
+#[cfg(not(feature = "separate-session-keys"))]
+#[derive(Command, Debug, Default)]
+#[command("encrypt-csv")]
+#[description("Encrypt the columns of a CSV (or TSV) table with a header row, as pseudonyms or data points, with a session public key.")]
+struct EncryptCsv {
+    /// column with pseudonyms (can be given multiple times), all other columns are data points
+    #[flag("-p", "--pseudonym", "column")]
+    pseudonyms: Vec<String>,
+    /// column to copy as is (can be given multiple times)
+    #[flag("-k", "--keep", "column")]
+    keep: Vec<String>,
+    /// the table has tab-separated instead of comma-separated values
+    #[flag("-t", "--tsv")]
+    tsv: bool,
+    /// read the table from this file instead of stdin
+    #[flag("-i", "--input", "file")]
+    input: Option<String>,
+    /// how pseudonyms are created from the values: pkcs7 (the default), zero or hash (as for pseudonym-from-origin)
+    #[flag("-m", "--mode", "mode")]
+    mode: Option<String>,
+    /// domain-separation label for the hash mode
+    #[flag("-l", "--label", "label")]
+    label: Option<String>,
+    /// the public session key
+    #[flag("--public-key", "key")]
+    public_key: Option<String>,
+}
+
+#[cfg(feature = "separate-session-keys")]
+#[derive(Command, Debug, Default)]
+#[command("encrypt-csv")]
+#[description("Encrypt the columns of a CSV (or TSV) table with a header row, as pseudonyms or data points, with a session public key.")]
+struct EncryptCsv {
+    /// column with pseudonyms (can be given multiple times), all other columns are data points
+    #[flag("-p", "--pseudonym", "column")]
+    pseudonyms: Vec<String>,
+    /// column to copy as is (can be given multiple times)
+    #[flag("-k", "--keep", "column")]
+    keep: Vec<String>,
+    /// the table has tab-separated instead of comma-separated values
+    #[flag("-t", "--tsv")]
+    tsv: bool,
+    /// read the table from this file instead of stdin
+    #[flag("-i", "--input", "file")]
+    input: Option<String>,
+    /// how pseudonyms are created from the values: pkcs7 (the default), zero or hash (as for pseudonym-from-origin)
+    #[flag("-m", "--mode", "mode")]
+    mode: Option<String>,
+    /// domain-separation label for the hash mode
+    #[flag("-l", "--label", "label")]
+    label: Option<String>,
+    /// the public session key for data points
+    #[flag("--public-key", "key")]
+    public_key: Option<String>,
+    /// the public session key for pseudonyms
+    #[flag("--pseudonym-public-key", "key")]
+    pseudonym_public_key: Option<String>,
+}
+
+#[derive(Command, Debug, Default)]
+#[command("transcrypt-csv")]
+#[description("Transcrypt the encrypted columns of a CSV (or TSV) table with a header row from one context to another.")]
+struct TranscryptCsv {
+    /// column with pseudonyms (can be given multiple times), all other columns are data points
+    #[flag("-p", "--pseudonym", "column")]
+    pseudonyms: Vec<String>,
+    /// column to copy as is (can be given multiple times)
+    #[flag("-k", "--keep", "column")]
+    keep: Vec<String>,
+    /// the table has tab-separated instead of comma-separated values
+    #[flag("-t", "--tsv")]
+    tsv: bool,
+    /// read the table from this file instead of stdin
+    #[flag("-i", "--input", "file")]
+    input: Option<String>,
+    /// the pseudonymization secret
+    #[flag("--pseudonymization-secret", "secret")]
+    pseudonymization_secret: Option<String>,
+    /// the encryption secret
+    #[flag("--encryption-secret", "secret")]
+    encryption_secret: Option<String>,
+    /// the pseudonymization context to transcrypt from
+    #[flag("--pseudonymization-from", "context")]
+    pseudonymization_from: Option<String>,
+    /// the pseudonymization context to transcrypt to
+    #[flag("--pseudonymization-to", "context")]
+    pseudonymization_to: Option<String>,
+    /// the session to transcrypt from
+    #[flag("--session-from", "context")]
+    session_from: Option<String>,
+    /// the session to transcrypt to
+    #[flag("--session-to", "context")]
+    session_to: Option<String>,
+}
+
+#[derive(Command, Debug, Default)]
+#[command("setup-distributed")]
+#[description("Creates the secrets needed for distributed systems.")]
+struct SetupDistributedSystems {
+    #[positional("n", 1, 1)]
+    args: Vec<String>,
+}
+
+#[derive(Command, Debug, Default)]
+#[command("session-key-share")]
+#[description("Outputs the session key share of a distributed system for a session context, derived from its encryption secret and blinding factor.")]
+struct SessionKeyShareCommand {
+    /// the encryption secret of the system
+    #[flag("--encryption-secret", "secret")]
+    encryption_secret: Option<String>,
+    /// the blinding factor of the system
+    #[flag("--blinding-factor", "factor")]
+    blinding_factor: Option<String>,
+    /// the context of the session
+    #[flag("--session", "context")]
+    session: Option<String>,
+}
+
+#[cfg(not(feature = "separate-session-keys"))]
+#[derive(Command, Debug, Default)]
+#[command("combine-session-key-shares")]
+#[description("Outputs a public session key and a secret session key, combined from the blinded global secret key and the session key shares of all distributed systems.")]
+struct CombineSessionKeyShares {
+    /// the blinded secret global key
+    #[flag("--blinded-global-secret-key", "key")]
+    blinded_global_secret_key: Option<String>,
+    #[positional("session-key-shares", 1, 1024)]
+    args: Vec<String>,
+}
+
+#[cfg(feature = "separate-session-keys")]
+#[derive(Command, Debug, Default)]
+#[command("combine-session-key-shares")]
+#[description("Outputs a public session key and a secret session key, combined from the blinded global secret key and the session key shares of all distributed systems.")]
+struct CombineSessionKeyShares {
+    /// session key share for pseudonyms of a system (once for every system)
+    #[flag("-p", "--pseudonym-share", "share")]
+    pseudonym_shares: Vec<String>,
+    /// the blinded secret global key
+    #[flag("--blinded-global-secret-key", "key")]
+    blinded_global_secret_key: Option<String>,
+    #[positional("session-key-shares", 1, 1024)]
+    args: Vec<String>,
+}
+
+#[derive(Command, Debug, Default)]
+#[command("system-transcrypt")]
+#[description("Transcrypt a ciphertext from one context to another, as one of the distributed systems (described by the configuration file).")]
+struct SystemTranscrypt {
+    /// the ciphertext is a data point instead of a pseudonym
+    #[flag("-d", "--data-point")]
+    data_point: bool,
+    /// the pseudonymization context to transcrypt from
+    #[flag("--pseudonymization-from", "context")]
+    pseudonymization_from: Option<String>,
+    /// the pseudonymization context to transcrypt to
+    #[flag("--pseudonymization-to", "context")]
+    pseudonymization_to: Option<String>,
+    /// the session to transcrypt from
+    #[flag("--session-from", "context")]
+    session_from: Option<String>,
+    /// the session to transcrypt to
+    #[flag("--session-to", "context")]
+    session_to: Option<String>,
+    #[positional("ciphertext", 1, 1)]
+    args: Vec<String>,
+}
+
+#[derive(Command, Debug, Default)]
+#[command("inspect")]
+#[description("Identify a hex or base64 encoded value (pseudonym, data point, key, scalar or ciphertext) and check whether it is valid.")]
+struct Inspect {
+    /// also check the value against this secret key, and decrypt ciphertexts with it
+    #[flag("-s", "--secret-key", "key")]
+    secret_key: Option<String>,
+    #[positional("value", 1, 1)]
+    args: Vec<String>,
+}
+
+#[cfg(all(unix, not(feature = "separate-session-keys")))]
+#[derive(Command, Debug, Default)]
+#[command("serve")]
+#[description("Answer line-delimited JSON requests on a Unix domain socket, as the system described by the configuration file and/or as a client with session key shares.")]
+struct Serve {
+    /// path of the socket to create
+    #[flag("--socket", "path")]
+    socket: Option<String>,
+    /// permissions of the socket, in octal (default 600, only the current user)
+    #[flag("--permissions", "mode")]
+    permissions: Option<String>,
+    /// the blinded secret global key, to encrypt and decrypt as a client
+    #[flag("--blinded-global-secret-key", "key")]
+    blinded_global_secret_key: Option<String>,
+    /// session key share of a system for the client (once for every system)
+    #[flag("--session-key-share", "share")]
+    session_key_shares: Vec<String>,
+}
+
+#[cfg(all(unix, feature = "separate-session-keys"))]
+#[derive(Command, Debug, Default)]
+#[command("serve")]
+#[description("Answer line-delimited JSON requests on a Unix domain socket, as the system described by the configuration file and/or as a client with session key shares.")]
+struct Serve {
+    /// path of the socket to create
+    #[flag("--socket", "path")]
+    socket: Option<String>,
+    /// permissions of the socket, in octal (default 600, only the current user)
+    #[flag("--permissions", "mode")]
+    permissions: Option<String>,
+    /// the blinded secret global key, to encrypt and decrypt as a client
+    #[flag("--blinded-global-secret-key", "key")]
+    blinded_global_secret_key: Option<String>,
+    /// session key share of a system for the client (once for every system)
+    #[flag("--session-key-share", "share")]
+    session_key_shares: Vec<String>,
+    /// session key share for pseudonyms of a system for the client (once for every system)
+    #[flag("-p", "--pseudonym-share", "share")]
+    pseudonym_shares: Vec<String>,
+}
+
+#[derive(Command, Debug)]
+enum Sub {
+    GenerateGlobalKeys(GenerateGlobalKeys),
+    GenerateSessionKeys(GenerateSessionKeys),
+    RandomPseudonym(RandomPseudonym),
+    PseudonymFromOrigin(PseudonymFromOrigin),
+    PseudonymToOrigin(PseudonymToOrigin),
+    RandomDataPoint(RandomDataPoint),
+    DataPointFromBytes(DataPointFromBytes),
+    DataPointFromString(DataPointFromString),
+    DataPointFromHash(DataPointFromHash),
+    DataPointToString(DataPointToString),
+    Encrypt(Encrypt),
+    EncryptGlobal(EncryptGlobal),
+    Decrypt(Decrypt),
+    Rerandomize(Rerandomize),
+    Transcrypt(Transcrypt),
+    TranscryptFromGlobal(TranscryptFromGlobal),
+    TranscryptToGlobal(TranscryptToGlobal),
+    Rekey(Rekey),
+    RekeyFromGlobal(RekeyFromGlobal),
+    RekeyToGlobal(RekeyToGlobal),
+    EncryptBatch(EncryptBatch),
+    DecryptBatch(DecryptBatch),
+    RerandomizeBatch(RerandomizeBatch),
+    TranscryptBatch(TranscryptBatch),
+    EncryptCsv(EncryptCsv),
+    TranscryptCsv(TranscryptCsv),
+    SetupDistributedSystems(SetupDistributedSystems),
+    SessionKeyShare(SessionKeyShareCommand),
+    CombineSessionKeyShares(CombineSessionKeyShares),
+    SystemTranscrypt(SystemTranscrypt),
+    Inspect(Inspect),
+    #[cfg(unix)]
+    Serve(Serve),
+}
+
+#[derive(Command, Debug, Default)]
+#[description("operations on PEP pseudonyms and data points")]
+#[program("peppy")] // can have an argument, outputs man-page + shell completion
+struct Options {
+    /// configuration file of a transcryptor (JSON), for arguments given as config:
+    #[flag("-c", "--config", "file")]
+    config: Option<String>,
+    /// output a JSON object (or one per line in batch mode), also for errors
+    #[flag("-j", "--json")]
+    json: bool,
+    #[subcommands()]
+    subcommand: Option<Sub>,
+}
+
+/// Parse the command line. All errors of the command line (unknown flags or subcommands, a wrong
+/// number of positional values and invalid flag values) are usage errors.
+fn parse_options() -> Options {
+    let args: Vec<String> = std::env::args().collect();
+    let mut args: Vec<&str> = args.iter().map(String::as_str).collect();
+    if let Err(e) = check_syntax(&args) {
+        fail(ErrorKind::Usage, e);
+    }
+    let mut options = Options::default();
+    if let Err(e) = options.parse_arguments(&mut args, "", ParseArgumentAction::Parse) {
+        fail(ErrorKind::Usage, format!("Invalid arguments: {}", e));
+    }
+    options
+}
+
+/// Decode an argument, or fail with an error naming it.
+fn decode<T>(value: Option<T>, name: &str) -> T {
+    value.unwrap_or_else(|| fail(ErrorKind::InvalidInput, format!("{}: invalid value", name)))
+}
+
+/// The value of an option without a default, or fail naming it.
+fn required<'a>(value: &'a Option<String>, name: &str) -> &'a str {
+    value
+        .as_deref()
+        .unwrap_or_else(|| fail(ErrorKind::Usage, format!("Missing option {}.", name)))
+}
+
+/// Decode a session public key option, or fail naming it.
+fn session_public_key(value: &Option<String>, name: &str) -> SessionPublicKey {
+    decode(SessionPublicKey::from_hex(required(value, name)), name)
+}
+
+/// Transcrypt a base64 encoded ciphertext of a pseudonym (or data point).
+fn transcrypt_value(ciphertext: &str, data_point: bool, info: &TranscryptionInfo) -> String {
+    if data_point {
+        let ciphertext = decode(EncryptedDataPoint::from_base64(ciphertext), "ciphertext");
+        transcrypt(&ciphertext, info).encode_as_base64()
+    } else {
+        let ciphertext = decode(EncryptedPseudonym::from_base64(ciphertext), "ciphertext");
+        transcrypt(&ciphertext, info).encode_as_base64()
+    }
+}
+
+/// The mode of converting origin identifiers to pseudonyms, which defaults to PKCS#7 padding.
+fn origin_mode(mode: &Option<String>) -> OriginMode {
+    mode.as_deref()
+        .map_or(Ok(OriginMode::Pkcs7), str::parse)
+        .unwrap_or_else(|e| fail(ErrorKind::Usage, e))
+}
+
+/// The domain-separation label of the hash mode, which cannot be given for other modes.
+fn origin_label(mode: OriginMode, label: &Option<String>) -> &str {
+    if label.is_some() && mode != OriginMode::Hash {
+        fail(ErrorKind::Usage, "A label can only be used in hash mode.");
+    }
+    label.as_deref().unwrap_or(DEFAULT_LABEL)
+}
+
+fn main() {
+    let mut rng = OsRng;
+    let options = parse_options();
+    set_json_output(options.json);
+    let resolver = Resolver::new(options.config.as_deref());
+    let mut output = Output::new();
+    match options.subcommand {
+        Some(Sub::GenerateGlobalKeys(_)) => {
+            let (pk, sk) = make_global_keys(&mut rng);
+            output.value("global_public_key", "Public global key", pk.encode_as_hex());
+            output.value(
+                "global_secret_key",
+                "Secret global key",
+                sk.value().encode_as_hex(),
+            );
+        }
+        Some(Sub::GenerateSessionKeys(arg)) => {
+            let global_secret_key = GlobalSecretKey::from(
+                resolver.scalar(arg.global_secret_key.as_deref(), "--global-secret-key"),
+            );
+            let encryption_secret = resolver.encryption_secret(arg.encryption_secret.as_deref());
+            let session_context = resolver.session(arg.session.as_deref());
+
+            let (session_pk, session_sk) =
+                make_session_keys(&global_secret_key, &session_context, &encryption_secret);
+            output.value(
+                "session_public_key",
+                "Public session key",
+                session_pk.encode_as_hex(),
+            );
+            output.value(
+                "session_secret_key",
+                "Secret session key",
+                session_sk.value().encode_as_hex(),
+            );
+            #[cfg(feature = "separate-session-keys")]
+            {
+                let (session_pk, session_sk) = make_pseudonym_session_keys(
+                    &global_secret_key,
+                    &session_context,
+                    &encryption_secret,
+                );
+                output.value(
+                    "pseudonym_session_public_key",
+                    "Public pseudonym session key",
+                    session_pk.encode_as_hex(),
+                );
+                output.value(
+                    "pseudonym_session_secret_key",
+                    "Secret pseudonym session key",
+                    session_sk.value().encode_as_hex(),
+                );
+            }
+        }
+        Some(Sub::RandomPseudonym(_)) => {
+            let pseudonym = Pseudonym::random(&mut rng);
+            output.value("pseudonym", "Random pseudonym", pseudonym.encode_as_hex());
+        }
+        Some(Sub::PseudonymFromOrigin(arg)) => {
+            let mode = origin_mode(&arg.mode);
+            let label = origin_label(mode, &arg.label);
+            let pseudonyms = pseudonyms_from_origin(arg.args[0].as_bytes(), mode, label)
+                .unwrap_or_else(|e| fail(ErrorKind::InvalidInput, e));
+            let pseudonyms: Vec<_> = pseudonyms.iter().map(|x| x.encode_as_hex()).collect();
+            if mode == OriginMode::Blocks {
+                output.list("pseudonyms", "Pseudonyms", &pseudonyms);
+            } else {
+                output.value("pseudonym", "Pseudonym", &pseudonyms[0]);
+            }
+        }
+        Some(Sub::PseudonymToOrigin(arg)) => {
+            let mode = origin_mode(&arg.mode);
+            if mode == OriginMode::Hash {
+                fail(
+                    ErrorKind::Usage,
+                    "Pseudonyms created from a hash cannot be converted back.",
+                );
+            }
+            let pseudonyms: Vec<Pseudonym> = arg
+                .args
+                .iter()
+                .map(|x| decode(Pseudonym::decode_from_hex(x), "pseudonym"))
+                .collect();
+            let origin = origin_from_pseudonyms(&pseudonyms, mode)
+                .unwrap_or_else(|e| fail(ErrorKind::InvalidInput, e));
+            output.value("origin", "Origin", String::from_utf8_lossy(&origin));
+        }
+        Some(Sub::RandomDataPoint(_)) => {
+            let data_point = DataPoint::random(&mut rng);
+            output.value(
+                "data_point",
+                "Random data point",
+                data_point.encode_as_hex(),
+            );
+        }
+        Some(Sub::DataPointFromBytes(arg)) => {
+            let bytes: [u8; 16] = match hex::decode(&arg.args[0]).map(|x| x.try_into()) {
+                Ok(Ok(bytes)) => bytes,
+                _ => {
+                    fail(
+                        ErrorKind::InvalidInput,
+                        "Data must be 16 bytes, hex encoded.",
+                    );
+                }
+            };
+            let data_point = DataPoint::from_bytes(&bytes);
+            output.value("data_point", "Data point", data_point.encode_as_hex());
+        }
+        Some(Sub::DataPointFromString(arg)) => {
+            if arg.args[0].len() > 15 {
+                fail(
+                    ErrorKind::InvalidInput,
+                    "String must be at most 15 bytes long.",
+                );
+            }
+            let bytes = pkcs7_pad(arg.args[0].as_bytes());
+            let data_point = DataPoint::from_bytes(bytes.as_slice().try_into().unwrap());
+            output.value("data_point", "Data point", data_point.encode_as_hex());
+        }
+        Some(Sub::DataPointFromHash(arg)) => {
+            let hash: [u8; 64] = Sha512::digest(arg.args[0].as_bytes()).into();
+            let data_point = DataPoint::from_hash(&hash);
+            output.value("data_point", "Data point", data_point.encode_as_hex());
+        }
+        Some(Sub::DataPointToString(arg)) => {
+            let mut padded = Vec::with_capacity(arg.args.len() * 16);
+            for data_point in &arg.args {
+                let data_point = decode(DataPoint::decode_from_hex(data_point), "data-point");
+                let bytes = data_point.as_bytes().unwrap_or_else(|| {
+                    fail(
+                        ErrorKind::InvalidInput,
+                        "Data point was not created from bytes.",
+                    )
+                });
+                padded.extend_from_slice(&bytes);
+            }
+            let string = pkcs7_unpad(&padded)
+                .unwrap_or_else(|| fail(ErrorKind::InvalidInput, "Invalid padding."));
+            output.value("string", "String", String::from_utf8_lossy(string));
+        }
+        Some(Sub::Encrypt(arg)) => {
+            #[cfg(feature = "separate-session-keys")]
+            let public_key = if arg.data_point {
+                session_public_key(&arg.public_key, "--public-key")
+            } else {
+                session_public_key(&arg.pseudonym_public_key, "--pseudonym-public-key")
+            };
+            #[cfg(not(feature = "separate-session-keys"))]
+            let public_key = session_public_key(&arg.public_key, "--public-key");
+            let ciphertext = if arg.data_point {
+                let data_point = decode(DataPoint::decode_from_hex(&arg.args[0]), "data-point");
+                encrypt(&data_point, &public_key, &mut rng).encode_as_base64()
+            } else {
+                let pseudonym = decode(Pseudonym::decode_from_hex(&arg.args[0]), "pseudonym");
+                encrypt(&pseudonym, &public_key, &mut rng).encode_as_base64()
+            };
+            output.value("ciphertext", "Ciphertext", ciphertext);
+        }
+        Some(Sub::EncryptGlobal(arg)) => {
+            let public_key = decode(
+                GlobalPublicKey::from_hex(required(&arg.public_key, "--public-key")),
+                "--public-key",
+            );
+            let ciphertext = if arg.data_point {
+                let data_point = decode(DataPoint::decode_from_hex(&arg.args[0]), "data-point");
+                encrypt_global(&data_point, &public_key, &mut rng).encode_as_base64()
+            } else {
+                let pseudonym = decode(Pseudonym::decode_from_hex(&arg.args[0]), "pseudonym");
+                encrypt_global(&pseudonym, &public_key, &mut rng).encode_as_base64()
+            };
+            output.value("ciphertext", "Ciphertext", ciphertext);
+        }
+        Some(Sub::Decrypt(arg)) => {
+            #[cfg(feature = "separate-session-keys")]
+            let secret_key = if arg.data_point {
+                resolver.scalar(arg.secret_key.as_deref(), "--secret-key")
+            } else {
+                resolver.scalar(
+                    arg.pseudonym_secret_key.as_deref(),
+                    "--pseudonym-secret-key",
+                )
+            };
+            #[cfg(not(feature = "separate-session-keys"))]
+            let secret_key = resolver.scalar(arg.secret_key.as_deref(), "--secret-key");
+            let secret_key = SessionSecretKey::from(secret_key);
+            let plaintext = if arg.data_point {
+                let ciphertext =
+                    decode(EncryptedDataPoint::from_base64(&arg.args[0]), "ciphertext");
+                decrypt(&ciphertext, &secret_key).encode_as_hex()
+            } else {
+                let ciphertext =
+                    decode(EncryptedPseudonym::from_base64(&arg.args[0]), "ciphertext");
+                decrypt(&ciphertext, &secret_key).encode_as_hex()
+            };
+            output.value("plaintext", "Plaintext", plaintext);
+        }
+        Some(Sub::Rerandomize(arg)) => {
+            // Pseudonyms and data points are rerandomized in the same way.
+            let ciphertext = if arg.data_point {
+                decode(EncryptedDataPoint::from_base64(&arg.args[0]), "ciphertext").value
+            } else {
+                decode(EncryptedPseudonym::from_base64(&arg.args[0]), "ciphertext").value
+            };
+            let ciphertext = EncryptedDataPoint::from(ciphertext);
+            let rerandomized;
+            #[cfg(not(feature = "elgamal3"))]
+            {
+                let public_key = session_public_key(&arg.public_key, "--public-key");
+                rerandomized = rerandomize(&ciphertext, &public_key, &mut rng);
+            }
+            #[cfg(feature = "elgamal3")]
+            {
+                rerandomized = rerandomize(&ciphertext, &mut rng);
+            }
+            output.value(
+                "ciphertext",
+                "Rerandomized ciphertext",
+                rerandomized.encode_as_base64(),
+            );
+        }
+        Some(Sub::Transcrypt(arg)) => {
+            let transcryption_info = TranscryptionInfo::new(
+                &resolver.pseudonymization_context_from(arg.pseudonymization_from.as_deref()),
+                &resolver.pseudonymization_context_to(arg.pseudonymization_to.as_deref()),
+                &resolver.session_from(arg.session_from.as_deref()),
+                &resolver.session_to(arg.session_to.as_deref()),
+                &resolver.pseudonymization_secret(arg.pseudonymization_secret.as_deref()),
+                &resolver.encryption_secret(arg.encryption_secret.as_deref()),
+            );
+            let transcrypted = transcrypt_value(&arg.args[0], arg.data_point, &transcryption_info);
+            output.value("ciphertext", "Transcrypted ciphertext", transcrypted);
+        }
+        Some(Sub::TranscryptFromGlobal(arg)) => {
+            let transcryption_info = TranscryptionInfo::new_from_global(
+                &resolver.pseudonymization_context_from(arg.pseudonymization_from.as_deref()),
+                &resolver.pseudonymization_context_to(arg.pseudonymization_to.as_deref()),
+                &resolver.session_to(arg.session_to.as_deref()),
+                &resolver.pseudonymization_secret(arg.pseudonymization_secret.as_deref()),
+                &resolver.encryption_secret(arg.encryption_secret.as_deref()),
+            );
+            let transcrypted = transcrypt_value(&arg.args[0], arg.data_point, &transcryption_info);
+            output.value("ciphertext", "Transcrypted ciphertext", transcrypted);
+        }
+        Some(Sub::TranscryptToGlobal(arg)) => {
+            let transcryption_info = TranscryptionInfo::new_to_global(
+                &resolver.pseudonymization_context_from(arg.pseudonymization_from.as_deref()),
+                &resolver.pseudonymization_context_to(arg.pseudonymization_to.as_deref()),
+                &resolver.session_from(arg.session_from.as_deref()),
+                &resolver.pseudonymization_secret(arg.pseudonymization_secret.as_deref()),
+                &resolver.encryption_secret(arg.encryption_secret.as_deref()),
+            );
+            let transcrypted = transcrypt_value(&arg.args[0], arg.data_point, &transcryption_info);
+            output.value("ciphertext", "Transcrypted ciphertext", transcrypted);
+        }
+        Some(Sub::Rekey(arg)) => {
+            let rekey_info = RekeyInfo::new(
+                &resolver.session_from(arg.session_from.as_deref()),
+                &resolver.session_to(arg.session_to.as_deref()),
+                &resolver.encryption_secret(arg.encryption_secret.as_deref()),
+            );
+            let ciphertext = decode(EncryptedDataPoint::from_base64(&arg.args[0]), "ciphertext");
+            let rekeyed = rekey(&ciphertext, &rekey_info);
+            output.value(
+                "ciphertext",
+                "Rekeyed ciphertext",
+                rekeyed.encode_as_base64(),
+            );
+        }
+        Some(Sub::RekeyFromGlobal(arg)) => {
+            let rekey_info = RekeyInfo::new_from_global(
+                &resolver.session_to(arg.session_to.as_deref()),
+                &resolver.encryption_secret(arg.encryption_secret.as_deref()),
+            );
+            let ciphertext = decode(EncryptedDataPoint::from_base64(&arg.args[0]), "ciphertext");
+            let rekeyed = rekey(&ciphertext, &rekey_info);
+            output.value(
+                "ciphertext",
+                "Rekeyed ciphertext",
+                rekeyed.encode_as_base64(),
+            );
+        }
+        Some(Sub::RekeyToGlobal(arg)) => {
+            let rekey_info = RekeyInfo::new_to_global(
+                &resolver.session_from(arg.session_from.as_deref()),
+                &resolver.encryption_secret(arg.encryption_secret.as_deref()),
+            );
+            let ciphertext = decode(EncryptedDataPoint::from_base64(&arg.args[0]), "ciphertext");
+            let rekeyed = rekey(&ciphertext, &rekey_info);
+            output.value(
+                "ciphertext",
+                "Rekeyed ciphertext",
+                rekeyed.encode_as_base64(),
+            );
+        }
+        Some(Sub::EncryptBatch(arg)) => {
+            #[cfg(feature = "separate-session-keys")]
+            let public_key = if arg.data_point {
+                session_public_key(&arg.public_key, "--public-key")
+            } else {
+                session_public_key(&arg.pseudonym_public_key, "--pseudonym-public-key")
+            };
+            #[cfg(not(feature = "separate-session-keys"))]
+            let public_key = session_public_key(&arg.public_key, "--public-key");
+            batch(arg.input.as_deref(), arg.shuffle, "ciphertext", |line| {
+                if arg.data_point {
+                    DataPoint::decode_from_hex(line)
+                        .map(|x| encrypt(&x, &public_key, &mut rng).encode_as_base64())
+                } else {
+                    Pseudonym::decode_from_hex(line)
+                        .map(|x| encrypt(&x, &public_key, &mut rng).encode_as_base64())
+                }
+            });
+        }
+        Some(Sub::DecryptBatch(arg)) => {
+            #[cfg(feature = "separate-session-keys")]
+            let secret_key = if arg.data_point {
+                resolver.scalar(arg.secret_key.as_deref(), "--secret-key")
+            } else {
+                resolver.scalar(
+                    arg.pseudonym_secret_key.as_deref(),
+                    "--pseudonym-secret-key",
+                )
+            };
+            #[cfg(not(feature = "separate-session-keys"))]
+            let secret_key = resolver.scalar(arg.secret_key.as_deref(), "--secret-key");
+            let secret_key = SessionSecretKey::from(secret_key);
+            batch(arg.input.as_deref(), arg.shuffle, "plaintext", |line| {
+                if arg.data_point {
+                    EncryptedDataPoint::from_base64(line)
+                        .map(|x| decrypt(&x, &secret_key).encode_as_hex())
+                } else {
+                    EncryptedPseudonym::from_base64(line)
+                        .map(|x| decrypt(&x, &secret_key).encode_as_hex())
+                }
+            });
+        }
+        Some(Sub::RerandomizeBatch(arg)) => {
+            #[cfg(not(feature = "elgamal3"))]
+            let public_key = session_public_key(&arg.public_key, "--public-key");
+            // Pseudonyms and data points are rerandomized in the same way.
+            batch(arg.input.as_deref(), arg.shuffle, "ciphertext", |line| {
+                let ciphertext = EncryptedDataPoint::from_base64(line)?;
+                #[cfg(not(feature = "elgamal3"))]
+                let rerandomized = rerandomize(&ciphertext, &public_key, &mut rng);
+                #[cfg(feature = "elgamal3")]
+                let rerandomized = rerandomize(&ciphertext, &mut rng);
+                Some(rerandomized.encode_as_base64())
+            });
+        }
+        Some(Sub::TranscryptBatch(arg)) => {
+            let transcryption_info = TranscryptionInfo::new(
+                &resolver.pseudonymization_context_from(arg.pseudonymization_from.as_deref()),
+                &resolver.pseudonymization_context_to(arg.pseudonymization_to.as_deref()),
+                &resolver.session_from(arg.session_from.as_deref()),
+                &resolver.session_to(arg.session_to.as_deref()),
+                &resolver.pseudonymization_secret(arg.pseudonymization_secret.as_deref()),
+                &resolver.encryption_secret(arg.encryption_secret.as_deref()),
+            );
+            batch(arg.input.as_deref(), arg.shuffle, "ciphertext", |line| {
+                if arg.data_point {
+                    EncryptedDataPoint::from_base64(line)
+                        .map(|x| transcrypt(&x, &transcryption_info).encode_as_base64())
+                } else {
+                    EncryptedPseudonym::from_base64(line)
+                        .map(|x| transcrypt(&x, &transcryption_info).encode_as_base64())
+                }
+            });
+        }
+        Some(Sub::EncryptCsv(arg)) => {
+            let public_key = session_public_key(&arg.public_key, "--public-key");
+            #[cfg(feature = "separate-session-keys")]
+            let pseudonym_public_key =
+                session_public_key(&arg.pseudonym_public_key, "--pseudonym-public-key");
+            #[cfg(not(feature = "separate-session-keys"))]
+            let pseudonym_public_key = public_key;
+            let mode = origin_mode(&arg.mode);
+            if mode == OriginMode::Blocks {
+                fail(
+                    ErrorKind::Usage,
+                    "Pseudonym columns cannot use blocks mode (use hash).",
+                );
+            }
+            let label = origin_label(mode, &arg.label);
+            process_table(
+                arg.input.as_deref(),
+                arg.tsv,
+                &arg.pseudonyms,
+                &arg.keep,
+                |column, value| match column {
+                    Column::Pseudonym => {
+                        let pseudonyms = pseudonyms_from_origin(value.as_bytes(), mode, label)?;
+                        Ok(encrypt(&pseudonyms[0], &pseudonym_public_key, &mut rng)
+                            .encode_as_base64())
+                    }
+                    _ => Ok(data_points(value.as_bytes())
+                        .iter()
+                        .map(|x| encrypt(x, &public_key, &mut rng).encode_as_base64())
+                        .collect::<Vec<_>>()
+                        .join(&BLOCK_SEPARATOR.to_string())),
+                },
+            );
+        }
+        Some(Sub::TranscryptCsv(arg)) => {
+            let transcryption_info = TranscryptionInfo::new(
+                &resolver.pseudonymization_context_from(arg.pseudonymization_from.as_deref()),
+                &resolver.pseudonymization_context_to(arg.pseudonymization_to.as_deref()),
+                &resolver.session_from(arg.session_from.as_deref()),
+                &resolver.session_to(arg.session_to.as_deref()),
+                &resolver.pseudonymization_secret(arg.pseudonymization_secret.as_deref()),
+                &resolver.encryption_secret(arg.encryption_secret.as_deref()),
+            );
+            process_table(
+                arg.input.as_deref(),
+                arg.tsv,
+                &arg.pseudonyms,
+                &arg.keep,
+                |column, value| match column {
+                    Column::Pseudonym => EncryptedPseudonym::from_base64(value)
+                        .map(|x| transcrypt(&x, &transcryption_info).encode_as_base64())
+                        .ok_or_else(|| "invalid ciphertext".to_string()),
+                    _ => value
+                        .split(BLOCK_SEPARATOR)
+                        .map(|block| {
+                            EncryptedDataPoint::from_base64(block)
+                                .map(|x| transcrypt(&x, &transcryption_info).encode_as_base64())
+                                .ok_or_else(|| "invalid ciphertext".to_string())
+                        })
+                        .collect::<Result<Vec<_>, _>>()
+                        .map(|blocks| blocks.join(&BLOCK_SEPARATOR.to_string())),
+                },
+            );
+        }
+        Some(Sub::SetupDistributedSystems(arg)) => {
+            let n = decode(arg.args[0].parse::<usize>().ok(), "n");
+            let (global_public_key, blinded_secret, blinding_factors) =
+                make_distributed_global_keys(n, &mut rng);
+            output.value(
+                "global_public_key",
+                "Public global key",
+                global_public_key.encode_as_hex(),
+            );
+            output.value(
+                "blinded_global_secret_key",
+                "Blinded secret key",
+                blinded_secret.encode_as_hex(),
+            );
+            let blinding_factors: Vec<_> =
+                blinding_factors.iter().map(|x| x.encode_as_hex()).collect();
+            output.list(
+                "blinding_factors",
+                "Blinding factors (KEEP SECRET)",
+                &blinding_factors,
+            );
+        }
+        Some(Sub::SessionKeyShare(arg)) => {
+            // The pseudonymization secret is not used for session key shares.
+            let system = PEPSystem::new(
+                PseudonymizationSecret::from(Vec::new()),
+                resolver.encryption_secret(arg.encryption_secret.as_deref()),
+                resolver.blinding_factor(arg.blinding_factor.as_deref()),
+            );
+            let session_context = resolver.session(arg.session.as_deref());
+            output.value(
+                "session_key_share",
+                "Session key share",
+                system.session_key_share(&session_context).encode_as_hex(),
+            );
+            #[cfg(feature = "separate-session-keys")]
+            {
+                output.value(
+                    "pseudonym_session_key_share",
+                    "Pseudonym session key share",
+                    system
+                        .pseudonym_session_key_share(&session_context)
+                        .encode_as_hex(),
+                );
+            }
+        }
+        Some(Sub::CombineSessionKeyShares(arg)) => {
+            #[cfg(feature = "separate-session-keys")]
+            if arg.pseudonym_shares.len() != arg.args.len() {
+                fail(
+                    ErrorKind::InvalidInput,
+                    "Expected a pseudonym session key share for every system.",
+                );
+            }
+            let blinded_global_secret_key =
+                resolver.blinded_global_secret_key(arg.blinded_global_secret_key.as_deref());
+            let shares = |shares: &[String]| -> Vec<SessionKeyShare> {
+                shares
+                    .iter()
+                    .map(|share| {
+                        <SessionKeyShare as SafeScalar>::from(
+                            resolver.scalar(Some(share), "session-key-share"),
+                        )
+                    })
+                    .collect()
+            };
+            let (session_pk, session_sk) =
+                make_session_key(blinded_global_secret_key, &shares(&arg.args));
+            output.value(
+                "session_public_key",
+                "Public session key",
+                session_pk.encode_as_hex(),
+            );
+            output.value(
+                "session_secret_key",
+                "Secret session key",
+                session_sk.value().encode_as_hex(),
+            );
+            #[cfg(feature = "separate-session-keys")]
+            {
+                let (session_pk, session_sk) =
+                    make_session_key(blinded_global_secret_key, &shares(&arg.pseudonym_shares));
+                output.value(
+                    "pseudonym_session_public_key",
+                    "Public pseudonym session key",
+                    session_pk.encode_as_hex(),
+                );
+                output.value(
+                    "pseudonym_session_secret_key",
+                    "Secret pseudonym session key",
+                    session_sk.value().encode_as_hex(),
+                );
+            }
+        }
+        Some(Sub::SystemTranscrypt(arg)) => {
+            let system = resolver.system();
+            let transcryption_info = system.transcryption_info(
+                &resolver.pseudonymization_context_from(arg.pseudonymization_from.as_deref()),
+                &resolver.pseudonymization_context_to(arg.pseudonymization_to.as_deref()),
+                &resolver.session_from(arg.session_from.as_deref()),
+                &resolver.session_to(arg.session_to.as_deref()),
+            );
+            let transcrypted = if arg.data_point {
+                let ciphertext =
+                    decode(EncryptedDataPoint::from_base64(&arg.args[0]), "ciphertext");
+                system
+                    .transcrypt(&ciphertext, &transcryption_info)
+                    .encode_as_base64()
+            } else {
+                let ciphertext =
+                    decode(EncryptedPseudonym::from_base64(&arg.args[0]), "ciphertext");
+                system
+                    .transcrypt(&ciphertext, &transcryption_info)
+                    .encode_as_base64()
+            };
+            output.value("ciphertext", "Transcrypted ciphertext", transcrypted);
+        }
+        Some(Sub::Inspect(arg)) => {
+            let secret_key = arg
+                .secret_key
+                .map(|key| resolver.scalar(Some(&key), "--secret-key"));
+            let interpretations = inspect(&arg.args[0], secret_key.as_ref());
+            if interpretations.is_empty() {
+                fail(
+                    ErrorKind::InvalidInput,
+                    "Not a hex or base64 encoding of a known value.",
+                );
+            }
+            output.structured(
+                "interpretations",
+                "Interpretations",
+                serde_json::Value::Array(interpretations),
+            );
+        }
+        #[cfg(unix)]
+        Some(Sub::Serve(arg)) => {
+            let socket = required(&arg.socket, "--socket");
+            let permissions = u32::from_str_radix(arg.permissions.as_deref().unwrap_or("600"), 8)
+                .unwrap_or_else(|_| fail(ErrorKind::Usage, "--permissions: invalid mode"));
+            let system = options.config.is_some().then(|| resolver.system());
+            let client = arg.blinded_global_secret_key.as_deref().map(|key| {
+                let blinded_global_secret_key = resolver.blinded_global_secret_key(Some(key));
+                let shares = |shares: &[String]| -> Vec<SessionKeyShare> {
+                    if shares.is_empty() {
+                        fail(ErrorKind::Usage, "Missing option --session-key-share.");
+                    }
+                    shares
+                        .iter()
+                        .map(|share| {
+                            <SessionKeyShare as SafeScalar>::from(
+                                resolver.scalar(Some(share), "--session-key-share"),
+                            )
+                        })
+                        .collect()
+                };
+                #[cfg(not(feature = "separate-session-keys"))]
+                let client =
+                    PEPClient::new(blinded_global_secret_key, &shares(&arg.session_key_shares));
+                #[cfg(feature = "separate-session-keys")]
+                let client = PEPClient::new(
+                    blinded_global_secret_key,
+                    &shares(&arg.session_key_shares),
+                    &shares(&arg.pseudonym_shares),
+                );
+                client
+            });
+            if system.is_none() && client.is_none() {
+                fail(
+                    ErrorKind::Usage,
+                    "Nothing to serve (use --config and/or --blinded-global-secret-key).",
+                );
+            }
+            serve(socket, permissions, State { system, client });
+        }
+        None => {
+            fail(ErrorKind::Usage, "No subcommand given.");
+        }
+    }
+    output.finish();
+}
@@ -0,0 +1,134 @@
+//! Conversion between origin identifiers and pseudonyms, for `peppy pseudonym-from-origin` and
+//! `peppy pseudonym-to-origin`, and the PKCS#7 padding that is also used for data points.
+
+use libpep::high_level::data_types::{Encryptable, Pseudonym};
+use sha2::{Digest, Sha512};
+use std::str::FromStr;
+
+/// The domain-separation label for pseudonyms created from a hash, if none is given.
+pub const DEFAULT_LABEL: &str = "peppy-pseudonym";
+
+/// How an origin identifier is converted to pseudonyms.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Mode {
+    /// At most 16 bytes, padded with zeros. Origins that only differ in trailing zeros collide.
+    Zero,
+    /// At most 15 bytes, with PKCS#7 padding to 16 bytes.
+    Pkcs7,
+    /// Any number of bytes, with PKCS#7 padding to a multiple of 16 bytes, as one pseudonym for
+    /// every block of 16 bytes.
+    ///
+    /// The blocks are pseudonymized independently, so identifiers that share a prefix of a
+    /// multiple of 16 bytes share the pseudonyms of that prefix, which links them (also after
+    /// transcryption). Use [`Mode::Hash`] for long identifiers unless they must be converted back.
+    Blocks,
+    /// The SHA-512 hash of a domain-separation label and the origin, which cannot be converted
+    /// back.
+    Hash,
+}
+
+impl FromStr for Mode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "zero" => Ok(Mode::Zero),
+            "pkcs7" => Ok(Mode::Pkcs7),
+            "blocks" => Ok(Mode::Blocks),
+            "hash" => Ok(Mode::Hash),
+            _ => Err(format!(
+                "Unknown mode {} (expected zero, pkcs7, blocks or hash).",
+                s
+            )),
+        }
+    }
+}
+
+/// Pad a value with PKCS#7 padding to a multiple of 16 bytes (adding a full block if the value
+/// already is a multiple of 16 bytes).
+pub fn pkcs7_pad(value: &[u8]) -> Vec<u8> {
+    let padding = 16 - value.len() % 16;
+    let mut padded = value.to_vec();
+    padded.resize(value.len() + padding, padding as u8);
+    padded
+}
+
+/// Remove PKCS#7 padding from a multiple of 16 bytes.
+pub fn pkcs7_unpad(padded: &[u8]) -> Option<&[u8]> {
+    let padding = *padded.last()? as usize;
+    if padding == 0 || padding > 16 || padding > padded.len() {
+        return None;
+    }
+    let (value, padding_bytes) = padded.split_at(padded.len() - padding);
+    padding_bytes
+        .iter()
+        .all(|&b| b as usize == padding)
+        .then_some(value)
+}
+
+/// The pseudonyms for an origin identifier. Only [`Mode::Blocks`] can result in more than one.
+/// The label is only used by [`Mode::Hash`], and is separated from the origin by `|`.
+pub fn pseudonyms_from_origin(
+    origin: &[u8],
+    mode: Mode,
+    label: &str,
+) -> Result<Vec<Pseudonym>, String> {
+    let block = |bytes: &[u8]| Pseudonym::from_bytes(bytes.try_into().unwrap());
+    match mode {
+        Mode::Zero => {
+            if origin.len() > 16 {
+                return Err("Origin identifier must be at most 16 bytes long.".to_string());
+            }
+            let mut padded = [0u8; 16];
+            padded[..origin.len()].copy_from_slice(origin);
+            Ok(vec![Pseudonym::from_bytes(&padded)])
+        }
+        Mode::Pkcs7 => {
+            if origin.len() > 15 {
+                return Err(
+                    "Origin identifier must be at most 15 bytes long (use blocks or hash)."
+                        .to_string(),
+                );
+            }
+            Ok(vec![block(&pkcs7_pad(origin))])
+        }
+        Mode::Blocks => Ok(pkcs7_pad(origin).chunks(16).map(block).collect()),
+        Mode::Hash => {
+            if label.contains('|') {
+                return Err("Label must not contain |.".to_string());
+            }
+            let mut hasher = Sha512::default();
+            hasher.update(label.as_bytes());
+            hasher.update(b"|");
+            hasher.update(origin);
+            Ok(vec![Pseudonym::from_hash(&hasher.finalize().into())])
+        }
+    }
+}
+
+/// The origin identifier the pseudonyms were created from with a mode.
+pub fn origin_from_pseudonyms(pseudonyms: &[Pseudonym], mode: Mode) -> Result<Vec<u8>, String> {
+    if mode == Mode::Hash {
+        return Err("Pseudonyms created from a hash cannot be converted back.".to_string());
+    }
+    if mode != Mode::Blocks && pseudonyms.len() != 1 {
+        return Err("Expected a single pseudonym (use blocks for more).".to_string());
+    }
+    let mut padded = Vec::with_capacity(pseudonyms.len() * 16);
+    for pseudonym in pseudonyms {
+        let bytes = pseudonym
+            .as_bytes()
+            .ok_or("Pseudonym was not created from an origin identifier.")?;
+        padded.extend_from_slice(&bytes);
+    }
+    match mode {
+        Mode::Zero => {
+            let length = padded.iter().rposition(|&b| b != 0).map_or(0, |i| i + 1);
+            padded.truncate(length);
+            Ok(padded)
+        }
+        _ => pkcs7_unpad(&padded)
+            .map(<[u8]>::to_vec)
+            .ok_or_else(|| "Invalid padding.".to_string()),
+    }
+}
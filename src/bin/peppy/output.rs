@@ -0,0 +1,209 @@
+//! Output of `peppy`, either as text (labels on stderr and values on stdout) or as JSON objects
+//! on stdout (with `--json`), and errors with consistent exit codes.
+
+use rand::seq::SliceRandom;
+use rand_core::OsRng;
+use serde_json::{json, Map, Value};
+use std::fmt::Display;
+use std::fs::File;
+use std::io::{stdin, stdout, BufRead, BufReader, BufWriter, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static JSON_OUTPUT: AtomicBool = AtomicBool::new(false);
+
+/// Output JSON objects instead of text, for the rest of this run.
+pub fn set_json_output(json: bool) {
+    JSON_OUTPUT.store(json, Ordering::SeqCst);
+}
+
+fn json_output() -> bool {
+    JSON_OUTPUT.load(Ordering::SeqCst)
+}
+
+/// The kind of an error, which determines the exit code.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum ErrorKind {
+    /// An argument or input line could not be decoded or is not valid (exit code 1).
+    InvalidInput,
+    /// The command was not used correctly, e.g. a required value is missing (exit code 2).
+    Usage,
+    /// A file, environment variable or stdin could not be read (exit code 3).
+    Io,
+}
+
+impl ErrorKind {
+    fn name(&self) -> &'static str {
+        match self {
+            ErrorKind::InvalidInput => "invalid_input",
+            ErrorKind::Usage => "usage",
+            ErrorKind::Io => "io",
+        }
+    }
+    fn exit_code(&self) -> i32 {
+        match self {
+            ErrorKind::InvalidInput => 1,
+            ErrorKind::Usage => 2,
+            ErrorKind::Io => 3,
+        }
+    }
+}
+
+/// Report an error and exit.
+/// In JSON mode, the error is written to stdout as `{"error": {"kind": ..., "message": ...}}`.
+pub fn fail<M: Display>(kind: ErrorKind, message: M) -> ! {
+    if json_output() {
+        println!(
+            "{}",
+            json!({"error": {"kind": kind.name(), "message": message.to_string()}})
+        );
+    } else {
+        eprintln!("{}", message);
+    }
+    std::process::exit(kind.exit_code());
+}
+
+/// The result of writing output, or fail if it could not be written (e.g. to a closed pipe).
+pub fn written<T, E: Display>(result: Result<T, E>) -> T {
+    result.unwrap_or_else(|e| fail(ErrorKind::Io, format!("Could not write output: {}", e)))
+}
+
+/// The named values resulting from a subcommand.
+/// In text mode, every value is printed immediately, in JSON mode they are collected into a single
+/// object that is printed by [`Output::finish`].
+pub struct Output {
+    json: bool,
+    object: Map<String, Value>,
+}
+
+impl Output {
+    pub fn new() -> Self {
+        Self {
+            json: json_output(),
+            object: Map::new(),
+        }
+    }
+
+    /// Output a value, printed as `label: value` in text mode and as field `name` in JSON mode.
+    pub fn value<V: Display>(&mut self, name: &str, label: &str, value: V) {
+        if self.json {
+            self.object
+                .insert(name.to_string(), Value::String(value.to_string()));
+        } else {
+            eprint!("{}: ", label);
+            println!("{}", value);
+        }
+    }
+
+    /// Output a list of values, printed one per line in text mode and as an array in JSON mode.
+    pub fn list<V: Display>(&mut self, name: &str, label: &str, values: &[V]) {
+        if self.json {
+            let values = values
+                .iter()
+                .map(|v| Value::String(v.to_string()))
+                .collect();
+            self.object.insert(name.to_string(), Value::Array(values));
+        } else {
+            eprintln!("{}: ", label);
+            for value in values {
+                println!("{}", value);
+            }
+        }
+    }
+
+    /// Output a structured value, printed as indented `key: value` lines in text mode.
+    pub fn structured(&mut self, name: &str, label: &str, value: Value) {
+        if self.json {
+            self.object.insert(name.to_string(), value);
+        } else {
+            eprintln!("{}: ", label);
+            print_structured(&value, 0);
+        }
+    }
+
+    /// Print the JSON object (in JSON mode), unless the subcommand wrote its own output.
+    pub fn finish(self) {
+        if self.json && !self.object.is_empty() {
+            println!("{}", Value::Object(self.object));
+        }
+    }
+}
+
+fn print_structured(value: &Value, indent: usize) {
+    let prefix = " ".repeat(indent);
+    match value {
+        Value::Array(values) => {
+            for value in values {
+                println!("{}-", prefix);
+                print_structured(value, indent + 2);
+            }
+        }
+        Value::Object(fields) => {
+            for (key, value) in fields {
+                match value {
+                    Value::Array(_) | Value::Object(_) => {
+                        println!("{}{}:", prefix, key);
+                        print_structured(value, indent + 2);
+                    }
+                    Value::String(s) => println!("{}{}: {}", prefix, key, s),
+                    _ => println!("{}{}: {}", prefix, key, value),
+                }
+            }
+        }
+        Value::String(s) => println!("{}{}", prefix, s),
+        _ => println!("{}{}", prefix, value),
+    }
+}
+
+/// Apply `f` to every line of the input file (or stdin), and write the results to stdout, in the
+/// same order or shuffled.
+/// In JSON mode, every result is written as an object with a single field `name`.
+/// Fails on the first line for which `f` returns `None`, after writing the results so far.
+pub fn batch<F: FnMut(&str) -> Option<String>>(
+    input: Option<&str>,
+    shuffle: bool,
+    name: &str,
+    mut f: F,
+) {
+    let reader: Box<dyn BufRead> = match input {
+        Some(path) => Box::new(BufReader::new(File::open(path).unwrap_or_else(|e| {
+            fail(ErrorKind::Io, format!("Could not open {}: {}", path, e))
+        }))),
+        None => Box::new(stdin().lock()),
+    };
+    let json = json_output();
+    let mut output = BufWriter::new(stdout().lock());
+    let write = |output: &mut BufWriter<_>, result: String| {
+        if json {
+            written(writeln!(output, "{}", json!({ name: result })));
+        } else {
+            written(writeln!(output, "{}", result));
+        }
+    };
+    let mut results = Vec::new();
+    for (i, line) in reader.lines().enumerate() {
+        let result = line
+            .map_err(|e| (ErrorKind::Io, format!("Could not read input: {}", e)))
+            .and_then(|line| {
+                f(line.trim()).ok_or_else(|| {
+                    (
+                        ErrorKind::InvalidInput,
+                        format!("Invalid input on line {}.", i + 1),
+                    )
+                })
+            });
+        match result {
+            Ok(result) if shuffle => results.push(result),
+            Ok(result) => write(&mut output, result),
+            Err((kind, message)) => {
+                written(output.flush());
+                drop(output);
+                fail(kind, message);
+            }
+        }
+    }
+    results.shuffle(&mut OsRng); // Shuffle the order to avoid linking
+    for result in results {
+        write(&mut output, result);
+    }
+    written(output.flush());
+}
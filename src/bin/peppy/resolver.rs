@@ -0,0 +1,199 @@
+//! Resolution of secrets and contexts given as arguments to `peppy`, which can be taken from
+//! files, environment variables, stdin or a configuration file.
+
+use crate::output::{fail, ErrorKind};
+use libpep::distributed::key_blinding::{BlindedGlobalSecretKey, BlindingFactor, SafeScalar};
+use libpep::distributed::systems::{PEPSystem, PEPSystemConfig};
+use libpep::high_level::contexts::{EncryptionContext, PseudonymizationContext};
+use libpep::high_level::keys::{EncryptionSecret, PseudonymizationSecret};
+use libpep::internal::arithmetic::ScalarNonZero;
+use serde::Deserialize;
+use std::io::stdin;
+
+/// The configuration of a transcryptor, as used by `peppy-server` (which ignores the default
+/// contexts).
+#[derive(Deserialize, Debug)]
+struct Config {
+    #[serde(flatten)]
+    system: PEPSystemConfig,
+    #[serde(default)]
+    contexts: DefaultContexts,
+}
+
+/// The contexts to use for context arguments given as `config:`.
+#[derive(Deserialize, Debug, Default)]
+struct DefaultContexts {
+    pseudonymization_from: Option<String>,
+    pseudonymization_to: Option<String>,
+    session_from: Option<String>,
+    session_to: Option<String>,
+}
+
+/// Resolves secrets and contexts given on the command line, which can refer to a file
+/// (`file:<path>`), an environment variable (`env:<name>`), the next line of stdin (`stdin:`) or
+/// the configuration file (`config:`), so that secrets do not end up in the shell history.
+/// Any other value (or one prefixed with `raw:`) is taken literally.
+/// Options that are not given at all are taken from the configuration file, if one is given.
+pub struct Resolver {
+    config: Option<Config>,
+}
+
+impl Resolver {
+    pub fn new(config_file: Option<&str>) -> Self {
+        let config = config_file.map(|path| {
+            let config = std::fs::read_to_string(path).unwrap_or_else(|e| {
+                fail(
+                    ErrorKind::Io,
+                    format!("Could not read configuration file {}: {}", path, e),
+                )
+            });
+            serde_json::from_str(&config).unwrap_or_else(|e| {
+                fail(
+                    ErrorKind::InvalidInput,
+                    format!("Invalid configuration file {}: {}", path, e),
+                )
+            })
+        });
+        Self { config }
+    }
+
+    fn resolve<F: Fn(&Config) -> Option<String>>(
+        &self,
+        value: Option<&str>,
+        name: &str,
+        from_config: F,
+    ) -> String {
+        let error = |kind: ErrorKind, message: String| -> ! {
+            fail(kind, format!("{}: {}", name, message))
+        };
+        let value = match (value, &self.config) {
+            (Some(value), _) => value,
+            // Options that are not given are taken from the configuration file, if any.
+            (None, Some(config)) => {
+                return from_config(config)
+                    .unwrap_or_else(|| fail(ErrorKind::Usage, format!("Missing option {}.", name)))
+            }
+            (None, None) => fail(ErrorKind::Usage, format!("Missing option {}.", name)),
+        };
+        if let Some(path) = value.strip_prefix("file:") {
+            let contents = std::fs::read_to_string(path).unwrap_or_else(|e| {
+                error(ErrorKind::Io, format!("could not read {}: {}", path, e))
+            });
+            contents.trim_end_matches(['\n', '\r']).to_string()
+        } else if let Some(variable) = value.strip_prefix("env:") {
+            std::env::var(variable).unwrap_or_else(|_| {
+                error(
+                    ErrorKind::Io,
+                    format!("environment variable {} is not set", variable),
+                )
+            })
+        } else if value == "stdin:" {
+            let mut line = String::new();
+            match stdin().read_line(&mut line) {
+                Ok(0) | Err(_) => error(ErrorKind::Io, "could not read from stdin".to_string()),
+                Ok(_) => line.trim_end_matches(['\n', '\r']).to_string(),
+            }
+        } else if value == "config:" {
+            match &self.config {
+                Some(config) => from_config(config).unwrap_or_else(|| {
+                    error(
+                        ErrorKind::Usage,
+                        "not in the configuration file".to_string(),
+                    )
+                }),
+                None => error(
+                    ErrorKind::Usage,
+                    "no configuration file given (use --config)".to_string(),
+                ),
+            }
+        } else {
+            value.strip_prefix("raw:").unwrap_or(value).to_string()
+        }
+    }
+
+    pub fn scalar(&self, value: Option<&str>, name: &str) -> ScalarNonZero {
+        ScalarNonZero::decode_from_hex(&self.resolve(value, name, |_| None)).unwrap_or_else(|| {
+            fail(
+                ErrorKind::InvalidInput,
+                format!("{}: invalid secret key", name),
+            )
+        })
+    }
+
+    pub fn pseudonymization_secret(&self, value: Option<&str>) -> PseudonymizationSecret {
+        let secret = self.resolve(value, "--pseudonymization-secret", |c| {
+            Some(c.system.pseudonymization_secret.clone())
+        });
+        PseudonymizationSecret::from(secret.into_bytes())
+    }
+
+    pub fn encryption_secret(&self, value: Option<&str>) -> EncryptionSecret {
+        let secret = self.resolve(value, "--encryption-secret", |c| {
+            Some(c.system.encryption_secret.clone())
+        });
+        EncryptionSecret::from(secret.into_bytes())
+    }
+
+    pub fn blinding_factor(&self, value: Option<&str>) -> BlindingFactor {
+        let factor = self.resolve(value, "--blinding-factor", |c| {
+            Some(c.system.blinding_factor.encode_as_hex())
+        });
+        BlindingFactor::decode_from_hex(&factor).unwrap_or_else(|| {
+            fail(
+                ErrorKind::InvalidInput,
+                "--blinding-factor: invalid blinding factor",
+            )
+        })
+    }
+
+    pub fn blinded_global_secret_key(&self, value: Option<&str>) -> BlindedGlobalSecretKey {
+        let key = self.resolve(value, "--blinded-global-secret-key", |_| None);
+        BlindedGlobalSecretKey::decode_from_hex(&key).unwrap_or_else(|| {
+            fail(
+                ErrorKind::InvalidInput,
+                "--blinded-global-secret-key: invalid blinded global secret key",
+            )
+        })
+    }
+
+    /// The distributed system described by the configuration file.
+    pub fn system(&self) -> PEPSystem {
+        match &self.config {
+            Some(config) => config.system.build(),
+            None => fail(
+                ErrorKind::Usage,
+                "No configuration file given (use --config).",
+            ),
+        }
+    }
+
+    pub fn pseudonymization_context_from(&self, value: Option<&str>) -> PseudonymizationContext {
+        let context = self.resolve(value, "--pseudonymization-from", |c| {
+            c.contexts.pseudonymization_from.clone()
+        });
+        PseudonymizationContext::from(context.as_str())
+    }
+
+    pub fn pseudonymization_context_to(&self, value: Option<&str>) -> PseudonymizationContext {
+        let context = self.resolve(value, "--pseudonymization-to", |c| {
+            c.contexts.pseudonymization_to.clone()
+        });
+        PseudonymizationContext::from(context.as_str())
+    }
+
+    pub fn session_from(&self, value: Option<&str>) -> EncryptionContext {
+        let context = self.resolve(value, "--session-from", |c| c.contexts.session_from.clone());
+        EncryptionContext::from(context.as_str())
+    }
+
+    pub fn session_to(&self, value: Option<&str>) -> EncryptionContext {
+        let context = self.resolve(value, "--session-to", |c| c.contexts.session_to.clone());
+        EncryptionContext::from(context.as_str())
+    }
+
+    /// The context of a session for which keys are created, which defaults to `session_to`.
+    pub fn session(&self, value: Option<&str>) -> EncryptionContext {
+        let context = self.resolve(value, "--session", |c| c.contexts.session_to.clone());
+        EncryptionContext::from(context.as_str())
+    }
+}
@@ -0,0 +1,272 @@
+//! A local daemon for `peppy serve`, answering line-delimited JSON requests on a Unix domain
+//! socket with a [`PEPSystem`] and/or [`PEPClient`] held in memory.
+
+use crate::output::{fail, ErrorKind, Output};
+use libpep::distributed::key_blinding::SafeScalar;
+use libpep::distributed::systems::{PEPClient, PEPSystem};
+use libpep::high_level::contexts::{EncryptionContext, PseudonymizationContext};
+use libpep::high_level::data_types::{
+    DataPoint, Encryptable, Encrypted, EncryptedDataPoint, EncryptedPseudonym, Pseudonym,
+};
+use rand_core::OsRng;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::fs::{DirBuilder, Permissions};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::sync::Arc;
+use std::thread;
+
+/// The maximum size of a request line in bytes.
+const MAX_REQUEST_SIZE: usize = 1024 * 1024;
+
+/// A request, as a JSON object on a single line.
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Request {
+    /// Encrypt a pseudonym (or data point) with the session public key of the client.
+    Encrypt {
+        message: String,
+        #[serde(default)]
+        data_point: bool,
+    },
+    /// Decrypt a ciphertext with the session secret key of the client.
+    Decrypt {
+        ciphertext: String,
+        #[serde(default)]
+        data_point: bool,
+    },
+    /// Transcrypt a ciphertext from one context to another, as the system.
+    Transcrypt {
+        ciphertext: String,
+        #[serde(default)]
+        data_point: bool,
+        pseudonymization_from: String,
+        pseudonymization_to: String,
+        session_from: String,
+        session_to: String,
+    },
+    /// The session key share of the system for a session.
+    SessionKeyShare { session: String },
+}
+
+/// The system and client requests are answered with.
+pub struct State {
+    pub system: Option<PEPSystem>,
+    pub client: Option<PEPClient>,
+}
+
+fn error(kind: &str, message: &str) -> Value {
+    json!({"error": {"kind": kind, "message": message}})
+}
+
+fn invalid(name: &str) -> Value {
+    error("invalid_input", &format!("{}: invalid value", name))
+}
+
+fn client(state: &State) -> Result<&PEPClient, Value> {
+    state.client.as_ref().ok_or_else(|| {
+        error(
+            "unsupported",
+            "No client keys given (use --blinded-global-secret-key).",
+        )
+    })
+}
+
+fn system(state: &State) -> Result<&PEPSystem, Value> {
+    state.system.as_ref().ok_or_else(|| {
+        error(
+            "unsupported",
+            "No system configuration given (use --config).",
+        )
+    })
+}
+
+fn encrypt<E: Encryptable>(client: &PEPClient, message: &str) -> Result<Value, Value> {
+    let message = E::decode_from_hex(message).ok_or_else(|| invalid("message"))?;
+    let ciphertext = client.encrypt(&message, &mut OsRng);
+    Ok(json!({"ciphertext": ciphertext.as_base64()}))
+}
+
+fn decrypt<E: Encrypted>(client: &PEPClient, ciphertext: &str) -> Result<Value, Value> {
+    let ciphertext = E::from_base64(ciphertext).ok_or_else(|| invalid("ciphertext"))?;
+    // Decrypting a ciphertext for another public key would panic.
+    #[cfg(feature = "elgamal3")]
+    {
+        #[cfg(feature = "separate-session-keys")]
+        let public_key = if E::IS_PSEUDONYM {
+            client.pseudonym_session_public_key
+        } else {
+            client.session_public_key
+        };
+        #[cfg(not(feature = "separate-session-keys"))]
+        let public_key = client.session_public_key;
+        if ciphertext.value().gy != public_key.0 {
+            return Err(invalid("ciphertext"));
+        }
+    }
+    let plaintext = client.decrypt(&ciphertext);
+    Ok(json!({"plaintext": plaintext.encode_as_hex()}))
+}
+
+fn answer(state: &State, line: &str) -> Result<Value, Value> {
+    let request: Request =
+        serde_json::from_str(line).map_err(|e| error("invalid_request", &e.to_string()))?;
+    match request {
+        Request::Encrypt {
+            message,
+            data_point: false,
+        } => encrypt::<Pseudonym>(client(state)?, &message),
+        Request::Encrypt {
+            message,
+            data_point: true,
+        } => encrypt::<DataPoint>(client(state)?, &message),
+        Request::Decrypt {
+            ciphertext,
+            data_point: false,
+        } => decrypt::<EncryptedPseudonym>(client(state)?, &ciphertext),
+        Request::Decrypt {
+            ciphertext,
+            data_point: true,
+        } => decrypt::<EncryptedDataPoint>(client(state)?, &ciphertext),
+        Request::Transcrypt {
+            ciphertext,
+            data_point,
+            pseudonymization_from,
+            pseudonymization_to,
+            session_from,
+            session_to,
+        } => {
+            let system = system(state)?;
+            let info = system.transcryption_info(
+                &PseudonymizationContext::from(pseudonymization_from.as_str()),
+                &PseudonymizationContext::from(pseudonymization_to.as_str()),
+                &EncryptionContext::from(session_from.as_str()),
+                &EncryptionContext::from(session_to.as_str()),
+            );
+            let transcrypted = if data_point {
+                let ciphertext = EncryptedDataPoint::from_base64(&ciphertext)
+                    .ok_or_else(|| invalid("ciphertext"))?;
+                system.transcrypt(&ciphertext, &info).encode_as_base64()
+            } else {
+                let ciphertext = EncryptedPseudonym::from_base64(&ciphertext)
+                    .ok_or_else(|| invalid("ciphertext"))?;
+                system.transcrypt(&ciphertext, &info).encode_as_base64()
+            };
+            Ok(json!({"ciphertext": transcrypted}))
+        }
+        Request::SessionKeyShare { session } => {
+            let system = system(state)?;
+            let session = EncryptionContext::from(session.as_str());
+            #[cfg(not(feature = "separate-session-keys"))]
+            let response = json!({
+                "session_key_share": system.session_key_share(&session).encode_as_hex(),
+            });
+            #[cfg(feature = "separate-session-keys")]
+            let response = json!({
+                "session_key_share": system.session_key_share(&session).encode_as_hex(),
+                "pseudonym_session_key_share":
+                    system.pseudonym_session_key_share(&session).encode_as_hex(),
+            });
+            Ok(response)
+        }
+    }
+}
+
+/// Answer the requests of a connection, one response line for every request line, until the
+/// connection is closed.
+fn handle(state: &State, stream: UnixStream) -> std::io::Result<()> {
+    let mut writer = stream.try_clone()?;
+    let mut reader = BufReader::new(stream);
+    loop {
+        let mut line = String::new();
+        let length = (&mut reader)
+            .take(MAX_REQUEST_SIZE as u64 + 1)
+            .read_line(&mut line)?;
+        if length == 0 {
+            return Ok(());
+        }
+        if length > MAX_REQUEST_SIZE {
+            // The rest of the request cannot be told apart from the next one.
+            let response = error(
+                "refused",
+                &format!("request exceeds {} bytes", MAX_REQUEST_SIZE),
+            );
+            writeln!(writer, "{}", response)?;
+            return Ok(());
+        }
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = answer(state, &line).unwrap_or_else(|e| e);
+        writeln!(writer, "{}", response)?;
+        writer.flush()?;
+    }
+}
+
+/// Listen on a Unix domain socket at `path`, only accessible with the given permissions, and
+/// answer every connection in its own thread. The socket is removed on `SIGINT` or `SIGTERM`.
+pub fn serve(path: &str, permissions: u32, state: State) {
+    if std::path::Path::new(path).exists() {
+        fail(
+            ErrorKind::Io,
+            format!("{} already exists (remove it if no server uses it)", path),
+        );
+    }
+    // Bind inside a temporary directory that only the current user can access, so that the
+    // socket cannot be connected to before its permissions are set and it is moved into place.
+    let directory = format!("{}.{}.tmp", path, std::process::id());
+    let temporary = format!("{}/socket", directory);
+    let listener = DirBuilder::new()
+        .mode(0o700)
+        .create(&directory)
+        .and_then(|_| UnixListener::bind(&temporary))
+        .and_then(|listener| {
+            std::fs::set_permissions(&temporary, Permissions::from_mode(permissions))?;
+            std::fs::rename(&temporary, path)?;
+            Ok(listener)
+        })
+        .unwrap_or_else(|e| {
+            let _ = std::fs::remove_file(&temporary);
+            let _ = std::fs::remove_dir(&directory);
+            fail(
+                ErrorKind::Io,
+                format!("Could not listen on {}: {}", path, e),
+            )
+        });
+    let _ = std::fs::remove_dir(&directory);
+    {
+        let path = path.to_string();
+        ctrlc::set_handler(move || {
+            let _ = std::fs::remove_file(&path);
+            eprintln!("Shut down.");
+            std::process::exit(0);
+        })
+        .unwrap_or_else(|e| {
+            fail(
+                ErrorKind::Io,
+                format!("Could not install signal handler: {}", e),
+            )
+        });
+    }
+
+    let mut output = Output::new();
+    output.value("socket", "Listening on", path);
+    output.finish();
+
+    let state = Arc::new(state);
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                let state = state.clone();
+                thread::spawn(move || {
+                    if let Err(e) = handle(&state, stream) {
+                        eprintln!("Connection failed: {}", e);
+                    }
+                });
+            }
+            Err(e) => eprintln!("Failed to accept connection: {}", e),
+        }
+    }
+}
@@ -0,0 +1,461 @@
+//! The syntax of the command line of `peppy`, as declared by the `commandy` attributes of the
+//! subcommands. `commandy` prints its own message and exits for an unknown flag or subcommand and
+//! for a wrong number of positional values, so the command line is checked against this table
+//! first, to report those errors like all others.
+
+use crate::output::set_json_output;
+
+/// The names of a flag and the number of values it takes.
+type Flag = (&'static [&'static str], usize);
+
+/// The flags of a subcommand and the name, minimum and maximum number of its positional values.
+struct Syntax {
+    command: &'static str,
+    flags: &'static [Flag],
+    positional: Option<(&'static str, usize, usize)>,
+}
+
+const CONFIG: Flag = (&["-c", "--config"], 1);
+const JSON: Flag = (&["-j", "--json"], 0);
+
+const DATA_POINT: Flag = (&["-d", "--data-point"], 0);
+const INPUT: Flag = (&["-i", "--input"], 1);
+const SHUFFLE: Flag = (&["-s", "--shuffle"], 0);
+const PUBLIC_KEY: Flag = (&["--public-key"], 1);
+const SECRET_KEY: Flag = (&["--secret-key"], 1);
+#[cfg(feature = "separate-session-keys")]
+const PSEUDONYM_PUBLIC_KEY: Flag = (&["--pseudonym-public-key"], 1);
+#[cfg(feature = "separate-session-keys")]
+const PSEUDONYM_SECRET_KEY: Flag = (&["--pseudonym-secret-key"], 1);
+const ENCRYPTION_SECRET: Flag = (&["--encryption-secret"], 1);
+const PSEUDONYMIZATION_SECRET: Flag = (&["--pseudonymization-secret"], 1);
+const PSEUDONYMIZATION_FROM: Flag = (&["--pseudonymization-from"], 1);
+const PSEUDONYMIZATION_TO: Flag = (&["--pseudonymization-to"], 1);
+const SESSION: Flag = (&["--session"], 1);
+const SESSION_FROM: Flag = (&["--session-from"], 1);
+const SESSION_TO: Flag = (&["--session-to"], 1);
+const MODE: Flag = (&["-m", "--mode"], 1);
+const LABEL: Flag = (&["-l", "--label"], 1);
+const PSEUDONYM_COLUMN: Flag = (&["-p", "--pseudonym"], 1);
+const KEEP_COLUMN: Flag = (&["-k", "--keep"], 1);
+const TSV: Flag = (&["-t", "--tsv"], 0);
+const BLINDED_GLOBAL_SECRET_KEY: Flag = (&["--blinded-global-secret-key"], 1);
+#[cfg(feature = "separate-session-keys")]
+const PSEUDONYM_SHARE: Flag = (&["-p", "--pseudonym-share"], 1);
+
+/// The subcommands, in the order of `Sub`.
+const COMMANDS: &[Syntax] = &[
+    Syntax {
+        command: "generate-global-keys",
+        flags: &[],
+        positional: None,
+    },
+    Syntax {
+        command: "generate-session-keys",
+        flags: &[(&["--global-secret-key"], 1), ENCRYPTION_SECRET, SESSION],
+        positional: None,
+    },
+    Syntax {
+        command: "random-pseudonym",
+        flags: &[],
+        positional: None,
+    },
+    Syntax {
+        command: "pseudonym-from-origin",
+        flags: &[MODE, LABEL],
+        positional: Some(("origin", 1, 1)),
+    },
+    Syntax {
+        command: "pseudonym-to-origin",
+        flags: &[MODE],
+        positional: Some(("pseudonym-hex", 1, 1024)),
+    },
+    Syntax {
+        command: "random-data-point",
+        flags: &[],
+        positional: None,
+    },
+    Syntax {
+        command: "data-point-from-bytes",
+        flags: &[],
+        positional: Some(("bytes-hex", 1, 1)),
+    },
+    Syntax {
+        command: "data-point-from-string",
+        flags: &[],
+        positional: Some(("string", 1, 1)),
+    },
+    Syntax {
+        command: "data-point-from-hash",
+        flags: &[],
+        positional: Some(("string", 1, 1)),
+    },
+    Syntax {
+        command: "data-point-to-string",
+        flags: &[],
+        positional: Some(("data-point-hex", 1, 1024)),
+    },
+    #[cfg(not(feature = "separate-session-keys"))]
+    Syntax {
+        command: "encrypt",
+        flags: &[DATA_POINT, PUBLIC_KEY],
+        positional: Some(("message-hex", 1, 1)),
+    },
+    #[cfg(feature = "separate-session-keys")]
+    Syntax {
+        command: "encrypt",
+        flags: &[DATA_POINT, PUBLIC_KEY, PSEUDONYM_PUBLIC_KEY],
+        positional: Some(("message-hex", 1, 1)),
+    },
+    Syntax {
+        command: "encrypt-global",
+        flags: &[DATA_POINT, PUBLIC_KEY],
+        positional: Some(("message-hex", 1, 1)),
+    },
+    #[cfg(not(feature = "separate-session-keys"))]
+    Syntax {
+        command: "decrypt",
+        flags: &[DATA_POINT, SECRET_KEY],
+        positional: Some(("ciphertext", 1, 1)),
+    },
+    #[cfg(feature = "separate-session-keys")]
+    Syntax {
+        command: "decrypt",
+        flags: &[DATA_POINT, SECRET_KEY, PSEUDONYM_SECRET_KEY],
+        positional: Some(("ciphertext", 1, 1)),
+    },
+    #[cfg(not(feature = "elgamal3"))]
+    Syntax {
+        command: "rerandomize",
+        flags: &[DATA_POINT, PUBLIC_KEY],
+        positional: Some(("ciphertext", 1, 1)),
+    },
+    #[cfg(feature = "elgamal3")]
+    Syntax {
+        command: "rerandomize",
+        flags: &[DATA_POINT],
+        positional: Some(("ciphertext", 1, 1)),
+    },
+    Syntax {
+        command: "transcrypt",
+        flags: &[
+            DATA_POINT,
+            PSEUDONYMIZATION_SECRET,
+            ENCRYPTION_SECRET,
+            PSEUDONYMIZATION_FROM,
+            PSEUDONYMIZATION_TO,
+            SESSION_FROM,
+            SESSION_TO,
+        ],
+        positional: Some(("ciphertext", 1, 1)),
+    },
+    Syntax {
+        command: "transcrypt-from-global",
+        flags: &[
+            DATA_POINT,
+            PSEUDONYMIZATION_SECRET,
+            ENCRYPTION_SECRET,
+            PSEUDONYMIZATION_FROM,
+            PSEUDONYMIZATION_TO,
+            SESSION_TO,
+        ],
+        positional: Some(("ciphertext", 1, 1)),
+    },
+    Syntax {
+        command: "transcrypt-to-global",
+        flags: &[
+            DATA_POINT,
+            PSEUDONYMIZATION_SECRET,
+            ENCRYPTION_SECRET,
+            PSEUDONYMIZATION_FROM,
+            PSEUDONYMIZATION_TO,
+            SESSION_FROM,
+        ],
+        positional: Some(("ciphertext", 1, 1)),
+    },
+    Syntax {
+        command: "rekey",
+        flags: &[ENCRYPTION_SECRET, SESSION_FROM, SESSION_TO],
+        positional: Some(("ciphertext", 1, 1)),
+    },
+    Syntax {
+        command: "rekey-from-global",
+        flags: &[ENCRYPTION_SECRET, SESSION_TO],
+        positional: Some(("ciphertext", 1, 1)),
+    },
+    Syntax {
+        command: "rekey-to-global",
+        flags: &[ENCRYPTION_SECRET, SESSION_FROM],
+        positional: Some(("ciphertext", 1, 1)),
+    },
+    #[cfg(not(feature = "separate-session-keys"))]
+    Syntax {
+        command: "encrypt-batch",
+        flags: &[DATA_POINT, INPUT, SHUFFLE, PUBLIC_KEY],
+        positional: None,
+    },
+    #[cfg(feature = "separate-session-keys")]
+    Syntax {
+        command: "encrypt-batch",
+        flags: &[DATA_POINT, INPUT, SHUFFLE, PUBLIC_KEY, PSEUDONYM_PUBLIC_KEY],
+        positional: None,
+    },
+    #[cfg(not(feature = "separate-session-keys"))]
+    Syntax {
+        command: "decrypt-batch",
+        flags: &[DATA_POINT, INPUT, SHUFFLE, SECRET_KEY],
+        positional: None,
+    },
+    #[cfg(feature = "separate-session-keys")]
+    Syntax {
+        command: "decrypt-batch",
+        flags: &[DATA_POINT, INPUT, SHUFFLE, SECRET_KEY, PSEUDONYM_SECRET_KEY],
+        positional: None,
+    },
+    #[cfg(not(feature = "elgamal3"))]
+    Syntax {
+        command: "rerandomize-batch",
+        flags: &[INPUT, SHUFFLE, PUBLIC_KEY],
+        positional: None,
+    },
+    #[cfg(feature = "elgamal3")]
+    Syntax {
+        command: "rerandomize-batch",
+        flags: &[INPUT, SHUFFLE],
+        positional: None,
+    },
+    Syntax {
+        command: "transcrypt-batch",
+        flags: &[
+            DATA_POINT,
+            INPUT,
+            SHUFFLE,
+            PSEUDONYMIZATION_SECRET,
+            ENCRYPTION_SECRET,
+            PSEUDONYMIZATION_FROM,
+            PSEUDONYMIZATION_TO,
+            SESSION_FROM,
+            SESSION_TO,
+        ],
+        positional: None,
+    },
+    #[cfg(not(feature = "separate-session-keys"))]
+    Syntax {
+        command: "encrypt-csv",
+        flags: &[
+            PSEUDONYM_COLUMN,
+            KEEP_COLUMN,
+            TSV,
+            INPUT,
+            MODE,
+            LABEL,
+            PUBLIC_KEY,
+        ],
+        positional: None,
+    },
+    #[cfg(feature = "separate-session-keys")]
+    Syntax {
+        command: "encrypt-csv",
+        flags: &[
+            PSEUDONYM_COLUMN,
+            KEEP_COLUMN,
+            TSV,
+            INPUT,
+            MODE,
+            LABEL,
+            PUBLIC_KEY,
+            PSEUDONYM_PUBLIC_KEY,
+        ],
+        positional: None,
+    },
+    Syntax {
+        command: "transcrypt-csv",
+        flags: &[
+            PSEUDONYM_COLUMN,
+            KEEP_COLUMN,
+            TSV,
+            INPUT,
+            PSEUDONYMIZATION_SECRET,
+            ENCRYPTION_SECRET,
+            PSEUDONYMIZATION_FROM,
+            PSEUDONYMIZATION_TO,
+            SESSION_FROM,
+            SESSION_TO,
+        ],
+        positional: None,
+    },
+    Syntax {
+        command: "setup-distributed",
+        flags: &[],
+        positional: Some(("n", 1, 1)),
+    },
+    Syntax {
+        command: "session-key-share",
+        flags: &[ENCRYPTION_SECRET, (&["--blinding-factor"], 1), SESSION],
+        positional: None,
+    },
+    #[cfg(not(feature = "separate-session-keys"))]
+    Syntax {
+        command: "combine-session-key-shares",
+        flags: &[BLINDED_GLOBAL_SECRET_KEY],
+        positional: Some(("session-key-shares", 1, 1024)),
+    },
+    #[cfg(feature = "separate-session-keys")]
+    Syntax {
+        command: "combine-session-key-shares",
+        flags: &[PSEUDONYM_SHARE, BLINDED_GLOBAL_SECRET_KEY],
+        positional: Some(("session-key-shares", 1, 1024)),
+    },
+    Syntax {
+        command: "system-transcrypt",
+        flags: &[
+            DATA_POINT,
+            PSEUDONYMIZATION_FROM,
+            PSEUDONYMIZATION_TO,
+            SESSION_FROM,
+            SESSION_TO,
+        ],
+        positional: Some(("ciphertext", 1, 1)),
+    },
+    Syntax {
+        command: "inspect",
+        flags: &[(&["-s", "--secret-key"], 1)],
+        positional: Some(("value", 1, 1)),
+    },
+    #[cfg(all(unix, not(feature = "separate-session-keys")))]
+    Syntax {
+        command: "serve",
+        flags: &[
+            (&["--socket"], 1),
+            (&["--permissions"], 1),
+            BLINDED_GLOBAL_SECRET_KEY,
+            (&["--session-key-share"], 1),
+        ],
+        positional: None,
+    },
+    #[cfg(all(unix, feature = "separate-session-keys"))]
+    Syntax {
+        command: "serve",
+        flags: &[
+            (&["--socket"], 1),
+            (&["--permissions"], 1),
+            BLINDED_GLOBAL_SECRET_KEY,
+            (&["--session-key-share"], 1),
+            PSEUDONYM_SHARE,
+        ],
+        positional: None,
+    },
+];
+
+/// Skip the flags at the start of `args` the way `commandy` parses them, calling `seen` for each.
+/// Returns the remaining (positional) arguments, or `None` for `--help`, which `commandy` answers.
+fn skip_flags<'a>(
+    args: &'a [&'a str],
+    flags: &[Flag],
+    mut seen: impl FnMut(&str),
+) -> Result<Option<&'a [&'a str]>, String> {
+    // without flags, everything is a positional value (even if it starts with a dash)
+    if flags.is_empty() {
+        return Ok(Some(args));
+    }
+    let mut i = 0;
+    while let Some(&arg) = args.get(i) {
+        if arg == "--" {
+            return Ok(Some(&args[i + 1..]));
+        }
+        if !arg.starts_with('-') {
+            break;
+        }
+        match flags.iter().find(|(names, _)| names.contains(&arg)) {
+            Some((_, values)) => {
+                if i + values >= args.len() {
+                    return Err(format!("Missing value of option {}.", arg));
+                }
+                seen(arg);
+                i += 1 + values;
+            }
+            None if arg.eq_ignore_ascii_case("--help") => return Ok(None),
+            None => return Err(format!("Unknown option {}.", arg)),
+        }
+    }
+    Ok(Some(&args[i..]))
+}
+
+/// Check the command line (including the program name) for errors that `commandy` would not
+/// return. JSON output is turned on as soon as `--json` is seen, so such errors are reported in
+/// JSON as well.
+pub fn check_syntax(args: &[&str]) -> Result<(), String> {
+    let Some(args) = skip_flags(args.get(1..).unwrap_or_default(), &[CONFIG, JSON], |flag| {
+        if JSON.0.contains(&flag) {
+            set_json_output(true);
+        }
+    })?
+    else {
+        return Ok(());
+    };
+    let Some((&command, args)) = args.split_first() else {
+        return Err("Missing subcommand (see peppy --help).".to_string());
+    };
+    let syntax = COMMANDS
+        .iter()
+        .find(|syntax| syntax.command == command)
+        .ok_or_else(|| format!("Unknown subcommand {} (see peppy --help).", command))?;
+    let Some(values) = skip_flags(args, syntax.flags, |_| {})? else {
+        return Ok(());
+    };
+    match syntax.positional {
+        None if !values.is_empty() => Err(format!(
+            "Unexpected value {} for {}.",
+            values[0], syntax.command
+        )),
+        Some((name, min, _)) if values.len() < min => {
+            Err(format!("Missing {} for {}.", name, syntax.command))
+        }
+        Some((name, _, max)) if values.len() > max => Err(format!(
+            "Too many values for {}: expected at most {} {}, not {}.",
+            syntax.command,
+            max,
+            name,
+            values.len()
+        )),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::COMMANDS;
+    use crate::{Options, Sub};
+    use commandy::{ArgumentParser, ParseArgumentAction};
+
+    /// Every subcommand has a syntax, and `commandy` accepts all flags and the minimum and maximum
+    /// number of positional values of each syntax (it exits the test otherwise).
+    #[test]
+    fn syntax_matches_commands() {
+        let mut names = Vec::new();
+        Sub::list_subcommands(&mut names);
+        let commands: Vec<&str> = COMMANDS.iter().map(|syntax| syntax.command).collect();
+        assert_eq!(commands, names);
+        for syntax in COMMANDS {
+            let (min, max) = syntax.positional.map_or((0, 0), |(_, min, max)| (min, max));
+            for count in [min, max] {
+                let mut args = vec!["peppy", syntax.command];
+                for (names, values) in syntax.flags {
+                    for name in names.iter() {
+                        args.push(name);
+                        args.extend(std::iter::repeat_n("0", *values));
+                    }
+                }
+                if !syntax.flags.is_empty() {
+                    args.push("--");
+                }
+                args.extend(std::iter::repeat_n("0", count));
+                let mut options = Options::default();
+                options
+                    .parse_arguments(&mut args, "", ParseArgumentAction::Parse)
+                    .unwrap();
+                assert!(options.subcommand.is_some(), "{}", syntax.command);
+            }
+        }
+    }
+}
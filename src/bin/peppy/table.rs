@@ -0,0 +1,117 @@
+//! Streaming processing of the columns of CSV/TSV tables, for pseudonymizing exports with `peppy`.
+
+use crate::origin::pkcs7_pad;
+use crate::output::{fail, written, ErrorKind};
+use libpep::high_level::data_types::{DataPoint, Encryptable};
+use std::fs::File;
+use std::io::{stdin, stdout, Read};
+
+/// Separates the ciphertexts of the blocks of a data point value longer than 16 bytes.
+pub const BLOCK_SEPARATOR: char = ':';
+
+/// How a column of a table is processed.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Column {
+    /// The values are (encrypted) pseudonyms.
+    Pseudonym,
+    /// The values are (encrypted) data points.
+    DataPoint,
+    /// The values are copied as is.
+    Keep,
+}
+
+/// Split a value with PKCS#7 padding into data points of 16 bytes each, which
+/// `peppy data-point-to-string` converts back.
+pub fn data_points(value: &[u8]) -> Vec<DataPoint> {
+    pkcs7_pad(value)
+        .chunks(16)
+        .map(|block| DataPoint::from_bytes(block.try_into().unwrap()))
+        .collect()
+}
+
+/// Read a table from the input file (or stdin) with a header row, apply `f` to every value in
+/// the pseudonym and data point columns, and write the resulting table to stdout, row by row.
+/// Columns are selected by their name in the header; all columns that are not pseudonym or kept
+/// columns are data point columns.
+/// Fails on the first value for which `f` returns an error, after writing the rows so far.
+pub fn process_table<F: FnMut(Column, &str) -> Result<String, String>>(
+    input: Option<&str>,
+    tsv: bool,
+    pseudonyms: &[String],
+    keep: &[String],
+    mut f: F,
+) {
+    let reader: Box<dyn Read> = match input {
+        Some(path) => Box::new(
+            File::open(path)
+                .unwrap_or_else(|e| fail(ErrorKind::Io, format!("Could not open {}: {}", path, e))),
+        ),
+        None => Box::new(stdin().lock()),
+    };
+    let delimiter = if tsv { b'\t' } else { b',' };
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .from_reader(reader);
+    let mut writer = csv::WriterBuilder::new()
+        .delimiter(delimiter)
+        .from_writer(stdout().lock());
+
+    let header = reader
+        .headers()
+        .unwrap_or_else(|e| fail(ErrorKind::InvalidInput, format!("Invalid header: {}", e)))
+        .clone();
+    for name in pseudonyms.iter().chain(keep) {
+        if !header.iter().any(|x| x == name) {
+            fail(ErrorKind::Usage, format!("Unknown column: {}", name));
+        }
+    }
+    let columns: Vec<Column> = header
+        .iter()
+        .map(|name| {
+            if pseudonyms.iter().any(|x| x == name) {
+                Column::Pseudonym
+            } else if keep.iter().any(|x| x == name) {
+                Column::Keep
+            } else {
+                Column::DataPoint
+            }
+        })
+        .collect();
+    written(writer.write_record(&header));
+
+    for (i, record) in reader.records().enumerate() {
+        // The header is the first row.
+        let row = i + 2;
+        let result = record
+            .map_err(|e| {
+                (
+                    ErrorKind::InvalidInput,
+                    format!("Invalid row {}: {}", row, e),
+                )
+            })
+            .and_then(|record| {
+                record
+                    .iter()
+                    .zip(header.iter().zip(&columns))
+                    .map(|(value, (name, &column))| match column {
+                        Column::Keep => Ok(value.to_string()),
+                        _ => f(column, value).map_err(|e| {
+                            (
+                                ErrorKind::InvalidInput,
+                                format!("Row {}, column {}: {}", row, name, e),
+                            )
+                        }),
+                    })
+                    .collect::<Result<Vec<_>, _>>()
+            });
+        match result {
+            Ok(values) => written(writer.write_record(&values)),
+            Err((kind, message)) => {
+                written(writer.flush());
+                drop(writer);
+                fail(kind, message);
+            }
+        }
+    }
+    written(writer.flush());
+}
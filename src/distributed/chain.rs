@@ -0,0 +1,285 @@
+//! A [`TranscryptionChain`] that passes encrypted messages through all *n* [`Transcryptor`]s of an
+//! n-PEP setup in order, as is required for distributed trust.
+
+use crate::distributed::systems::PEPSystem;
+use crate::high_level::contexts::*;
+use crate::high_level::data_types::*;
+use crate::high_level::ops::EncryptedEntityDataPair;
+use rand_core::{CryptoRng, RngCore};
+use std::convert::Infallible;
+
+/// A single hop in a [`TranscryptionChain`], which derives its own factors for the given contexts
+/// and applies them.
+/// This is implemented for in-process [`PEPSystem`]s (which cannot fail), but can also be
+/// implemented for handles to remote transcryptors.
+pub trait Transcryptor {
+    /// The error that can occur when this transcryptor is asked to perform an operation.
+    type Error;
+
+    /// Pseudonymize an [`EncryptedPseudonym`] from one pseudonymization and encryption context to
+    /// another.
+    fn pseudonymize(
+        &self,
+        encrypted: &EncryptedPseudonym,
+        from_pseudo: &PseudonymizationContext,
+        to_pseudo: &PseudonymizationContext,
+        from_enc: &EncryptionContext,
+        to_enc: &EncryptionContext,
+    ) -> Result<EncryptedPseudonym, Self::Error>;
+
+    /// Rekey an [`EncryptedDataPoint`] from one encryption context to another.
+    fn rekey(
+        &self,
+        encrypted: &EncryptedDataPoint,
+        from_enc: &EncryptionContext,
+        to_enc: &EncryptionContext,
+    ) -> Result<EncryptedDataPoint, Self::Error>;
+
+    /// Transcrypt (rekey or pseudonymize) an encrypted message from one pseudonymization and
+    /// encryption context to another.
+    fn transcrypt<E: Encrypted>(
+        &self,
+        encrypted: &E,
+        from_pseudo: &PseudonymizationContext,
+        to_pseudo: &PseudonymizationContext,
+        from_enc: &EncryptionContext,
+        to_enc: &EncryptionContext,
+    ) -> Result<E, Self::Error>;
+
+    /// Pseudonymize and shuffle a batch of [`EncryptedPseudonym`]s.
+    fn pseudonymize_batch<R: RngCore + CryptoRng>(
+        &self,
+        encrypted: &mut [EncryptedPseudonym],
+        from_pseudo: &PseudonymizationContext,
+        to_pseudo: &PseudonymizationContext,
+        from_enc: &EncryptionContext,
+        to_enc: &EncryptionContext,
+        rng: &mut R,
+    ) -> Result<Box<[EncryptedPseudonym]>, Self::Error>;
+
+    /// Rekey and shuffle a batch of [`EncryptedDataPoint`]s.
+    fn rekey_batch<R: RngCore + CryptoRng>(
+        &self,
+        encrypted: &mut [EncryptedDataPoint],
+        from_enc: &EncryptionContext,
+        to_enc: &EncryptionContext,
+        rng: &mut R,
+    ) -> Result<Box<[EncryptedDataPoint]>, Self::Error>;
+
+    /// Transcrypt and shuffle a batch of [`EncryptedEntityDataPair`]s.
+    fn transcrypt_batch<R: RngCore + CryptoRng>(
+        &self,
+        encrypted: &mut Box<[EncryptedEntityDataPair]>,
+        from_pseudo: &PseudonymizationContext,
+        to_pseudo: &PseudonymizationContext,
+        from_enc: &EncryptionContext,
+        to_enc: &EncryptionContext,
+        rng: &mut R,
+    ) -> Result<Box<[EncryptedEntityDataPair]>, Self::Error>;
+}
+
+impl Transcryptor for PEPSystem {
+    type Error = Infallible;
+
+    fn pseudonymize(
+        &self,
+        encrypted: &EncryptedPseudonym,
+        from_pseudo: &PseudonymizationContext,
+        to_pseudo: &PseudonymizationContext,
+        from_enc: &EncryptionContext,
+        to_enc: &EncryptionContext,
+    ) -> Result<EncryptedPseudonym, Self::Error> {
+        let info = self.pseudonymization_info(from_pseudo, to_pseudo, from_enc, to_enc);
+        Ok(PEPSystem::pseudonymize(self, encrypted, &info))
+    }
+
+    fn rekey(
+        &self,
+        encrypted: &EncryptedDataPoint,
+        from_enc: &EncryptionContext,
+        to_enc: &EncryptionContext,
+    ) -> Result<EncryptedDataPoint, Self::Error> {
+        let info = self.rekey_info(from_enc, to_enc);
+        Ok(PEPSystem::rekey(self, encrypted, &info))
+    }
+
+    fn transcrypt<E: Encrypted>(
+        &self,
+        encrypted: &E,
+        from_pseudo: &PseudonymizationContext,
+        to_pseudo: &PseudonymizationContext,
+        from_enc: &EncryptionContext,
+        to_enc: &EncryptionContext,
+    ) -> Result<E, Self::Error> {
+        let info = self.transcryption_info(from_pseudo, to_pseudo, from_enc, to_enc);
+        Ok(PEPSystem::transcrypt(self, encrypted, &info))
+    }
+
+    fn pseudonymize_batch<R: RngCore + CryptoRng>(
+        &self,
+        encrypted: &mut [EncryptedPseudonym],
+        from_pseudo: &PseudonymizationContext,
+        to_pseudo: &PseudonymizationContext,
+        from_enc: &EncryptionContext,
+        to_enc: &EncryptionContext,
+        rng: &mut R,
+    ) -> Result<Box<[EncryptedPseudonym]>, Self::Error> {
+        let info = self.pseudonymization_info(from_pseudo, to_pseudo, from_enc, to_enc);
+        Ok(PEPSystem::pseudonymize_batch(self, encrypted, &info, rng))
+    }
+
+    fn rekey_batch<R: RngCore + CryptoRng>(
+        &self,
+        encrypted: &mut [EncryptedDataPoint],
+        from_enc: &EncryptionContext,
+        to_enc: &EncryptionContext,
+        rng: &mut R,
+    ) -> Result<Box<[EncryptedDataPoint]>, Self::Error> {
+        let info = self.rekey_info(from_enc, to_enc);
+        Ok(PEPSystem::rekey_batch(self, encrypted, &info, rng))
+    }
+
+    fn transcrypt_batch<R: RngCore + CryptoRng>(
+        &self,
+        encrypted: &mut Box<[EncryptedEntityDataPair]>,
+        from_pseudo: &PseudonymizationContext,
+        to_pseudo: &PseudonymizationContext,
+        from_enc: &EncryptionContext,
+        to_enc: &EncryptionContext,
+        rng: &mut R,
+    ) -> Result<Box<[EncryptedEntityDataPair]>, Self::Error> {
+        let info = self.transcryption_info(from_pseudo, to_pseudo, from_enc, to_enc);
+        Ok(PEPSystem::transcrypt_batch(self, encrypted, &info, rng))
+    }
+}
+
+/// An error of a [`Transcryptor`] in a [`TranscryptionChain`], together with the index of the hop
+/// at which it occurred.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct HopError<E> {
+    pub hop: usize,
+    pub error: E,
+}
+
+/// An ordered chain of [`Transcryptor`]s, through which encrypted messages are passed one hop
+/// after another.
+/// For batch operations, every hop shuffles the batch, so that no single transcryptor can link
+/// the input and output order.
+#[derive(Clone)]
+pub struct TranscryptionChain<T: Transcryptor> {
+    hops: Vec<T>,
+}
+
+impl<T: Transcryptor> TranscryptionChain<T> {
+    /// Create a new chain of the given transcryptors, in the order in which they are applied.
+    pub fn new(hops: Vec<T>) -> Self {
+        Self { hops }
+    }
+    /// The transcryptors in this chain.
+    pub fn hops(&self) -> &[T] {
+        &self.hops
+    }
+    /// The number of transcryptors in this chain.
+    pub fn len(&self) -> usize {
+        self.hops.len()
+    }
+    /// Whether this chain has no transcryptors.
+    pub fn is_empty(&self) -> bool {
+        self.hops.is_empty()
+    }
+
+    fn run<M, F>(&self, message: M, mut f: F) -> Result<M, HopError<T::Error>>
+    where
+        F: FnMut(&T, M) -> Result<M, T::Error>,
+    {
+        self.hops
+            .iter()
+            .enumerate()
+            .try_fold(message, |acc, (hop, transcryptor)| {
+                f(transcryptor, acc).map_err(|error| HopError { hop, error })
+            })
+    }
+
+    /// Pseudonymize an [`EncryptedPseudonym`] through all hops.
+    pub fn pseudonymize(
+        &self,
+        encrypted: &EncryptedPseudonym,
+        from_pseudo: &PseudonymizationContext,
+        to_pseudo: &PseudonymizationContext,
+        from_enc: &EncryptionContext,
+        to_enc: &EncryptionContext,
+    ) -> Result<EncryptedPseudonym, HopError<T::Error>> {
+        self.run(*encrypted, |t, x| {
+            t.pseudonymize(&x, from_pseudo, to_pseudo, from_enc, to_enc)
+        })
+    }
+
+    /// Rekey an [`EncryptedDataPoint`] through all hops.
+    pub fn rekey(
+        &self,
+        encrypted: &EncryptedDataPoint,
+        from_enc: &EncryptionContext,
+        to_enc: &EncryptionContext,
+    ) -> Result<EncryptedDataPoint, HopError<T::Error>> {
+        self.run(*encrypted, |t, x| t.rekey(&x, from_enc, to_enc))
+    }
+
+    /// Transcrypt (rekey or pseudonymize) an encrypted message through all hops.
+    pub fn transcrypt<E: Encrypted + Clone>(
+        &self,
+        encrypted: &E,
+        from_pseudo: &PseudonymizationContext,
+        to_pseudo: &PseudonymizationContext,
+        from_enc: &EncryptionContext,
+        to_enc: &EncryptionContext,
+    ) -> Result<E, HopError<T::Error>> {
+        self.run(encrypted.clone(), |t, x| {
+            t.transcrypt(&x, from_pseudo, to_pseudo, from_enc, to_enc)
+        })
+    }
+
+    /// Pseudonymize a batch of [`EncryptedPseudonym`]s through all hops, shuffling them at every
+    /// hop.
+    pub fn pseudonymize_batch<R: RngCore + CryptoRng>(
+        &self,
+        encrypted: &[EncryptedPseudonym],
+        from_pseudo: &PseudonymizationContext,
+        to_pseudo: &PseudonymizationContext,
+        from_enc: &EncryptionContext,
+        to_enc: &EncryptionContext,
+        rng: &mut R,
+    ) -> Result<Box<[EncryptedPseudonym]>, HopError<T::Error>> {
+        self.run(encrypted.into(), |t, mut x| {
+            t.pseudonymize_batch(&mut x, from_pseudo, to_pseudo, from_enc, to_enc, rng)
+        })
+    }
+
+    /// Rekey a batch of [`EncryptedDataPoint`]s through all hops, shuffling them at every hop.
+    pub fn rekey_batch<R: RngCore + CryptoRng>(
+        &self,
+        encrypted: &[EncryptedDataPoint],
+        from_enc: &EncryptionContext,
+        to_enc: &EncryptionContext,
+        rng: &mut R,
+    ) -> Result<Box<[EncryptedDataPoint]>, HopError<T::Error>> {
+        self.run(encrypted.into(), |t, mut x| {
+            t.rekey_batch(&mut x, from_enc, to_enc, rng)
+        })
+    }
+
+    /// Transcrypt a batch of [`EncryptedEntityDataPair`]s through all hops, shuffling the entities
+    /// at every hop.
+    pub fn transcrypt_batch<R: RngCore + CryptoRng>(
+        &self,
+        encrypted: &[EncryptedEntityDataPair],
+        from_pseudo: &PseudonymizationContext,
+        to_pseudo: &PseudonymizationContext,
+        from_enc: &EncryptionContext,
+        to_enc: &EncryptionContext,
+        rng: &mut R,
+    ) -> Result<Box<[EncryptedEntityDataPair]>, HopError<T::Error>> {
+        self.run(encrypted.into(), |t, mut x| {
+            t.transcrypt_batch(&mut x, from_pseudo, to_pseudo, from_enc, to_enc, rng)
+        })
+    }
+}
@@ -0,0 +1,285 @@
+//! A [`PEPClientBuilder`] that fetches and verifies the session key shares for a [`PEPClient`]
+//! from the remote transcryptors of an n-PEP setup, over any [`Transport`].
+//!
+//! Shares are requested from all transcryptors concurrently, and every request is retried on
+//! transport failures. Timeouts are left to the [`Transport`] (e.g. `HttpTransport::with_timeout`),
+//! so that no request is left running in the background.
+//! Shares are verified against the [`BlindingCommitment`]s of the transcryptors, which are either
+//! pinned with [`PEPClientBuilder::with_blinding_commitments`] or fetched once and kept (trust on
+//! first use). This detects shares that were not created with the committed blinding factor, but
+//! not a transcryptor that uses a wrong rekey factor (see [`VerifiableSessionKeyShare`]).
+//!
+//! A [`RenewingPEPClient`] rebuilds its [`PEPClient`] whenever its [`SessionSchedule`] rolls over to
+//! a new [`EncryptionContext`].
+
+use crate::distributed::key_blinding::*;
+use crate::distributed::protocol::*;
+use crate::distributed::systems::{PEPClient, SessionKeyShareError};
+use crate::high_level::contexts::EncryptionContext;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The reason why a request to a single transcryptor failed.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum FetchError<E> {
+    /// The request failed (after all retries, in case of a transport error).
+    Protocol(ProtocolError<E>),
+    /// The transcryptor did not respond within the timeout of the transport (after all retries).
+    Timeout,
+}
+
+/// Errors that can occur when building a [`PEPClient`] from remote transcryptors.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum ClientBuilderError<E> {
+    /// A request to the transcryptor with this index failed.
+    Fetch {
+        transcryptor: usize,
+        error: FetchError<E>,
+    },
+    /// The number of pinned blinding commitments does not match the number of transcryptors.
+    WrongNumberOfCommitments { expected: usize, received: usize },
+    /// A session key share could not be verified against the blinding commitment.
+    InvalidShare(SessionKeyShareError),
+}
+
+struct Shares {
+    commitment: Option<BlindingCommitment>,
+    share: VerifiableSessionKeyShare,
+    #[cfg(feature = "separate-session-keys")]
+    pseudonym_share: VerifiableSessionKeyShare,
+}
+
+/// Builds [`PEPClient`]s for encryption contexts, by fetching verifiable session key shares from
+/// all transcryptors (in the order in which they are given).
+pub struct PEPClientBuilder<T: Transport> {
+    blinded_global_secret_key: BlindedGlobalSecretKey,
+    transcryptors: Vec<Arc<RemoteTranscryptor<T>>>,
+    blinding_commitments: Option<Vec<BlindingCommitment>>,
+    retries: usize,
+    retry_delay: Duration,
+}
+
+impl<T> PEPClientBuilder<T>
+where
+    T: Transport + Send + Sync,
+    T::Error: Send,
+{
+    /// Create a new builder with a transport to every transcryptor, with 2 retries per request.
+    pub fn new(blinded_global_secret_key: BlindedGlobalSecretKey, transports: Vec<T>) -> Self {
+        Self {
+            blinded_global_secret_key,
+            transcryptors: transports
+                .into_iter()
+                .map(|transport| Arc::new(RemoteTranscryptor::new(transport)))
+                .collect(),
+            blinding_commitments: None,
+            retries: 2,
+            retry_delay: Duration::from_millis(100),
+        }
+    }
+    /// Pin the [`BlindingCommitment`]s of the transcryptors (in the same order), instead of
+    /// fetching them from the transcryptors themselves.
+    pub fn with_blinding_commitments(
+        mut self,
+        blinding_commitments: Vec<BlindingCommitment>,
+    ) -> Self {
+        self.blinding_commitments = Some(blinding_commitments);
+        self
+    }
+    /// Set how often a request is retried after a transport error or timeout, and how long to wait
+    /// in between.
+    pub fn with_retries(mut self, retries: usize, retry_delay: Duration) -> Self {
+        self.retries = retries;
+        self.retry_delay = retry_delay;
+        self
+    }
+    /// The transcryptors this builder fetches shares from.
+    pub fn transcryptors(&self) -> &[Arc<RemoteTranscryptor<T>>] {
+        &self.transcryptors
+    }
+    /// The blinding commitments against which shares are verified, if pinned or already fetched.
+    pub fn blinding_commitments(&self) -> Option<&[BlindingCommitment]> {
+        self.blinding_commitments.as_deref()
+    }
+
+    fn call<V, F>(
+        &self,
+        transcryptor: &RemoteTranscryptor<T>,
+        f: F,
+    ) -> Result<V, FetchError<T::Error>>
+    where
+        F: Fn(&RemoteTranscryptor<T>) -> Result<V, ProtocolError<T::Error>>,
+    {
+        let mut attempt = 0;
+        loop {
+            let error = match f(transcryptor) {
+                Ok(value) => return Ok(value),
+                Err(ProtocolError::Transport(e)) if T::is_timeout(&e) => FetchError::Timeout,
+                Err(ProtocolError::Transport(e)) => {
+                    FetchError::Protocol(ProtocolError::Transport(e))
+                }
+                Err(e) => return Err(FetchError::Protocol(e)),
+            };
+            if attempt >= self.retries {
+                return Err(error);
+            }
+            attempt += 1;
+            thread::sleep(self.retry_delay);
+        }
+    }
+
+    fn fetch(
+        &self,
+        transcryptor: &RemoteTranscryptor<T>,
+        context: &EncryptionContext,
+        fetch_commitment: bool,
+    ) -> Result<Shares, FetchError<T::Error>> {
+        let commitment = if fetch_commitment {
+            Some(self.call(transcryptor, |t| t.blinding_commitment())?)
+        } else {
+            None
+        };
+        let share = self.call(transcryptor, |t| t.verifiable_session_key_share(context))?;
+        #[cfg(feature = "separate-session-keys")]
+        let pseudonym_share = self.call(transcryptor, |t| {
+            t.verifiable_pseudonym_session_key_share(context)
+        })?;
+        Ok(Shares {
+            commitment,
+            share,
+            #[cfg(feature = "separate-session-keys")]
+            pseudonym_share,
+        })
+    }
+
+    /// Fetch the session key shares for an encryption context from all transcryptors
+    /// concurrently, verify them and build the [`PEPClient`] for that context.
+    /// If no blinding commitments are known yet, they are fetched as well and kept for later
+    /// builds.
+    pub fn build(
+        &mut self,
+        context: &EncryptionContext,
+    ) -> Result<PEPClient, ClientBuilderError<T::Error>> {
+        if let Some(commitments) = &self.blinding_commitments {
+            if commitments.len() != self.transcryptors.len() {
+                return Err(ClientBuilderError::WrongNumberOfCommitments {
+                    expected: self.transcryptors.len(),
+                    received: commitments.len(),
+                });
+            }
+        }
+        let fetch_commitments = self.blinding_commitments.is_none();
+        let results: Vec<_> = thread::scope(|scope| {
+            let handles: Vec<_> = self
+                .transcryptors
+                .iter()
+                .map(|t| scope.spawn(|| self.fetch(t, context, fetch_commitments)))
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().expect("fetching shares panicked"))
+                .collect()
+        });
+        let mut fetched = Vec::with_capacity(results.len());
+        for (transcryptor, result) in results.into_iter().enumerate() {
+            fetched.push(result.map_err(|error| ClientBuilderError::Fetch {
+                transcryptor,
+                error,
+            })?);
+        }
+        let commitments: Vec<BlindingCommitment> = match &self.blinding_commitments {
+            Some(commitments) => commitments.clone(),
+            None => fetched.iter().filter_map(|x| x.commitment).collect(),
+        };
+        let shares: Vec<_> = fetched.iter().map(|x| x.share).collect();
+        #[cfg(not(feature = "separate-session-keys"))]
+        let client = PEPClient::new_verified(self.blinded_global_secret_key, &commitments, &shares);
+        #[cfg(feature = "separate-session-keys")]
+        let client = PEPClient::new_verified(
+            self.blinded_global_secret_key,
+            &commitments,
+            &shares,
+            &fetched
+                .iter()
+                .map(|x| x.pseudonym_share)
+                .collect::<Vec<_>>(),
+        );
+        let client = client.map_err(ClientBuilderError::InvalidShare)?;
+        self.blinding_commitments = Some(commitments);
+        Ok(client)
+    }
+}
+
+/// Determines the [`EncryptionContext`] of the current session of a client.
+pub trait SessionSchedule {
+    /// The encryption context of the current session.
+    fn current_context(&self) -> EncryptionContext;
+}
+
+impl<F: Fn() -> EncryptionContext> SessionSchedule for F {
+    fn current_context(&self) -> EncryptionContext {
+        self()
+    }
+}
+
+/// A [`SessionSchedule`] with sessions of a fixed length, named `{prefix}-{n}` for the `n`-th
+/// interval since the Unix epoch.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct IntervalSchedule {
+    pub prefix: String,
+    pub interval: Duration,
+}
+
+impl SessionSchedule for IntervalSchedule {
+    fn current_context(&self) -> EncryptionContext {
+        let elapsed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let n = elapsed.as_secs() / self.interval.as_secs().max(1);
+        EncryptionContext::from(format!("{}-{}", self.prefix, n).as_str())
+    }
+}
+
+/// A [`PEPClient`] that is transparently rebuilt with a [`PEPClientBuilder`] when its
+/// [`SessionSchedule`] rolls over to a new encryption context.
+pub struct RenewingPEPClient<T: Transport, S: SessionSchedule> {
+    builder: PEPClientBuilder<T>,
+    schedule: S,
+    current: Option<(EncryptionContext, PEPClient)>,
+}
+
+impl<T, S> RenewingPEPClient<T, S>
+where
+    T: Transport + Send + Sync,
+    T::Error: Send,
+    S: SessionSchedule,
+{
+    /// Create a new renewing client. No shares are fetched until the client is first used.
+    pub fn new(builder: PEPClientBuilder<T>, schedule: S) -> Self {
+        Self {
+            builder,
+            schedule,
+            current: None,
+        }
+    }
+    /// The builder used to renew the client.
+    pub fn builder(&self) -> &PEPClientBuilder<T> {
+        &self.builder
+    }
+    /// The encryption context of the current client, if it has been built.
+    pub fn context(&self) -> Option<&EncryptionContext> {
+        self.current.as_ref().map(|(context, _)| context)
+    }
+    /// The client for the current session, which is (re)built if the session context has
+    /// changed since the last call.
+    /// If renewing fails, the error is returned and the next call tries again.
+    pub fn client(&mut self) -> Result<&PEPClient, ClientBuilderError<T::Error>> {
+        let context = self.schedule.current_context();
+        if self.context() != Some(&context) {
+            let client = self.builder.build(&context)?;
+            self.current = Some((context, client));
+        }
+        Ok(&self.current.as_ref().unwrap().1)
+    }
+}
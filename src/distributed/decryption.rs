@@ -0,0 +1,127 @@
+//! Verifiable distributed decryption of ciphertexts encrypted with the [`GlobalPublicKey`]
+//! (e.g. by an [`OfflinePEPClient`](crate::distributed::systems::OfflinePEPClient)), towards a
+//! designated recipient, without ever reconstructing the global secret key.
+//!
+//! Every transcryptor in turn rekeys the ciphertext from the global key to the session key of the
+//! recipient's [`EncryptionContext`](crate::high_level::contexts::EncryptionContext) (see
+//! [`PEPSystem::verifiable_rekey_from_global`](crate::distributed::systems::PEPSystem::verifiable_rekey_from_global)),
+//! and proves that it used the same rekey factor as in its [`VerifiableSessionKeyShare`] for that
+//! context.
+//! The recipient verifies all shares and rekey steps, and decrypts the result with
+//! [`decrypt_distributed`].
+//!
+//! [`GlobalPublicKey`]: crate::high_level::keys::GlobalPublicKey
+
+use crate::distributed::key_blinding::*;
+use crate::high_level::data_types::*;
+use crate::high_level::ops::decrypt;
+use crate::internal::arithmetic::*;
+use crate::low_level::elgamal::ElGamal;
+use crate::low_level::primitives::rekey;
+use crate::low_level::proofs::*;
+use rand_core::{CryptoRng, RngCore};
+use serde::{Deserialize, Serialize};
+
+/// The result of rekeying an encrypted message with a rekey factor `k`, with a proof that `k`
+/// matches the rekey commitment `k * G` of a [`VerifiableSessionKeyShare`].
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub struct VerifiableRekey<E> {
+    pub result: E,
+    /// Proof that `result.gb = k^-1 * gb`.
+    pub proof: DleqProof,
+    /// Proof that `result.gy = k * gy`.
+    #[cfg(feature = "elgamal3")]
+    pub gy_proof: DleqProof,
+}
+
+/// Rekey an encrypted message with rekey factor `k` and prove that this was done correctly.
+pub fn make_verifiable_rekey<E: Encrypted, R: RngCore + CryptoRng>(
+    encrypted: &E,
+    k: &ScalarNonZero,
+    rng: &mut R,
+) -> VerifiableRekey<E> {
+    let value = encrypted.value();
+    let rekey_commitment = k * G;
+    VerifiableRekey {
+        result: E::from_value(rekey(value, k)),
+        proof: prove_dleq(&k.invert(), &rekey_commitment, &value.gb, rng),
+        #[cfg(feature = "elgamal3")]
+        gy_proof: prove_dleq(k, &G, &value.gy, rng),
+    }
+}
+
+/// Verify that a [`VerifiableRekey`] of `encrypted` is correct for the given rekey commitment
+/// `k * G`.
+pub fn verify_rekey<E: Encrypted>(
+    encrypted: &E,
+    rekey: &VerifiableRekey<E>,
+    rekey_commitment: &GroupElement,
+) -> bool {
+    let input: &ElGamal = encrypted.value();
+    let output: &ElGamal = rekey.result.value();
+    #[cfg(feature = "elgamal3")]
+    if !verify_dleq(&rekey.gy_proof, &G, rekey_commitment, &input.gy, &output.gy) {
+        return false;
+    }
+    input.gc == output.gc && verify_dleq(&rekey.proof, rekey_commitment, &G, &input.gb, &output.gb)
+}
+
+/// Errors that can occur during [`decrypt_distributed`].
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum DistributedDecryptionError {
+    /// The number of session key shares does not match the number of blinding commitments.
+    WrongNumberOfShares { expected: usize, received: usize },
+    /// The session key share of this transcryptor is not consistent with its blinding commitment.
+    InvalidShare(usize),
+    /// The number of rekey steps does not match the number of transcryptors.
+    WrongNumberOfRekeys { expected: usize, received: usize },
+    /// The rekey step of this transcryptor is incorrect.
+    InvalidRekey(usize),
+}
+
+/// Verify and decrypt a ciphertext encrypted with the global public key, that was rekeyed by all
+/// transcryptors (in the same order as their blinding commitments and session key shares)
+/// towards the session of the recipient.
+///
+/// The session key shares must be those for the recipient's encryption context, and for the same
+/// kind of message (with the `separate-session-keys` feature, pseudonyms use pseudonym session key
+/// shares).
+pub fn decrypt_distributed<E: Encrypted>(
+    encrypted: &E,
+    blinded_global_secret_key: BlindedGlobalSecretKey,
+    blinding_commitments: &[BlindingCommitment],
+    session_key_shares: &[VerifiableSessionKeyShare],
+    rekeys: &[VerifiableRekey<E>],
+) -> Result<E::UnencryptedType, DistributedDecryptionError> {
+    let n = blinding_commitments.len();
+    if session_key_shares.len() != n {
+        return Err(DistributedDecryptionError::WrongNumberOfShares {
+            expected: n,
+            received: session_key_shares.len(),
+        });
+    }
+    if rekeys.len() != n {
+        return Err(DistributedDecryptionError::WrongNumberOfRekeys {
+            expected: n,
+            received: rekeys.len(),
+        });
+    }
+    let mut current = encrypted;
+    for (i, ((commitment, share), rekey)) in blinding_commitments
+        .iter()
+        .zip(session_key_shares)
+        .zip(rekeys)
+        .enumerate()
+    {
+        if !verify_session_key_share(share, commitment) {
+            return Err(DistributedDecryptionError::InvalidShare(i));
+        }
+        if !verify_rekey(current, rekey, &share.rekey_commitment) {
+            return Err(DistributedDecryptionError::InvalidRekey(i));
+        }
+        current = &rekey.result;
+    }
+    let shares: Vec<SessionKeyShare> = session_key_shares.iter().map(|x| x.share).collect();
+    let (_, session_secret_key) = make_session_key(blinded_global_secret_key, &shares);
+    Ok(decrypt(current, &session_secret_key))
+}
@@ -0,0 +1,267 @@
+//! Dealerless distributed key generation, in which *n* transcryptors jointly generate the
+//! [`GlobalPublicKey`] and [`BlindedGlobalSecretKey`] and their own [`BlindingFactor`]s, without any
+//! party ever knowing the global secret key.
+//!
+//! The global secret key is the product `y = y_1 * ... * y_n` of a secret contribution `y_i` of
+//! every party, and party `i` publishes its contribution blinded with its own blinding factor:
+//! `y_i * b_i^-1`.
+//! The product of these is the blinded global secret key `y * (b_1 * ... * b_n)^-1`, exactly as in
+//! [`make_blinded_global_secret_key`](crate::distributed::key_blinding::make_blinded_global_secret_key).
+//!
+//! The protocol consists of the following rounds:
+//! 1. Every party broadcasts a [`DkgCommitment`] to its contribution `y_i * G` and blinding factor
+//!    `b_i * G`, together with its blinded contribution. The blinded contribution is checked to be
+//!    consistent with both commitments.
+//! 2. In order of their index, every party multiplies the public key of the previous party (starting
+//!    with `G`) with its contribution and broadcasts the result as a [`DkgChainStep`], with a
+//!    [`DleqProof`] that it used the same contribution it committed to.
+//!
+//! The public key of the last party is the global public key.
+//! All messages are serializable, so they can be sent over any channel, but must be delivered to all
+//! parties (i.e. broadcast). [`run_dkg`] runs the full protocol in-process.
+
+use crate::distributed::key_blinding::*;
+use crate::high_level::keys::*;
+use crate::internal::arithmetic::*;
+use crate::low_level::proofs::*;
+use rand_core::{CryptoRng, RngCore};
+use serde::{Deserialize, Serialize};
+
+/// Errors that can occur while running the distributed key generation.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum DkgError {
+    /// The message claims to be from a party that does not exist.
+    UnknownParty(usize),
+    /// A message of this party was already received.
+    DuplicateMessage(usize),
+    /// The commitment of this party is not consistent with its blinded contribution.
+    InvalidCommitment(usize),
+    /// The proof in the chain step of this party is invalid.
+    InvalidProof(usize),
+    /// A chain step of a party was received while a step of another party was expected.
+    UnexpectedChainStep { expected: usize, received: usize },
+    /// The protocol cannot proceed yet, since messages from these parties are missing.
+    MissingMessages(Vec<usize>),
+}
+
+/// The message broadcast by every party in the first round.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub struct DkgCommitment {
+    pub party: usize,
+    /// Commitment `y_i * G` to the contribution to the global secret key.
+    pub contribution_commitment: GroupElement,
+    /// Commitment `b_i * G` to the blinding factor.
+    pub blinding_commitment: BlindingCommitment,
+    /// The blinded contribution `y_i * b_i^-1`.
+    pub blinded_contribution: BlindedGlobalSecretKey,
+}
+
+/// The message broadcast by every party (in order) in the second round.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub struct DkgChainStep {
+    pub party: usize,
+    /// The previous public key multiplied by `y_i`.
+    pub public_key: GroupElement,
+    /// Proof that `public_key` is the previous public key multiplied by the committed `y_i`.
+    pub proof: DleqProof,
+}
+
+/// A party in the distributed key generation.
+pub struct DkgParticipant {
+    party: usize,
+    n: usize,
+    contribution: ScalarNonZero,
+    blinding_factor: BlindingFactor,
+    commitments: Vec<Option<DkgCommitment>>,
+    chain: Vec<DkgChainStep>,
+}
+
+impl Drop for DkgParticipant {
+    fn drop(&mut self) {
+        self.contribution.zeroize();
+    }
+}
+
+impl DkgParticipant {
+    /// Start the distributed key generation as party `party` (in `0..n`) out of `n`.
+    pub fn new<R: RngCore + CryptoRng>(party: usize, n: usize, rng: &mut R) -> Self {
+        assert!(party < n);
+        let contribution = loop {
+            let y = ScalarNonZero::random(rng);
+            if y != ScalarNonZero::one() {
+                break y;
+            }
+        };
+        let mut participant = Self {
+            party,
+            n,
+            contribution,
+            blinding_factor: BlindingFactor::random(rng),
+            commitments: vec![None; n],
+            chain: vec![],
+        };
+        participant.commitments[party] = Some(participant.commitment());
+        participant
+    }
+    /// The index of this party.
+    pub fn party(&self) -> usize {
+        self.party
+    }
+    /// The message of this party for the first round.
+    pub fn commitment(&self) -> DkgCommitment {
+        DkgCommitment {
+            party: self.party,
+            contribution_commitment: self.contribution * G,
+            blinding_commitment: make_blinding_commitment(&self.blinding_factor),
+            blinded_contribution: BlindedGlobalSecretKey(
+                self.contribution * self.blinding_factor.0.invert(),
+            ),
+        }
+    }
+    /// Receive and verify the first round message of another party.
+    pub fn receive_commitment(&mut self, commitment: DkgCommitment) -> Result<(), DkgError> {
+        let party = commitment.party;
+        if party >= self.n {
+            return Err(DkgError::UnknownParty(party));
+        }
+        if self.commitments[party].is_some() {
+            return Err(DkgError::DuplicateMessage(party));
+        }
+        if commitment.blinded_contribution.0 * commitment.blinding_commitment.0
+            != commitment.contribution_commitment
+            || commitment.contribution_commitment == GroupElement::identity()
+        {
+            return Err(DkgError::InvalidCommitment(party));
+        }
+        self.commitments[party] = Some(commitment);
+        Ok(())
+    }
+    fn missing_commitments(&self) -> Vec<usize> {
+        (0..self.n)
+            .filter(|i| self.commitments[*i].is_none())
+            .collect()
+    }
+    fn previous_public_key(&self) -> GroupElement {
+        self.chain.last().map(|x| x.public_key).unwrap_or(G)
+    }
+    /// The party whose chain step is expected next, or `None` if the chain is complete.
+    pub fn next_chain_party(&self) -> Option<usize> {
+        if self.chain.len() < self.n {
+            Some(self.chain.len())
+        } else {
+            None
+        }
+    }
+    /// The message of this party for the second round.
+    /// Can only be created once the first round has completed and it is this party's turn.
+    pub fn chain_step<R: RngCore + CryptoRng>(
+        &mut self,
+        rng: &mut R,
+    ) -> Result<DkgChainStep, DkgError> {
+        let missing = self.missing_commitments();
+        if !missing.is_empty() {
+            return Err(DkgError::MissingMessages(missing));
+        }
+        if self.chain.len() != self.party {
+            return Err(DkgError::MissingMessages(
+                (self.chain.len()..self.party).collect(),
+            ));
+        }
+        let previous = self.previous_public_key();
+        let step = DkgChainStep {
+            party: self.party,
+            public_key: self.contribution * previous,
+            proof: prove_dleq(&self.contribution, &G, &previous, rng),
+        };
+        self.chain.push(step);
+        Ok(step)
+    }
+    /// Receive and verify the second round message of another party.
+    pub fn receive_chain_step(&mut self, step: DkgChainStep) -> Result<(), DkgError> {
+        let missing = self.missing_commitments();
+        if !missing.is_empty() {
+            return Err(DkgError::MissingMessages(missing));
+        }
+        let expected = self
+            .next_chain_party()
+            .ok_or(DkgError::DuplicateMessage(step.party))?;
+        if step.party != expected {
+            return Err(if step.party < expected {
+                DkgError::DuplicateMessage(step.party)
+            } else {
+                DkgError::UnexpectedChainStep {
+                    expected,
+                    received: step.party,
+                }
+            });
+        }
+        let commitment = self.commitments[step.party].unwrap();
+        if !verify_dleq(
+            &step.proof,
+            &G,
+            &commitment.contribution_commitment,
+            &self.previous_public_key(),
+            &step.public_key,
+        ) {
+            return Err(DkgError::InvalidProof(step.party));
+        }
+        self.chain.push(step);
+        Ok(())
+    }
+    /// Finish the protocol, resulting in the global public key, the blinded global secret key and
+    /// the blinding factor of this party.
+    pub fn finish(
+        self,
+    ) -> Result<(GlobalPublicKey, BlindedGlobalSecretKey, BlindingFactor), DkgError> {
+        if let Some(next) = self.next_chain_party() {
+            return Err(DkgError::MissingMessages((next..self.n).collect()));
+        }
+        let blinded_global_secret_key = BlindedGlobalSecretKey(
+            self.commitments
+                .iter()
+                .flatten()
+                .fold(ScalarNonZero::one(), |acc, x| {
+                    acc * x.blinded_contribution.0
+                }),
+        );
+        Ok((
+            GlobalPublicKey(self.previous_public_key()),
+            blinded_global_secret_key,
+            self.blinding_factor,
+        ))
+    }
+}
+
+/// Run the distributed key generation for `n` parties in-process.
+/// This results in keys of the same form as [`make_distributed_global_keys`], but is mostly useful
+/// for testing, since all blinding factors end up in a single process.
+pub fn run_dkg<R: RngCore + CryptoRng>(
+    n: usize,
+    rng: &mut R,
+) -> Result<(GlobalPublicKey, BlindedGlobalSecretKey, Vec<BlindingFactor>), DkgError> {
+    let mut participants: Vec<DkgParticipant> =
+        (0..n).map(|i| DkgParticipant::new(i, n, rng)).collect();
+    let commitments: Vec<DkgCommitment> = participants.iter().map(|p| p.commitment()).collect();
+    for participant in participants.iter_mut() {
+        for commitment in commitments.iter() {
+            if commitment.party != participant.party() {
+                participant.receive_commitment(*commitment)?;
+            }
+        }
+    }
+    for i in 0..n {
+        let step = participants[i].chain_step(rng)?;
+        for participant in participants.iter_mut() {
+            if participant.party() != i {
+                participant.receive_chain_step(step)?;
+            }
+        }
+    }
+    let results = participants
+        .into_iter()
+        .map(|p| p.finish())
+        .collect::<Result<Vec<_>, _>>()?;
+    let (public_key, blinded_global_secret_key, _) = results[0];
+    let blinding_factors = results.into_iter().map(|(_, _, b)| b).collect();
+    Ok((public_key, blinded_global_secret_key, blinding_factors))
+}
@@ -0,0 +1,222 @@
+//! Bounded least-recently-used cache for factors derived from contexts, so that a
+//! [`PEPSystem`](crate::distributed::systems::PEPSystem) does not need to recompute them for the
+//! contexts it transcrypts between most often.
+
+use crate::high_level::contexts::*;
+use crate::high_level::keys::{EncryptionSecret, PseudonymizationSecret};
+#[cfg(feature = "separate-session-keys")]
+use crate::high_level::utils::make_pseudonym_rekey_factor;
+use crate::high_level::utils::{make_pseudonymisation_factor, make_rekey_factor};
+use crate::internal::arithmetic::ScalarNonZero;
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard};
+
+/// A derived factor together with its precomputed inverse.
+/// Both are overwritten with zeroes when the entry is evicted or invalidated (but the copies
+/// returned from the cache are not).
+struct CachedFactor {
+    factor: ScalarNonZero,
+    inverse: ScalarNonZero,
+    last_used: u64,
+}
+impl Drop for CachedFactor {
+    fn drop(&mut self) {
+        self.factor.zeroize();
+        self.inverse.zeroize();
+    }
+}
+
+#[derive(Clone, Eq, Hash, PartialEq, Debug)]
+enum CacheKey {
+    Pseudonymization(PseudonymizationContext),
+    Encryption(EncryptionContext),
+    #[cfg(feature = "separate-session-keys")]
+    PseudonymEncryption(EncryptionContext),
+}
+
+#[derive(Default)]
+struct CacheState {
+    entries: HashMap<CacheKey, CachedFactor>,
+    clock: u64,
+    hits: u64,
+    misses: u64,
+}
+
+/// A bounded cache of [`ReshuffleFactor`]s and [`RekeyFactor`]s (and their inverses), keyed on the
+/// [`PseudonymizationContext`] or [`EncryptionContext`] they are derived from.
+/// When the cache is full, the least recently used factor is evicted.
+/// Evicted and invalidated factors are zeroized.
+///
+/// Only the copies held by the cache itself are zeroized: factors are returned by value (like
+/// the factors derived without a cache), so the copies handed out on a hit or a miss are not.
+/// The cache bounds how long a factor stays in its own memory, not how long it stays in memory
+/// at all.
+///
+/// Cloning a cache results in an empty cache with the same capacity, so that secrets are not
+/// duplicated in memory.
+pub struct FactorCache {
+    capacity: usize,
+    state: Mutex<CacheState>,
+}
+
+impl FactorCache {
+    /// Create a new empty cache that holds at most `capacity` factors.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            state: Mutex::new(CacheState::default()),
+        }
+    }
+    /// The maximum number of factors this cache holds.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+    /// The number of factors currently cached.
+    pub fn len(&self) -> usize {
+        self.lock().entries.len()
+    }
+    /// Whether the cache is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// The number of cache hits and misses since the cache was created or last [cleared](Self::clear).
+    pub fn stats(&self) -> (u64, u64) {
+        let state = self.lock();
+        (state.hits, state.misses)
+    }
+    /// Remove (and zeroize) all cached factors.
+    pub fn clear(&self) {
+        let mut state = self.lock();
+        state.entries.clear();
+        state.hits = 0;
+        state.misses = 0;
+    }
+    /// Remove (and zeroize) the cached factor for a pseudonymization context, if any.
+    /// Returns whether a factor was removed.
+    pub fn invalidate_pseudonymization_context(&self, context: &PseudonymizationContext) -> bool {
+        self.lock()
+            .entries
+            .remove(&CacheKey::Pseudonymization(context.clone()))
+            .is_some()
+    }
+    /// Remove (and zeroize) the cached factors for an encryption context, if any.
+    /// Returns whether a factor was removed.
+    pub fn invalidate_encryption_context(&self, context: &EncryptionContext) -> bool {
+        let mut state = self.lock();
+        #[cfg(feature = "separate-session-keys")]
+        let removed_pseudonym = state
+            .entries
+            .remove(&CacheKey::PseudonymEncryption(context.clone()))
+            .is_some();
+        #[cfg(not(feature = "separate-session-keys"))]
+        let removed_pseudonym = false;
+        let removed = state
+            .entries
+            .remove(&CacheKey::Encryption(context.clone()))
+            .is_some();
+        removed || removed_pseudonym
+    }
+
+    /// Get the reshuffle factor for a pseudonymization context and its inverse, deriving them if
+    /// they are not cached yet.
+    pub(crate) fn reshuffle_factor(
+        &self,
+        secret: &PseudonymizationSecret,
+        context: &PseudonymizationContext,
+    ) -> (ReshuffleFactor, ReshuffleFactor) {
+        let (factor, inverse) = self
+            .get_or_insert(CacheKey::Pseudonymization(context.clone()), || {
+                make_pseudonymisation_factor(secret, context).0
+            });
+        (
+            ReshuffleFactor::from(factor),
+            ReshuffleFactor::from(inverse),
+        )
+    }
+
+    /// Get the rekey factor for an encryption context and its inverse, deriving them if they are not
+    /// cached yet.
+    pub(crate) fn rekey_factor(
+        &self,
+        secret: &EncryptionSecret,
+        context: &EncryptionContext,
+    ) -> (RekeyFactor, RekeyFactor) {
+        let (factor, inverse) = self.get_or_insert(CacheKey::Encryption(context.clone()), || {
+            make_rekey_factor(secret, context).0
+        });
+        (RekeyFactor::from(factor), RekeyFactor::from(inverse))
+    }
+
+    /// Get the rekey factor for pseudonyms for an encryption context and its inverse, deriving them
+    /// if they are not cached yet.
+    /// Without the `separate-session-keys` feature, this is the same as [`Self::rekey_factor`].
+    pub(crate) fn pseudonym_rekey_factor(
+        &self,
+        secret: &EncryptionSecret,
+        context: &EncryptionContext,
+    ) -> (RekeyFactor, RekeyFactor) {
+        #[cfg(not(feature = "separate-session-keys"))]
+        {
+            self.rekey_factor(secret, context)
+        }
+        #[cfg(feature = "separate-session-keys")]
+        {
+            let (factor, inverse) = self
+                .get_or_insert(CacheKey::PseudonymEncryption(context.clone()), || {
+                    make_pseudonym_rekey_factor(secret, context).0
+                });
+            (RekeyFactor::from(factor), RekeyFactor::from(inverse))
+        }
+    }
+
+    fn get_or_insert<F: FnOnce() -> ScalarNonZero>(
+        &self,
+        key: CacheKey,
+        derive: F,
+    ) -> (ScalarNonZero, ScalarNonZero) {
+        let mut state = self.lock();
+        state.clock += 1;
+        let now = state.clock;
+        if let Some(entry) = state.entries.get_mut(&key) {
+            entry.last_used = now;
+            let result = (entry.factor, entry.inverse);
+            state.hits += 1;
+            return result;
+        }
+        state.misses += 1;
+        let factor = derive();
+        let inverse = factor.invert();
+        if self.capacity == 0 {
+            return (factor, inverse);
+        }
+        if state.entries.len() >= self.capacity {
+            let oldest = state
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                state.entries.remove(&oldest);
+            }
+        }
+        state.entries.insert(
+            key,
+            CachedFactor {
+                factor,
+                inverse,
+                last_used: now,
+            },
+        );
+        (factor, inverse)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, CacheState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Clone for FactorCache {
+    fn clone(&self) -> Self {
+        Self::new(self.capacity)
+    }
+}
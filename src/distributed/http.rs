@@ -0,0 +1,97 @@
+//! A [`Transport`] that sends [`Request`]s as JSON over HTTP, to a transcryptor served by
+//! `peppy-server` (or any other server implementing the same API).
+//! This module is only available when the `http-client` feature is enabled.
+
+use crate::distributed::protocol::*;
+use std::time::Duration;
+
+/// Errors that can occur when sending a request over HTTP.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum HttpTransportError {
+    /// The server could not be reached, or the connection failed.
+    Connection(String),
+    /// The server did not respond within the timeout of the transport.
+    Timeout(String),
+    /// The server responded with an HTTP error status without a protocol [`Response`].
+    Status(u16),
+    /// The response could not be parsed.
+    InvalidResponse(String),
+}
+
+/// A [`Transport`] to the `/v1/request` endpoint of a transcryptor at a base URL (e.g.
+/// `http://localhost:8080`).
+#[derive(Clone, Debug)]
+pub struct HttpTransport {
+    url: String,
+    agent: ureq::Agent,
+}
+
+impl HttpTransport {
+    /// Create a new transport to the transcryptor at the given base URL, with a timeout of 30
+    /// seconds for every request.
+    pub fn new(base_url: &str) -> Self {
+        Self::with_timeout(base_url, Duration::from_secs(30))
+    }
+    /// Create a new transport to the transcryptor at the given base URL, with the given timeout
+    /// for every request.
+    pub fn with_timeout(base_url: &str, timeout: Duration) -> Self {
+        Self {
+            url: format!("{}/v1/request", base_url.trim_end_matches('/')),
+            agent: ureq::AgentBuilder::new().timeout(timeout).build(),
+        }
+    }
+    /// The URL requests are sent to.
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+}
+
+impl Transport for HttpTransport {
+    type Error = HttpTransportError;
+
+    fn send(&self, request: &Request) -> Result<Response, Self::Error> {
+        let body = serde_json::to_string(request)
+            .map_err(|e| HttpTransportError::InvalidResponse(e.to_string()))?;
+        let response = match self
+            .agent
+            .post(&self.url)
+            .set("Content-Type", "application/json")
+            .send_string(&body)
+        {
+            Ok(response) => response,
+            // The server reports protocol errors as a JSON response with an error status.
+            Err(ureq::Error::Status(status, response)) => {
+                return response
+                    .into_string()
+                    .ok()
+                    .and_then(|body| serde_json::from_str(&body).ok())
+                    .ok_or(HttpTransportError::Status(status))
+            }
+            Err(e) => return Err(connection_error(&e)),
+        };
+        let body = response.into_string().map_err(|e| connection_error(&e))?;
+        serde_json::from_str(&body).map_err(|e| HttpTransportError::InvalidResponse(e.to_string()))
+    }
+
+    fn is_timeout(error: &Self::Error) -> bool {
+        matches!(error, HttpTransportError::Timeout(_))
+    }
+}
+
+/// A [`HttpTransportError::Timeout`] if the error (or its source) is an I/O timeout, and a
+/// [`HttpTransportError::Connection`] otherwise.
+fn connection_error(error: &(dyn std::error::Error + 'static)) -> HttpTransportError {
+    let mut source = Some(error);
+    while let Some(e) = source {
+        if let Some(io) = e.downcast_ref::<std::io::Error>() {
+            if matches!(
+                io.kind(),
+                std::io::ErrorKind::TimedOut | std::io::ErrorKind::WouldBlock
+            ) {
+                return HttpTransportError::Timeout(error.to_string());
+            }
+        }
+        source = e.source();
+    }
+    HttpTransportError::Connection(error.to_string())
+}
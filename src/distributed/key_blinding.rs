@@ -1,7 +1,11 @@
-//! Key blinding, session key share generation and session key retrieval for distributed trust.
+//! Key blinding, session key share generation and session key retrieval for distributed trust,
+//! [proactive refresh](BlindingRefresh) of blinding factors, and transcryptors
+//! [joining](run_transcryptor_join) or [leaving](run_transcryptor_leave) a distributed setup.
 
 use crate::high_level::keys::*;
 use crate::internal::arithmetic::*;
+use crate::low_level::proofs::*;
+use derive_more::{Deref, From};
 use rand_core::{CryptoRng, RngCore};
 use serde::de::{Error, Visitor};
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
@@ -22,6 +26,29 @@ pub struct BlindedGlobalSecretKey(pub(crate) ScalarNonZero);
 #[derive(Copy, Clone, Eq, PartialEq, Debug)]
 pub struct SessionKeyShare(pub(crate) ScalarNonZero);
 
+/// A public commitment `b * G` to the [`BlindingFactor`] `b` of a transcryptor, against which its
+/// [`VerifiableSessionKeyShare`]s can be verified.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Deref, From, Serialize, Deserialize)]
+pub struct BlindingCommitment(pub GroupElement);
+
+/// A [`SessionKeyShare`] `k * b` together with a proof that it is consistent with the
+/// [`BlindingCommitment`] of the transcryptor that created it.
+/// The proof shows that the share is the rekey commitment `k * G` multiplied with the same `b` as
+/// the blinding commitment.
+///
+/// The proof does *not* show that `k` is the correct rekey factor for the session: the rekey
+/// commitment is chosen by the transcryptor itself and is not bound to anything published in
+/// advance. A share created without the committed blinding factor (e.g. by a transcryptor with an
+/// outdated configuration) or altered in transit is detected, but a transcryptor that knows its
+/// blinding factor can hand out a share for any other rekey factor with a valid proof.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub struct VerifiableSessionKeyShare {
+    pub share: SessionKeyShare,
+    /// Commitment `k * G` to the rekey factor, as claimed by the transcryptor.
+    pub rekey_commitment: GroupElement,
+    pub proof: DleqProof,
+}
+
 /// A trait for scalars that are safe to encode and decode since they do not need to remain absolutely secret.
 pub trait SafeScalar {
     /// Create from a scalar.
@@ -94,6 +121,15 @@ impl SafeScalar for SessionKeyShare {
         &self.0
     }
 }
+impl SafeScalar for BlindingRefreshFactor {
+    fn from(x: ScalarNonZero) -> Self {
+        BlindingRefreshFactor(x)
+    }
+
+    fn value(&self) -> &ScalarNonZero {
+        &self.0
+    }
+}
 impl BlindingFactor {
     /// Create a random blinding factor.
     pub fn random<R: RngCore + CryptoRng>(rng: &mut R) -> Self {
@@ -103,6 +139,39 @@ impl BlindingFactor {
     }
 }
 
+impl Serialize for BlindingFactor {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(self.encode_as_hex().as_str())
+    }
+}
+impl<'de> Deserialize<'de> for BlindingFactor {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct BlindingFactorVisitor;
+        impl Visitor<'_> for BlindingFactorVisitor {
+            type Value = BlindingFactor;
+            fn expecting(&self, formatter: &mut Formatter) -> std::fmt::Result {
+                formatter.write_str("a hex encoded string representing a BlindingFactor")
+            }
+
+            fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+            where
+                E: Error,
+            {
+                ScalarNonZero::decode_from_hex(v)
+                    .map(BlindingFactor)
+                    .ok_or(E::custom(format!("invalid hex encoded string: {}", v)))
+            }
+        }
+
+        deserializer.deserialize_str(BlindingFactorVisitor)
+    }
+}
 impl Serialize for BlindedGlobalSecretKey {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -195,6 +264,42 @@ pub fn make_session_key_share(
     SessionKeyShare(rekey_factor * blinding_factor.0)
 }
 
+/// Create the public [`BlindingCommitment`] for a [`BlindingFactor`].
+pub fn make_blinding_commitment(blinding_factor: &BlindingFactor) -> BlindingCommitment {
+    BlindingCommitment(blinding_factor.0 * G)
+}
+
+/// Create a [`VerifiableSessionKeyShare`] from a [`ScalarNonZero`] rekey factor and a
+/// [`BlindingFactor`].
+pub fn make_verifiable_session_key_share<R: RngCore + CryptoRng>(
+    rekey_factor: &ScalarNonZero,
+    blinding_factor: &BlindingFactor,
+    rng: &mut R,
+) -> VerifiableSessionKeyShare {
+    let rekey_commitment = rekey_factor * G;
+    VerifiableSessionKeyShare {
+        share: make_session_key_share(rekey_factor, blinding_factor),
+        rekey_commitment,
+        proof: prove_dleq(&blinding_factor.0, &G, &rekey_commitment, rng),
+    }
+}
+
+/// Verify that a [`VerifiableSessionKeyShare`] is consistent with a [`BlindingCommitment`], i.e.
+/// that it was created with the committed blinding factor (but not that its rekey factor is the
+/// correct one, see [`VerifiableSessionKeyShare`]).
+pub fn verify_session_key_share(
+    share: &VerifiableSessionKeyShare,
+    blinding_commitment: &BlindingCommitment,
+) -> bool {
+    verify_dleq(
+        &share.proof,
+        &G,
+        &blinding_commitment.0,
+        &share.rekey_commitment,
+        &(share.share.0 * G),
+    )
+}
+
 /// Reconstruct a session key from a [`BlindedGlobalSecretKey`] and a list of [`SessionKeyShare`]s.
 pub fn make_session_key(
     blinded_global_secret_key: BlindedGlobalSecretKey,
@@ -223,3 +328,381 @@ pub fn make_distributed_global_keys<R: RngCore + CryptoRng>(
     let bsk = make_blinded_global_secret_key(&sk, &blinding_factors).unwrap();
     (pk, bsk, blinding_factors)
 }
+
+/// A factor with which a [`BlindingFactor`] (or [`BlindedGlobalSecretKey`]) is multiplied during a
+/// [`BlindingRefresh`].
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct BlindingRefreshFactor(pub(crate) ScalarNonZero);
+
+impl Serialize for BlindingRefreshFactor {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(self.encode_as_hex().as_str())
+    }
+}
+impl<'de> Deserialize<'de> for BlindingRefreshFactor {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct BlindingRefreshFactorVisitor;
+        impl Visitor<'_> for BlindingRefreshFactorVisitor {
+            type Value = BlindingRefreshFactor;
+            fn expecting(&self, formatter: &mut Formatter) -> std::fmt::Result {
+                formatter.write_str("a hex encoded string representing a BlindingRefreshFactor")
+            }
+
+            fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+            where
+                E: Error,
+            {
+                ScalarNonZero::decode_from_hex(v)
+                    .map(BlindingRefreshFactor)
+                    .ok_or(E::custom(format!("invalid hex encoded string: {}", v)))
+            }
+        }
+
+        deserializer.deserialize_str(BlindingRefreshFactorVisitor)
+    }
+}
+
+/// Whether a [`BlindingRefresh`] keeps the [`BlindedGlobalSecretKey`] as it is, or results in a
+/// new one.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub enum BlindingRefreshMode {
+    /// The product of all blinding factors remains unchanged, and so does the blinded global
+    /// secret key. Session keys also remain the same.
+    KeepBlindedGlobalSecretKey,
+    /// The product of all blinding factors changes, and a new blinded global secret key is
+    /// published, which clients need to use from then on.
+    NewBlindedGlobalSecretKey,
+}
+
+/// A message from one transcryptor to another during a [`BlindingRefresh`].
+/// This message must be sent over a confidential channel, since the new blinding factor of the
+/// recipient depends on it.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub struct BlindingRefreshShare {
+    pub from: usize,
+    pub to: usize,
+    pub factor: BlindingRefreshFactor,
+}
+
+/// A message broadcast by every transcryptor during a [`BlindingRefresh`] in
+/// [`BlindingRefreshMode::NewBlindedGlobalSecretKey`], with which the blinded global secret key is
+/// updated.
+/// It contains the inverse of the product of all factors this party sent, so it does not reveal
+/// the update of any individual blinding factor.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub struct BlindedGlobalSecretKeyRefresh {
+    pub party: usize,
+    pub factor: BlindingRefreshFactor,
+}
+
+/// Errors that can occur during a [`BlindingRefresh`].
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum BlindingRefreshError {
+    /// The message claims to be from a party that does not exist.
+    UnknownParty(usize),
+    /// The message is meant for another party.
+    WrongRecipient(usize),
+    /// A message of this party was already received.
+    DuplicateMessage(usize),
+    /// A [`BlindedGlobalSecretKeyRefresh`] was received while the blinded global secret key is kept.
+    UnexpectedMessage(usize),
+    /// The refresh cannot be finished yet, since messages from these parties are missing.
+    MissingMessages(Vec<usize>),
+    /// This party is the last one, so it cannot leave.
+    LastParty(usize),
+}
+
+/// A party in the proactive refresh of blinding factors, after which a compromised old
+/// [`BlindingFactor`] is useless.
+/// The [`GlobalPublicKey`] (and therefore all ciphertexts encrypted with it) remains valid.
+///
+/// Every party `i` draws a random factor `d_ij` for every party `j` (including itself), which is
+/// sent to party `j` in a [`BlindingRefreshShare`].
+/// The new blinding factor of party `j` is `b_j * d_1j * ... * d_nj`.
+/// In [`BlindingRefreshMode::KeepBlindedGlobalSecretKey`], every party chooses its factors such
+/// that `d_i1 * ... * d_in = 1`, so the product of all blinding factors is unchanged.
+/// In [`BlindingRefreshMode::NewBlindedGlobalSecretKey`], all factors are random and every party
+/// additionally broadcasts a [`BlindedGlobalSecretKeyRefresh`] with `(d_i1 * ... * d_in)^-1`.
+pub struct BlindingRefresh {
+    party: usize,
+    mode: BlindingRefreshMode,
+    outgoing: Vec<ScalarNonZero>,
+    received: Vec<Option<ScalarNonZero>>,
+    key_refreshes: Vec<Option<ScalarNonZero>>,
+}
+
+impl Drop for BlindingRefresh {
+    fn drop(&mut self) {
+        self.outgoing.iter_mut().for_each(|x| x.zeroize());
+        self.received.iter_mut().flatten().for_each(|x| x.zeroize());
+    }
+}
+
+impl BlindingRefresh {
+    /// Start a refresh as party `party` (in `0..n`) out of `n`.
+    pub fn new<R: RngCore + CryptoRng>(
+        party: usize,
+        n: usize,
+        mode: BlindingRefreshMode,
+        rng: &mut R,
+    ) -> Self {
+        assert!(party < n);
+        let mut outgoing: Vec<ScalarNonZero> = (0..n).map(|_| ScalarNonZero::random(rng)).collect();
+        if mode == BlindingRefreshMode::KeepBlindedGlobalSecretKey {
+            let product = outgoing[..n - 1]
+                .iter()
+                .fold(ScalarNonZero::one(), |acc, x| acc * x);
+            outgoing[n - 1] = product.invert();
+        }
+        let mut received = vec![None; n];
+        received[party] = Some(outgoing[party]);
+        let mut key_refreshes = vec![None; n];
+        if mode == BlindingRefreshMode::NewBlindedGlobalSecretKey {
+            key_refreshes[party] = Some(Self::product(&outgoing).invert());
+        }
+        Self {
+            party,
+            mode,
+            outgoing,
+            received,
+            key_refreshes,
+        }
+    }
+    fn product(factors: &[ScalarNonZero]) -> ScalarNonZero {
+        factors.iter().fold(ScalarNonZero::one(), |acc, x| acc * x)
+    }
+    /// The index of this party.
+    pub fn party(&self) -> usize {
+        self.party
+    }
+    /// The mode of this refresh.
+    pub fn mode(&self) -> BlindingRefreshMode {
+        self.mode
+    }
+    /// The confidential messages of this party for all other parties.
+    pub fn shares(&self) -> Vec<BlindingRefreshShare> {
+        (0..self.outgoing.len())
+            .filter(|j| *j != self.party)
+            .map(|j| BlindingRefreshShare {
+                from: self.party,
+                to: j,
+                factor: BlindingRefreshFactor(self.outgoing[j]),
+            })
+            .collect()
+    }
+    /// The public message of this party, if a new blinded global secret key is created.
+    pub fn blinded_global_secret_key_refresh(&self) -> Option<BlindedGlobalSecretKeyRefresh> {
+        self.key_refreshes[self.party].map(|x| BlindedGlobalSecretKeyRefresh {
+            party: self.party,
+            factor: BlindingRefreshFactor(x),
+        })
+    }
+    /// Receive a confidential message of another party.
+    pub fn receive_share(
+        &mut self,
+        share: BlindingRefreshShare,
+    ) -> Result<(), BlindingRefreshError> {
+        if share.from >= self.received.len() {
+            return Err(BlindingRefreshError::UnknownParty(share.from));
+        }
+        if share.to != self.party {
+            return Err(BlindingRefreshError::WrongRecipient(share.to));
+        }
+        if self.received[share.from].is_some() {
+            return Err(BlindingRefreshError::DuplicateMessage(share.from));
+        }
+        self.received[share.from] = Some(share.factor.0);
+        Ok(())
+    }
+    /// Receive the public message of another party.
+    pub fn receive_blinded_global_secret_key_refresh(
+        &mut self,
+        refresh: BlindedGlobalSecretKeyRefresh,
+    ) -> Result<(), BlindingRefreshError> {
+        if refresh.party >= self.key_refreshes.len() {
+            return Err(BlindingRefreshError::UnknownParty(refresh.party));
+        }
+        if self.mode == BlindingRefreshMode::KeepBlindedGlobalSecretKey {
+            return Err(BlindingRefreshError::UnexpectedMessage(refresh.party));
+        }
+        if self.key_refreshes[refresh.party].is_some() {
+            return Err(BlindingRefreshError::DuplicateMessage(refresh.party));
+        }
+        self.key_refreshes[refresh.party] = Some(refresh.factor.0);
+        Ok(())
+    }
+    /// Finish the refresh, resulting in the new blinding factor of this party and the (possibly
+    /// new) blinded global secret key.
+    /// The old blinding factor should be discarded afterwards.
+    pub fn finish(
+        self,
+        blinding_factor: &BlindingFactor,
+        blinded_global_secret_key: &BlindedGlobalSecretKey,
+    ) -> Result<(BlindingFactor, BlindedGlobalSecretKey), BlindingRefreshError> {
+        let mut missing: Vec<usize> = (0..self.received.len())
+            .filter(|i| self.received[*i].is_none())
+            .collect();
+        if self.mode == BlindingRefreshMode::NewBlindedGlobalSecretKey {
+            missing
+                .extend((0..self.key_refreshes.len()).filter(|i| self.key_refreshes[*i].is_none()));
+            missing.sort();
+            missing.dedup();
+        }
+        if !missing.is_empty() {
+            return Err(BlindingRefreshError::MissingMessages(missing));
+        }
+        let received: Vec<ScalarNonZero> = self.received.iter().flatten().copied().collect();
+        let new_blinding_factor = BlindingFactor(blinding_factor.0 * Self::product(&received));
+        let key_refreshes: Vec<ScalarNonZero> =
+            self.key_refreshes.iter().flatten().copied().collect();
+        let new_blinded_global_secret_key =
+            BlindedGlobalSecretKey(blinded_global_secret_key.0 * Self::product(&key_refreshes));
+        Ok((new_blinding_factor, new_blinded_global_secret_key))
+    }
+    /// Finish the refresh as a transcryptor that joins the distributed setup (and therefore has
+    /// no blinding factor yet), resulting in its first blinding factor and the new blinded global
+    /// secret key.
+    /// See [`run_transcryptor_join`].
+    pub fn finish_joining(
+        self,
+        blinded_global_secret_key: &BlindedGlobalSecretKey,
+    ) -> Result<(BlindingFactor, BlindedGlobalSecretKey), BlindingRefreshError> {
+        self.finish(
+            &BlindingFactor(ScalarNonZero::one()),
+            blinded_global_secret_key,
+        )
+    }
+}
+
+/// A message in which a transcryptor that leaves the distributed setup hands over its blinding
+/// factor to one of the remaining transcryptors, which absorbs it into its own blinding factor.
+/// This message must be sent over a confidential channel.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub struct BlindingFactorHandover {
+    pub from: usize,
+    pub to: usize,
+    pub factor: BlindingRefreshFactor,
+}
+
+/// Create a [`BlindingFactorHandover`] of the blinding factor of leaving party `from` to party
+/// `to`.
+pub fn make_blinding_factor_handover(
+    from: usize,
+    to: usize,
+    blinding_factor: &BlindingFactor,
+) -> BlindingFactorHandover {
+    BlindingFactorHandover {
+        from,
+        to,
+        factor: BlindingRefreshFactor(blinding_factor.0),
+    }
+}
+
+/// Absorb the blinding factor of a leaving party into the blinding factor of the recipient of the
+/// [`BlindingFactorHandover`].
+/// The product of all blinding factors (and thus the blinded global secret key) is unchanged.
+pub fn absorb_blinding_factor_handover(
+    blinding_factor: &BlindingFactor,
+    handover: &BlindingFactorHandover,
+) -> BlindingFactor {
+    BlindingFactor(blinding_factor.0 * handover.factor.0)
+}
+
+/// Run a [`BlindingRefresh`] for all parties in-process, resulting in the new blinded global
+/// secret key and new blinding factors (in the same order).
+/// Mostly useful for testing, since all blinding factors are available in a single process.
+pub fn run_blinding_refresh<R: RngCore + CryptoRng>(
+    blinding_factors: &[BlindingFactor],
+    blinded_global_secret_key: &BlindedGlobalSecretKey,
+    mode: BlindingRefreshMode,
+    rng: &mut R,
+) -> Result<(BlindedGlobalSecretKey, Vec<BlindingFactor>), BlindingRefreshError> {
+    let n = blinding_factors.len();
+    let mut parties: Vec<BlindingRefresh> = (0..n)
+        .map(|i| BlindingRefresh::new(i, n, mode, rng))
+        .collect();
+    let shares: Vec<BlindingRefreshShare> = parties.iter().flat_map(|p| p.shares()).collect();
+    let key_refreshes: Vec<BlindedGlobalSecretKeyRefresh> = parties
+        .iter()
+        .filter_map(|p| p.blinded_global_secret_key_refresh())
+        .collect();
+    for party in parties.iter_mut() {
+        let i = party.party();
+        for share in shares.iter().filter(|s| s.to == i) {
+            party.receive_share(*share)?;
+        }
+        for refresh in key_refreshes.iter().filter(|r| r.party != i) {
+            party.receive_blinded_global_secret_key_refresh(*refresh)?;
+        }
+    }
+    let mut new_blinded_global_secret_key = *blinded_global_secret_key;
+    let mut new_blinding_factors = Vec::with_capacity(n);
+    for (party, blinding_factor) in parties.into_iter().zip(blinding_factors) {
+        let (b, bsk) = party.finish(blinding_factor, blinded_global_secret_key)?;
+        new_blinding_factors.push(b);
+        new_blinded_global_secret_key = bsk;
+    }
+    Ok((new_blinded_global_secret_key, new_blinding_factors))
+}
+
+/// Let a new transcryptor join a distributed setup of `blinding_factors.len()` transcryptors
+/// in-process, without changing the [`GlobalPublicKey`].
+/// All parties, including the joining one (with the last index), run a [`BlindingRefresh`] in
+/// [`BlindingRefreshMode::NewBlindedGlobalSecretKey`], in which the joining party starts without a
+/// blinding factor (see [`BlindingRefresh::finish_joining`]).
+/// Results in the new blinded global secret key and all `n + 1` blinding factors.
+pub fn run_transcryptor_join<R: RngCore + CryptoRng>(
+    blinding_factors: &[BlindingFactor],
+    blinded_global_secret_key: &BlindedGlobalSecretKey,
+    rng: &mut R,
+) -> Result<(BlindedGlobalSecretKey, Vec<BlindingFactor>), BlindingRefreshError> {
+    let mut blinding_factors = blinding_factors.to_vec();
+    blinding_factors.push(BlindingFactor(ScalarNonZero::one()));
+    run_blinding_refresh(
+        &blinding_factors,
+        blinded_global_secret_key,
+        BlindingRefreshMode::NewBlindedGlobalSecretKey,
+        rng,
+    )
+}
+
+/// Let transcryptor `leaving` leave a distributed setup in-process, without changing the
+/// [`GlobalPublicKey`].
+/// The leaving party hands over its blinding factor with a [`BlindingFactorHandover`], after which
+/// the remaining parties run a [`BlindingRefresh`] in
+/// [`BlindingRefreshMode::NewBlindedGlobalSecretKey`], so that the blinding factor known to the
+/// leaving party becomes useless.
+/// Results in the new blinded global secret key and the blinding factors of the remaining `n - 1`
+/// parties (in the same order, without the leaving party), or an error if `leaving` is not a party
+/// or the last one.
+pub fn run_transcryptor_leave<R: RngCore + CryptoRng>(
+    blinding_factors: &[BlindingFactor],
+    blinded_global_secret_key: &BlindedGlobalSecretKey,
+    leaving: usize,
+    rng: &mut R,
+) -> Result<(BlindedGlobalSecretKey, Vec<BlindingFactor>), BlindingRefreshError> {
+    let n = blinding_factors.len();
+    if leaving >= n {
+        return Err(BlindingRefreshError::UnknownParty(leaving));
+    }
+    if n == 1 {
+        return Err(BlindingRefreshError::LastParty(leaving));
+    }
+    let recipient = if leaving == 0 { 1 } else { 0 };
+    let handover = make_blinding_factor_handover(leaving, recipient, &blinding_factors[leaving]);
+    let mut remaining = blinding_factors.to_vec();
+    remaining[recipient] = absorb_blinding_factor_handover(&remaining[recipient], &handover);
+    remaining.remove(leaving);
+    run_blinding_refresh(
+        &remaining,
+        blinded_global_secret_key,
+        BlindingRefreshMode::NewBlindedGlobalSecretKey,
+        rng,
+    )
+}
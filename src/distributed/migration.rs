@@ -0,0 +1,165 @@
+//! Migration of ciphertexts held by a [`PEPClient`] from one session ([`EncryptionContext`]) to
+//! the next, e.g. after a [`RenewingPEPClient`](crate::distributed::client::RenewingPEPClient)
+//! rolled over.
+//!
+//! Every transcryptor provides its [`RekeyInfo`] from the old to the new session, and these are
+//! combined into a single [`SessionMigration`], which the client applies locally to its stored
+//! [`EncryptedDataPoint`]s and [`EncryptedPseudonym`]s.
+//! The combined rekey factor equals the ratio of the new and old session secret keys, so it does
+//! not reveal anything to a client holding both session keys.
+//! Pseudonyms are only rekeyed, not pseudonymized.
+
+use crate::distributed::chain::HopError;
+use crate::distributed::protocol::{ProtocolError, RemoteTranscryptor, Transport};
+use crate::distributed::systems::{PEPClient, PEPSystem};
+use crate::high_level::contexts::*;
+use crate::high_level::data_types::*;
+use crate::internal::arithmetic::ScalarNonZero;
+use crate::low_level::primitives::rekey;
+
+/// The progress of migrating a collection of ciphertexts.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct MigrationProgress {
+    /// The number of ciphertexts migrated so far.
+    pub done: usize,
+    /// The total number of ciphertexts to migrate.
+    pub total: usize,
+}
+
+/// The combined rekey information of all transcryptors to migrate ciphertexts from one session to
+/// another.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct SessionMigration {
+    pub from: EncryptionContext,
+    pub to: EncryptionContext,
+    data: RekeyInfo,
+    #[cfg(feature = "separate-session-keys")]
+    pseudonym: RekeyInfo,
+}
+
+fn combine(rekey_infos: &[RekeyInfo]) -> RekeyInfo {
+    RekeyInfo::from(
+        rekey_infos
+            .iter()
+            .fold(ScalarNonZero::one(), |acc, x| acc * x.0),
+    )
+}
+
+impl SessionMigration {
+    /// Combine the rekey infos of all transcryptors from one session to another.
+    #[cfg(not(feature = "separate-session-keys"))]
+    pub fn new(from: EncryptionContext, to: EncryptionContext, rekey_infos: &[RekeyInfo]) -> Self {
+        Self {
+            from,
+            to,
+            data: combine(rekey_infos),
+        }
+    }
+    /// Combine the rekey infos of all transcryptors from one session to another, for data points
+    /// and for pseudonyms.
+    #[cfg(feature = "separate-session-keys")]
+    pub fn new(
+        from: EncryptionContext,
+        to: EncryptionContext,
+        rekey_infos: &[RekeyInfo],
+        pseudonym_rekey_infos: &[RekeyInfo],
+    ) -> Self {
+        Self {
+            from,
+            to,
+            data: combine(rekey_infos),
+            pseudonym: combine(pseudonym_rekey_infos),
+        }
+    }
+    /// Derive the migration from the given (in-process) systems.
+    pub fn from_systems(
+        systems: &[PEPSystem],
+        from: &EncryptionContext,
+        to: &EncryptionContext,
+    ) -> Self {
+        let rekey_infos: Vec<_> = systems.iter().map(|s| s.rekey_info(from, to)).collect();
+        #[cfg(not(feature = "separate-session-keys"))]
+        return Self::new(from.clone(), to.clone(), &rekey_infos);
+        #[cfg(feature = "separate-session-keys")]
+        Self::new(
+            from.clone(),
+            to.clone(),
+            &rekey_infos,
+            &systems
+                .iter()
+                .map(|s| s.pseudonym_rekey_info(from, to))
+                .collect::<Vec<_>>(),
+        )
+    }
+    /// Request the rekey infos from all remote transcryptors and combine them.
+    pub fn request<T: Transport>(
+        transcryptors: &[RemoteTranscryptor<T>],
+        from: &EncryptionContext,
+        to: &EncryptionContext,
+    ) -> Result<Self, HopError<ProtocolError<T::Error>>> {
+        let rekey_infos = transcryptors
+            .iter()
+            .enumerate()
+            .map(|(hop, t)| {
+                t.rekey_info(from, to)
+                    .map_err(|error| HopError { hop, error })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        #[cfg(not(feature = "separate-session-keys"))]
+        return Ok(Self::new(from.clone(), to.clone(), &rekey_infos));
+        #[cfg(feature = "separate-session-keys")]
+        Ok(Self::new(
+            from.clone(),
+            to.clone(),
+            &rekey_infos,
+            &transcryptors
+                .iter()
+                .enumerate()
+                .map(|(hop, t)| {
+                    t.pseudonym_rekey_info(from, to)
+                        .map_err(|error| HopError { hop, error })
+                })
+                .collect::<Result<Vec<_>, _>>()?,
+        ))
+    }
+    /// Check that this migration rekeys ciphertexts from the session of client `from` to that of
+    /// client `to`, using their session public keys.
+    pub fn verify(&self, from: &PEPClient, to: &PEPClient) -> bool {
+        #[cfg(feature = "separate-session-keys")]
+        if self.pseudonym.0 * from.pseudonym_session_public_key.0
+            != to.pseudonym_session_public_key.0
+        {
+            return false;
+        }
+        self.data.0 * from.session_public_key.0 == to.session_public_key.0
+    }
+    /// Migrate a single encrypted message to the new session.
+    pub fn migrate<E: Encrypted>(&self, encrypted: &E) -> E {
+        #[cfg(feature = "separate-session-keys")]
+        if E::IS_PSEUDONYM {
+            return E::from_value(rekey(encrypted.value(), &self.pseudonym.0));
+        }
+        E::from_value(rekey(encrypted.value(), &self.data.0))
+    }
+    /// Migrate a collection of encrypted messages in place (keeping their order), reporting
+    /// progress after every `report_every` messages and when done.
+    pub fn migrate_all<E: Encrypted, F: FnMut(MigrationProgress)>(
+        &self,
+        encrypted: &mut [E],
+        report_every: usize,
+        mut progress: F,
+    ) {
+        let total = encrypted.len();
+        let mut done = 0;
+        for chunk in encrypted.chunks_mut(report_every.max(1)) {
+            for x in chunk.iter_mut() {
+                *x = self.migrate(x);
+            }
+            done += chunk.len();
+            progress(MigrationProgress { done, total });
+        }
+        if total == 0 {
+            progress(MigrationProgress { done, total });
+        }
+    }
+}
@@ -0,0 +1,554 @@
+//! Transport-agnostic protocol between clients and [`PEPSystem`]s (transcryptors).
+//!
+//! Clients send a [`Request`] over any [`Transport`], and a [`PEPSystemServer`] answers it with a
+//! [`Response`].
+//! Both are serializable with serde, so that clients and transcryptors from different parties can
+//! interoperate, regardless of the serialization format and transport they use.
+//! A [`RemoteTranscryptor`] wraps a transport into a [`Transcryptor`], so that remote systems can
+//! be used in a [`TranscryptionChain`](crate::distributed::chain::TranscryptionChain).
+//! For testing, an [`InMemoryTransport`] directly passes messages to a server in the same process.
+
+use crate::distributed::chain::Transcryptor;
+use crate::distributed::key_blinding::*;
+use crate::distributed::systems::PEPSystem;
+use crate::high_level::contexts::*;
+use crate::high_level::data_types::*;
+use crate::high_level::ops::EncryptedEntityDataPair;
+use rand_core::{CryptoRng, OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+
+/// A request to a transcryptor.
+#[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Request {
+    /// Request the [`BlindingCommitment`] of the transcryptor.
+    BlindingCommitment,
+    /// Request a [`SessionKeyShare`] for an encryption context.
+    SessionKeyShare { context: EncryptionContext },
+    /// Request a [`VerifiableSessionKeyShare`] for an encryption context.
+    VerifiableSessionKeyShare { context: EncryptionContext },
+    /// Request a [`SessionKeyShare`] for the session key for pseudonyms for an encryption context.
+    #[cfg(feature = "separate-session-keys")]
+    PseudonymSessionKeyShare { context: EncryptionContext },
+    /// Request a [`VerifiableSessionKeyShare`] for the session key for pseudonyms for an
+    /// encryption context.
+    #[cfg(feature = "separate-session-keys")]
+    VerifiablePseudonymSessionKeyShare { context: EncryptionContext },
+    /// Request the [`PseudonymizationInfo`] between contexts.
+    PseudonymizationInfo {
+        from_pseudo: PseudonymizationContext,
+        to_pseudo: PseudonymizationContext,
+        from_enc: EncryptionContext,
+        to_enc: EncryptionContext,
+    },
+    /// Request the [`RekeyInfo`] between encryption contexts.
+    RekeyInfo {
+        from_enc: EncryptionContext,
+        to_enc: EncryptionContext,
+    },
+    /// Request the [`RekeyInfo`] to rekey encrypted pseudonyms between encryption contexts.
+    #[cfg(feature = "separate-session-keys")]
+    PseudonymRekeyInfo {
+        from_enc: EncryptionContext,
+        to_enc: EncryptionContext,
+    },
+    /// Pseudonymize an [`EncryptedPseudonym`].
+    Pseudonymize {
+        encrypted: EncryptedPseudonym,
+        from_pseudo: PseudonymizationContext,
+        to_pseudo: PseudonymizationContext,
+        from_enc: EncryptionContext,
+        to_enc: EncryptionContext,
+    },
+    /// Rekey an [`EncryptedDataPoint`].
+    Rekey {
+        encrypted: EncryptedDataPoint,
+        from_enc: EncryptionContext,
+        to_enc: EncryptionContext,
+    },
+    /// Pseudonymize and shuffle a batch of [`EncryptedPseudonym`]s.
+    PseudonymizeBatch {
+        encrypted: Vec<EncryptedPseudonym>,
+        from_pseudo: PseudonymizationContext,
+        to_pseudo: PseudonymizationContext,
+        from_enc: EncryptionContext,
+        to_enc: EncryptionContext,
+    },
+    /// Rekey and shuffle a batch of [`EncryptedDataPoint`]s.
+    RekeyBatch {
+        encrypted: Vec<EncryptedDataPoint>,
+        from_enc: EncryptionContext,
+        to_enc: EncryptionContext,
+    },
+    /// Transcrypt and shuffle a batch of [`EncryptedEntityDataPair`]s.
+    TranscryptBatch {
+        encrypted: Vec<EncryptedEntityDataPair>,
+        from_pseudo: PseudonymizationContext,
+        to_pseudo: PseudonymizationContext,
+        from_enc: EncryptionContext,
+        to_enc: EncryptionContext,
+    },
+}
+
+/// An error reported by a transcryptor in a [`Response`].
+#[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "message", rename_all = "snake_case")]
+pub enum ServerError {
+    /// The request could not be parsed.
+    InvalidRequest(String),
+    /// The request is not supported by this transcryptor (e.g. because it is built with different
+    /// features).
+    UnsupportedRequest(String),
+    /// The request was refused, e.g. because it was too large.
+    Refused(String),
+    /// Any other error.
+    Internal(String),
+}
+
+/// A response of a transcryptor to a [`Request`].
+#[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", content = "value", rename_all = "snake_case")]
+pub enum Response {
+    BlindingCommitment(BlindingCommitment),
+    SessionKeyShare(SessionKeyShare),
+    VerifiableSessionKeyShare(Box<VerifiableSessionKeyShare>),
+    PseudonymizationInfo(PseudonymizationInfo),
+    RekeyInfo(RekeyInfo),
+    Pseudonymized(EncryptedPseudonym),
+    Rekeyed(EncryptedDataPoint),
+    PseudonymizedBatch(Vec<EncryptedPseudonym>),
+    RekeyedBatch(Vec<EncryptedDataPoint>),
+    TranscryptedBatch(Vec<EncryptedEntityDataPair>),
+    Error(ServerError),
+}
+
+/// Server-side dispatcher that answers [`Request`]s using a [`PEPSystem`].
+pub struct PEPSystemServer {
+    system: PEPSystem,
+}
+
+impl PEPSystemServer {
+    /// Create a new server for the given system.
+    pub fn new(system: PEPSystem) -> Self {
+        Self { system }
+    }
+    /// The system this server uses.
+    pub fn system(&self) -> &PEPSystem {
+        &self.system
+    }
+    /// Answer a request, using the operating system's random number generator for shuffling and
+    /// proofs.
+    pub fn handle(&self, request: Request) -> Response {
+        self.handle_with_rng(request, &mut OsRng)
+    }
+    /// Answer a request, using the given random number generator for shuffling and proofs.
+    pub fn handle_with_rng<R: RngCore + CryptoRng>(
+        &self,
+        request: Request,
+        rng: &mut R,
+    ) -> Response {
+        let system = &self.system;
+        match request {
+            Request::BlindingCommitment => {
+                Response::BlindingCommitment(system.blinding_commitment())
+            }
+            Request::SessionKeyShare { context } => {
+                Response::SessionKeyShare(system.session_key_share(&context))
+            }
+            Request::VerifiableSessionKeyShare { context } => Response::VerifiableSessionKeyShare(
+                Box::new(system.verifiable_session_key_share(&context, rng)),
+            ),
+            #[cfg(feature = "separate-session-keys")]
+            Request::PseudonymSessionKeyShare { context } => {
+                Response::SessionKeyShare(system.pseudonym_session_key_share(&context))
+            }
+            #[cfg(feature = "separate-session-keys")]
+            Request::VerifiablePseudonymSessionKeyShare { context } => {
+                Response::VerifiableSessionKeyShare(Box::new(
+                    system.verifiable_pseudonym_session_key_share(&context, rng),
+                ))
+            }
+            Request::PseudonymizationInfo {
+                from_pseudo,
+                to_pseudo,
+                from_enc,
+                to_enc,
+            } => Response::PseudonymizationInfo(system.pseudonymization_info(
+                &from_pseudo,
+                &to_pseudo,
+                &from_enc,
+                &to_enc,
+            )),
+            Request::RekeyInfo { from_enc, to_enc } => {
+                Response::RekeyInfo(system.rekey_info(&from_enc, &to_enc))
+            }
+            #[cfg(feature = "separate-session-keys")]
+            Request::PseudonymRekeyInfo { from_enc, to_enc } => {
+                Response::RekeyInfo(system.pseudonym_rekey_info(&from_enc, &to_enc))
+            }
+            Request::Pseudonymize {
+                encrypted,
+                from_pseudo,
+                to_pseudo,
+                from_enc,
+                to_enc,
+            } => {
+                let info =
+                    system.pseudonymization_info(&from_pseudo, &to_pseudo, &from_enc, &to_enc);
+                Response::Pseudonymized(system.pseudonymize(&encrypted, &info))
+            }
+            Request::Rekey {
+                encrypted,
+                from_enc,
+                to_enc,
+            } => {
+                let info = system.rekey_info(&from_enc, &to_enc);
+                Response::Rekeyed(system.rekey(&encrypted, &info))
+            }
+            Request::PseudonymizeBatch {
+                mut encrypted,
+                from_pseudo,
+                to_pseudo,
+                from_enc,
+                to_enc,
+            } => {
+                let info =
+                    system.pseudonymization_info(&from_pseudo, &to_pseudo, &from_enc, &to_enc);
+                Response::PseudonymizedBatch(
+                    system
+                        .pseudonymize_batch(&mut encrypted, &info, rng)
+                        .into_vec(),
+                )
+            }
+            Request::RekeyBatch {
+                mut encrypted,
+                from_enc,
+                to_enc,
+            } => {
+                let info = system.rekey_info(&from_enc, &to_enc);
+                Response::RekeyedBatch(system.rekey_batch(&mut encrypted, &info, rng).into_vec())
+            }
+            Request::TranscryptBatch {
+                encrypted,
+                from_pseudo,
+                to_pseudo,
+                from_enc,
+                to_enc,
+            } => {
+                let info = system.transcryption_info(&from_pseudo, &to_pseudo, &from_enc, &to_enc);
+                let mut encrypted = encrypted.into_boxed_slice();
+                Response::TranscryptedBatch(
+                    system
+                        .transcrypt_batch(&mut encrypted, &info, rng)
+                        .into_vec(),
+                )
+            }
+        }
+    }
+}
+
+/// A transport that delivers a [`Request`] to a transcryptor and returns its [`Response`].
+/// A transport is responsible for its own timeouts: [`Transport::send`] should not block
+/// indefinitely on an unresponsive transcryptor.
+pub trait Transport {
+    /// The error that can occur when delivering a request.
+    type Error;
+    /// Send a request and wait for the response.
+    fn send(&self, request: &Request) -> Result<Response, Self::Error>;
+    /// Whether an error means that the transcryptor did not respond in time.
+    fn is_timeout(_error: &Self::Error) -> bool {
+        false
+    }
+}
+
+/// A [`Transport`] that directly passes requests to a [`PEPSystemServer`] in the same process.
+pub struct InMemoryTransport {
+    server: PEPSystemServer,
+}
+
+impl InMemoryTransport {
+    /// Create a new in-memory transport to the given server.
+    pub fn new(server: PEPSystemServer) -> Self {
+        Self { server }
+    }
+    /// The server this transport delivers requests to.
+    pub fn server(&self) -> &PEPSystemServer {
+        &self.server
+    }
+}
+
+impl Transport for InMemoryTransport {
+    type Error = std::convert::Infallible;
+    fn send(&self, request: &Request) -> Result<Response, Self::Error> {
+        Ok(self.server.handle(request.clone()))
+    }
+}
+
+/// Errors that can occur when using a [`RemoteTranscryptor`].
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum ProtocolError<E> {
+    /// The request could not be delivered.
+    Transport(E),
+    /// The transcryptor reported an error.
+    Server(ServerError),
+    /// The transcryptor responded with a response of the wrong type.
+    UnexpectedResponse,
+}
+
+/// A client-side handle to a transcryptor reachable over a [`Transport`].
+pub struct RemoteTranscryptor<T: Transport> {
+    transport: T,
+}
+
+impl<T: Transport> RemoteTranscryptor<T> {
+    /// Create a new handle using the given transport.
+    pub fn new(transport: T) -> Self {
+        Self { transport }
+    }
+    /// The transport of this handle.
+    pub fn transport(&self) -> &T {
+        &self.transport
+    }
+    /// Send a request and return the response, turning [`Response::Error`] into an error.
+    pub fn request(&self, request: &Request) -> Result<Response, ProtocolError<T::Error>> {
+        match self.transport.send(request) {
+            Ok(Response::Error(error)) => Err(ProtocolError::Server(error)),
+            Ok(response) => Ok(response),
+            Err(error) => Err(ProtocolError::Transport(error)),
+        }
+    }
+    /// Request the [`BlindingCommitment`] of the transcryptor.
+    pub fn blinding_commitment(&self) -> Result<BlindingCommitment, ProtocolError<T::Error>> {
+        match self.request(&Request::BlindingCommitment)? {
+            Response::BlindingCommitment(x) => Ok(x),
+            _ => Err(ProtocolError::UnexpectedResponse),
+        }
+    }
+    /// Request a [`SessionKeyShare`] for an encryption context.
+    pub fn session_key_share(
+        &self,
+        context: &EncryptionContext,
+    ) -> Result<SessionKeyShare, ProtocolError<T::Error>> {
+        match self.request(&Request::SessionKeyShare {
+            context: context.clone(),
+        })? {
+            Response::SessionKeyShare(x) => Ok(x),
+            _ => Err(ProtocolError::UnexpectedResponse),
+        }
+    }
+    /// Request a [`VerifiableSessionKeyShare`] for an encryption context.
+    pub fn verifiable_session_key_share(
+        &self,
+        context: &EncryptionContext,
+    ) -> Result<VerifiableSessionKeyShare, ProtocolError<T::Error>> {
+        match self.request(&Request::VerifiableSessionKeyShare {
+            context: context.clone(),
+        })? {
+            Response::VerifiableSessionKeyShare(x) => Ok(*x),
+            _ => Err(ProtocolError::UnexpectedResponse),
+        }
+    }
+    /// Request a [`SessionKeyShare`] for the session key for pseudonyms for an encryption context.
+    #[cfg(feature = "separate-session-keys")]
+    pub fn pseudonym_session_key_share(
+        &self,
+        context: &EncryptionContext,
+    ) -> Result<SessionKeyShare, ProtocolError<T::Error>> {
+        match self.request(&Request::PseudonymSessionKeyShare {
+            context: context.clone(),
+        })? {
+            Response::SessionKeyShare(x) => Ok(x),
+            _ => Err(ProtocolError::UnexpectedResponse),
+        }
+    }
+    /// Request a [`VerifiableSessionKeyShare`] for the session key for pseudonyms for an
+    /// encryption context.
+    #[cfg(feature = "separate-session-keys")]
+    pub fn verifiable_pseudonym_session_key_share(
+        &self,
+        context: &EncryptionContext,
+    ) -> Result<VerifiableSessionKeyShare, ProtocolError<T::Error>> {
+        match self.request(&Request::VerifiablePseudonymSessionKeyShare {
+            context: context.clone(),
+        })? {
+            Response::VerifiableSessionKeyShare(x) => Ok(*x),
+            _ => Err(ProtocolError::UnexpectedResponse),
+        }
+    }
+    /// Request the [`PseudonymizationInfo`] between contexts.
+    pub fn pseudonymization_info(
+        &self,
+        from_pseudo: &PseudonymizationContext,
+        to_pseudo: &PseudonymizationContext,
+        from_enc: &EncryptionContext,
+        to_enc: &EncryptionContext,
+    ) -> Result<PseudonymizationInfo, ProtocolError<T::Error>> {
+        match self.request(&Request::PseudonymizationInfo {
+            from_pseudo: from_pseudo.clone(),
+            to_pseudo: to_pseudo.clone(),
+            from_enc: from_enc.clone(),
+            to_enc: to_enc.clone(),
+        })? {
+            Response::PseudonymizationInfo(x) => Ok(x),
+            _ => Err(ProtocolError::UnexpectedResponse),
+        }
+    }
+    /// Request the [`RekeyInfo`] between encryption contexts.
+    pub fn rekey_info(
+        &self,
+        from_enc: &EncryptionContext,
+        to_enc: &EncryptionContext,
+    ) -> Result<RekeyInfo, ProtocolError<T::Error>> {
+        match self.request(&Request::RekeyInfo {
+            from_enc: from_enc.clone(),
+            to_enc: to_enc.clone(),
+        })? {
+            Response::RekeyInfo(x) => Ok(x),
+            _ => Err(ProtocolError::UnexpectedResponse),
+        }
+    }
+    /// Request the [`RekeyInfo`] to rekey encrypted pseudonyms between encryption contexts.
+    #[cfg(feature = "separate-session-keys")]
+    pub fn pseudonym_rekey_info(
+        &self,
+        from_enc: &EncryptionContext,
+        to_enc: &EncryptionContext,
+    ) -> Result<RekeyInfo, ProtocolError<T::Error>> {
+        match self.request(&Request::PseudonymRekeyInfo {
+            from_enc: from_enc.clone(),
+            to_enc: to_enc.clone(),
+        })? {
+            Response::RekeyInfo(x) => Ok(x),
+            _ => Err(ProtocolError::UnexpectedResponse),
+        }
+    }
+}
+
+impl<T: Transport> Transcryptor for RemoteTranscryptor<T> {
+    type Error = ProtocolError<T::Error>;
+
+    fn pseudonymize(
+        &self,
+        encrypted: &EncryptedPseudonym,
+        from_pseudo: &PseudonymizationContext,
+        to_pseudo: &PseudonymizationContext,
+        from_enc: &EncryptionContext,
+        to_enc: &EncryptionContext,
+    ) -> Result<EncryptedPseudonym, Self::Error> {
+        match self.request(&Request::Pseudonymize {
+            encrypted: *encrypted,
+            from_pseudo: from_pseudo.clone(),
+            to_pseudo: to_pseudo.clone(),
+            from_enc: from_enc.clone(),
+            to_enc: to_enc.clone(),
+        })? {
+            Response::Pseudonymized(x) => Ok(x),
+            _ => Err(ProtocolError::UnexpectedResponse),
+        }
+    }
+
+    fn rekey(
+        &self,
+        encrypted: &EncryptedDataPoint,
+        from_enc: &EncryptionContext,
+        to_enc: &EncryptionContext,
+    ) -> Result<EncryptedDataPoint, Self::Error> {
+        match self.request(&Request::Rekey {
+            encrypted: *encrypted,
+            from_enc: from_enc.clone(),
+            to_enc: to_enc.clone(),
+        })? {
+            Response::Rekeyed(x) => Ok(x),
+            _ => Err(ProtocolError::UnexpectedResponse),
+        }
+    }
+
+    fn transcrypt<E: Encrypted>(
+        &self,
+        encrypted: &E,
+        from_pseudo: &PseudonymizationContext,
+        to_pseudo: &PseudonymizationContext,
+        from_enc: &EncryptionContext,
+        to_enc: &EncryptionContext,
+    ) -> Result<E, Self::Error> {
+        let value = *encrypted.value();
+        if E::IS_PSEUDONYM {
+            let result = self.pseudonymize(
+                &EncryptedPseudonym::from_value(value),
+                from_pseudo,
+                to_pseudo,
+                from_enc,
+                to_enc,
+            )?;
+            Ok(E::from_value(*result.value()))
+        } else {
+            let result = self.rekey(&EncryptedDataPoint::from_value(value), from_enc, to_enc)?;
+            Ok(E::from_value(*result.value()))
+        }
+    }
+
+    /// The batch is shuffled by the remote transcryptor; the given random number generator is not
+    /// used.
+    fn pseudonymize_batch<R: RngCore + CryptoRng>(
+        &self,
+        encrypted: &mut [EncryptedPseudonym],
+        from_pseudo: &PseudonymizationContext,
+        to_pseudo: &PseudonymizationContext,
+        from_enc: &EncryptionContext,
+        to_enc: &EncryptionContext,
+        _rng: &mut R,
+    ) -> Result<Box<[EncryptedPseudonym]>, Self::Error> {
+        match self.request(&Request::PseudonymizeBatch {
+            encrypted: encrypted.to_vec(),
+            from_pseudo: from_pseudo.clone(),
+            to_pseudo: to_pseudo.clone(),
+            from_enc: from_enc.clone(),
+            to_enc: to_enc.clone(),
+        })? {
+            Response::PseudonymizedBatch(x) => Ok(x.into_boxed_slice()),
+            _ => Err(ProtocolError::UnexpectedResponse),
+        }
+    }
+
+    /// The batch is shuffled by the remote transcryptor; the given random number generator is not
+    /// used.
+    fn rekey_batch<R: RngCore + CryptoRng>(
+        &self,
+        encrypted: &mut [EncryptedDataPoint],
+        from_enc: &EncryptionContext,
+        to_enc: &EncryptionContext,
+        _rng: &mut R,
+    ) -> Result<Box<[EncryptedDataPoint]>, Self::Error> {
+        match self.request(&Request::RekeyBatch {
+            encrypted: encrypted.to_vec(),
+            from_enc: from_enc.clone(),
+            to_enc: to_enc.clone(),
+        })? {
+            Response::RekeyedBatch(x) => Ok(x.into_boxed_slice()),
+            _ => Err(ProtocolError::UnexpectedResponse),
+        }
+    }
+
+    /// The batch is shuffled by the remote transcryptor; the given random number generator is not
+    /// used.
+    fn transcrypt_batch<R: RngCore + CryptoRng>(
+        &self,
+        encrypted: &mut Box<[EncryptedEntityDataPair]>,
+        from_pseudo: &PseudonymizationContext,
+        to_pseudo: &PseudonymizationContext,
+        from_enc: &EncryptionContext,
+        to_enc: &EncryptionContext,
+        _rng: &mut R,
+    ) -> Result<Box<[EncryptedEntityDataPair]>, Self::Error> {
+        match self.request(&Request::TranscryptBatch {
+            encrypted: encrypted.to_vec(),
+            from_pseudo: from_pseudo.clone(),
+            to_pseudo: to_pseudo.clone(),
+            from_enc: from_enc.clone(),
+            to_enc: to_enc.clone(),
+        })? {
+            Response::TranscryptedBatch(x) => Ok(x.into_boxed_slice()),
+            _ => Err(ProtocolError::UnexpectedResponse),
+        }
+    }
+}
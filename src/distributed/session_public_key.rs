@@ -0,0 +1,121 @@
+//! Distributed derivation of the [`SessionPublicKey`] for any
+//! [`EncryptionContext`](crate::high_level::contexts::EncryptionContext), so that anyone can
+//! encrypt directly for someone else's session, without needing its session key shares.
+//!
+//! Starting from `BGSK * G` (see [`session_public_key_base`]), every transcryptor in turn
+//! multiplies the point by its rekey factor `k_i` and blinding factor `b_i` (see
+//! [`PEPSystem::session_public_key_step`](crate::distributed::systems::PEPSystem::session_public_key_step)),
+//! which results in `BGSK * k_1 * b_1 * ... * k_n * b_n * G`, the session public key.
+//! Every step is proven consistent with the [`BlindingCommitment`] of the transcryptor, and
+//! verified with [`verify_session_public_key_steps`].
+//!
+//! Like for [`VerifiableSessionKeyShare`]s, this does not prove that a transcryptor used the
+//! correct rekey factor: the rekey commitment `k_i * G` is chosen by the transcryptor itself and
+//! is not bound to anything published in advance. A step that does not use the committed blinding
+//! factor, or that does not use the same `k_i` for both the point and the rekey commitment, is
+//! detected, but a transcryptor that knows its blinding factor can use any other rekey factor and
+//! produce a step that verifies, resulting in a session public key that the owner of the session
+//! cannot decrypt for.
+//! Since the rekey commitment of a transcryptor is the same for every derivation for an encryption
+//! context, and equal to the one in its [`VerifiableSessionKeyShare`]s for that context, parties
+//! can detect an inconsistent transcryptor by comparing them.
+
+use crate::distributed::key_blinding::*;
+use crate::high_level::keys::*;
+use crate::internal::arithmetic::*;
+use crate::low_level::proofs::*;
+use rand_core::{CryptoRng, RngCore};
+use serde::{Deserialize, Serialize};
+
+/// A single step of the session public key derivation by one transcryptor.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub struct SessionPublicKeyStep {
+    /// Commitment `k * G` to the rekey factor, as claimed by the transcryptor.
+    pub rekey_commitment: GroupElement,
+    /// The previous point multiplied by `k`.
+    pub rekeyed: GroupElement,
+    /// The previous point multiplied by `k * b`.
+    pub point: GroupElement,
+    /// Proof that `rekeyed` is the previous point multiplied by `k`.
+    pub rekey_proof: DleqProof,
+    /// Proof that `point` is `rekeyed` multiplied by `b`.
+    pub blinding_proof: DleqProof,
+}
+
+/// Errors that can occur when verifying the session public key derivation.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum SessionPublicKeyError {
+    /// The number of steps does not match the number of blinding commitments.
+    WrongNumberOfSteps { expected: usize, received: usize },
+    /// The step of the transcryptor with this index is inconsistent with its blinding commitment
+    /// or its rekey commitment.
+    InvalidStep(usize),
+}
+
+/// The point `BGSK * G` from which the session public key derivation starts.
+pub fn session_public_key_base(blinded_global_secret_key: &BlindedGlobalSecretKey) -> GroupElement {
+    blinded_global_secret_key.0 * G
+}
+
+/// Perform a step of the session public key derivation with rekey factor `k` and blinding
+/// factor `b` on the point resulting from the previous step.
+pub fn make_session_public_key_step<R: RngCore + CryptoRng>(
+    previous: &GroupElement,
+    rekey_factor: &ScalarNonZero,
+    blinding_factor: &BlindingFactor,
+    rng: &mut R,
+) -> SessionPublicKeyStep {
+    let rekeyed = rekey_factor * previous;
+    SessionPublicKeyStep {
+        rekey_commitment: rekey_factor * G,
+        rekeyed,
+        point: blinding_factor.0 * rekeyed,
+        rekey_proof: prove_dleq(rekey_factor, &G, previous, rng),
+        blinding_proof: prove_dleq(&blinding_factor.0, &G, &rekeyed, rng),
+    }
+}
+
+/// Verify a single step of the session public key derivation against the blinding commitment of
+/// the transcryptor that performed it (but not that its rekey factor is the correct one).
+pub fn verify_session_public_key_step(
+    previous: &GroupElement,
+    step: &SessionPublicKeyStep,
+    blinding_commitment: &BlindingCommitment,
+) -> bool {
+    verify_dleq(
+        &step.rekey_proof,
+        &G,
+        &step.rekey_commitment,
+        previous,
+        &step.rekeyed,
+    ) && verify_dleq(
+        &step.blinding_proof,
+        &G,
+        &blinding_commitment.0,
+        &step.rekeyed,
+        &step.point,
+    )
+}
+
+/// Verify all steps of the session public key derivation (in the same order as the blinding
+/// commitments of the transcryptors), resulting in the session public key.
+pub fn verify_session_public_key_steps(
+    blinded_global_secret_key: &BlindedGlobalSecretKey,
+    blinding_commitments: &[BlindingCommitment],
+    steps: &[SessionPublicKeyStep],
+) -> Result<SessionPublicKey, SessionPublicKeyError> {
+    if steps.len() != blinding_commitments.len() {
+        return Err(SessionPublicKeyError::WrongNumberOfSteps {
+            expected: blinding_commitments.len(),
+            received: steps.len(),
+        });
+    }
+    let mut previous = session_public_key_base(blinded_global_secret_key);
+    for (i, (step, commitment)) in steps.iter().zip(blinding_commitments).enumerate() {
+        if !verify_session_public_key_step(&previous, step, commitment) {
+            return Err(SessionPublicKeyError::InvalidStep(i));
+        }
+        previous = step.point;
+    }
+    Ok(SessionPublicKey(previous))
+}
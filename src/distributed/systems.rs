@@ -1,20 +1,32 @@
 //! High-level [`PEPSystem`]s and [`PEPClient`]s.
 
+use crate::distributed::decryption::{make_verifiable_rekey, VerifiableRekey};
+use crate::distributed::factor_cache::FactorCache;
 use crate::distributed::key_blinding::*;
+use crate::distributed::session_public_key::{make_session_public_key_step, SessionPublicKeyStep};
+use crate::distributed::threshold::{
+    make_threshold_session_key, ThresholdConfig, ThresholdError, ThresholdSessionKeyShare,
+};
 use crate::high_level::contexts::*;
 use crate::high_level::data_types::*;
 use crate::high_level::keys::*;
 use crate::high_level::ops::*;
+#[cfg(feature = "separate-session-keys")]
+use crate::high_level::utils::make_pseudonym_rekey_factor;
 use crate::high_level::utils::make_rekey_factor;
+use crate::internal::arithmetic::{GroupElement, ScalarNonZero};
 use rand_core::{CryptoRng, RngCore};
+use serde::{Deserialize, Serialize};
 
 /// A PEP transcryptor system that can [pseudonymize] and [rekey] data, based on
 /// a pseudonymisation secret, a rekeying secret and a blinding factor.
+/// Optionally, derived factors can be kept in a [`FactorCache`] (see [`PEPSystem::with_factor_cache`]).
 #[derive(Clone)]
 pub struct PEPSystem {
     pub(crate) pseudonymisation_secret: PseudonymizationSecret,
     pub(crate) rekeying_secret: EncryptionSecret,
     blinding_factor: BlindingFactor,
+    factor_cache: Option<FactorCache>,
 }
 impl PEPSystem {
     /// Create a new PEP system with the given secrets and blinding factor.
@@ -27,12 +39,146 @@ impl PEPSystem {
             pseudonymisation_secret,
             rekeying_secret,
             blinding_factor,
+            factor_cache: None,
+        }
+    }
+    /// Enable a [`FactorCache`] holding at most `capacity` derived factors, so that factors for
+    /// frequently used contexts are not derived again for every call.
+    pub fn with_factor_cache(mut self, capacity: usize) -> Self {
+        self.factor_cache = Some(FactorCache::new(capacity));
+        self
+    }
+    /// The [`FactorCache`] of this system, if enabled.
+    /// Can be used to explicitly invalidate cached factors.
+    pub fn factor_cache(&self) -> Option<&FactorCache> {
+        self.factor_cache.as_ref()
+    }
+    /// Replace the blinding factor of this system, e.g. after a
+    /// [`BlindingRefresh`](crate::distributed::key_blinding::BlindingRefresh).
+    pub fn set_blinding_factor(&mut self, blinding_factor: BlindingFactor) {
+        self.blinding_factor = blinding_factor;
+    }
+    /// The public commitment to the blinding factor of this system, which clients use to verify
+    /// [`VerifiableSessionKeyShare`]s.
+    pub fn blinding_commitment(&self) -> BlindingCommitment {
+        make_blinding_commitment(&self.blinding_factor)
+    }
+    fn session_rekey_factor(&self, context: &EncryptionContext) -> ScalarNonZero {
+        match &self.factor_cache {
+            Some(cache) => cache.rekey_factor(&self.rekeying_secret, context).0 .0,
+            None => make_rekey_factor(&self.rekeying_secret, context).0,
+        }
+    }
+    #[cfg(feature = "separate-session-keys")]
+    fn pseudonym_session_rekey_factor(&self, context: &EncryptionContext) -> ScalarNonZero {
+        match &self.factor_cache {
+            Some(cache) => {
+                cache
+                    .pseudonym_rekey_factor(&self.rekeying_secret, context)
+                    .0
+                     .0
+            }
+            None => make_pseudonym_rekey_factor(&self.rekeying_secret, context).0,
         }
     }
     /// Generate a session key share for the given encryption context.
+    /// With the `separate-session-keys` feature enabled, this share is only used for the session
+    /// key for data points (see [`PEPSystem::pseudonym_session_key_share`]).
     pub fn session_key_share(&self, context: &EncryptionContext) -> SessionKeyShare {
-        let k = make_rekey_factor(&self.rekeying_secret, context);
-        make_session_key_share(&k.0, &self.blinding_factor)
+        make_session_key_share(&self.session_rekey_factor(context), &self.blinding_factor)
+    }
+    /// Generate a session key share for the given encryption context, with a proof that it is
+    /// consistent with the [blinding commitment](PEPSystem::blinding_commitment) of this system.
+    pub fn verifiable_session_key_share<R: RngCore + CryptoRng>(
+        &self,
+        context: &EncryptionContext,
+        rng: &mut R,
+    ) -> VerifiableSessionKeyShare {
+        make_verifiable_session_key_share(
+            &self.session_rekey_factor(context),
+            &self.blinding_factor,
+            rng,
+        )
+    }
+    /// Generate a session key share for the session key for pseudonyms for the given encryption
+    /// context.
+    #[cfg(feature = "separate-session-keys")]
+    pub fn pseudonym_session_key_share(&self, context: &EncryptionContext) -> SessionKeyShare {
+        make_session_key_share(
+            &self.pseudonym_session_rekey_factor(context),
+            &self.blinding_factor,
+        )
+    }
+    /// Generate a session key share for the session key for pseudonyms for the given encryption
+    /// context, with a proof that it is consistent with the
+    /// [blinding commitment](PEPSystem::blinding_commitment) of this system.
+    #[cfg(feature = "separate-session-keys")]
+    pub fn verifiable_pseudonym_session_key_share<R: RngCore + CryptoRng>(
+        &self,
+        context: &EncryptionContext,
+        rng: &mut R,
+    ) -> VerifiableSessionKeyShare {
+        make_verifiable_session_key_share(
+            &self.pseudonym_session_rekey_factor(context),
+            &self.blinding_factor,
+            rng,
+        )
+    }
+    /// Perform this system's step of the
+    /// [distributed session public key derivation](crate::distributed::session_public_key) for the
+    /// given encryption context, on the point resulting from the previous system.
+    /// With the `separate-session-keys` feature enabled, this derives the session public key for
+    /// data points (see [`PEPSystem::pseudonym_session_public_key_step`]).
+    pub fn session_public_key_step<R: RngCore + CryptoRng>(
+        &self,
+        context: &EncryptionContext,
+        previous: &GroupElement,
+        rng: &mut R,
+    ) -> SessionPublicKeyStep {
+        make_session_public_key_step(
+            previous,
+            &self.session_rekey_factor(context),
+            &self.blinding_factor,
+            rng,
+        )
+    }
+    /// Perform this system's step of the
+    /// [distributed session public key derivation](crate::distributed::session_public_key) of the
+    /// session public key for pseudonyms for the given encryption context.
+    #[cfg(feature = "separate-session-keys")]
+    pub fn pseudonym_session_public_key_step<R: RngCore + CryptoRng>(
+        &self,
+        context: &EncryptionContext,
+        previous: &GroupElement,
+        rng: &mut R,
+    ) -> SessionPublicKeyStep {
+        make_session_public_key_step(
+            previous,
+            &self.pseudonym_session_rekey_factor(context),
+            &self.blinding_factor,
+            rng,
+        )
+    }
+    /// Rekey an encrypted message (encrypted with the global public key, or already rekeyed by
+    /// previous systems) towards the session of the given encryption context, with a proof that
+    /// the same rekey factor was used as in
+    /// [`verifiable_session_key_share`](PEPSystem::verifiable_session_key_share) (or its pseudonym
+    /// variant), for [distributed decryption](crate::distributed::decryption).
+    pub fn verifiable_rekey_from_global<E: Encrypted, R: RngCore + CryptoRng>(
+        &self,
+        encrypted: &E,
+        context: &EncryptionContext,
+        rng: &mut R,
+    ) -> VerifiableRekey<E> {
+        #[cfg(feature = "separate-session-keys")]
+        if E::IS_PSEUDONYM {
+            return make_verifiable_rekey(
+                encrypted,
+                &self.pseudonym_session_rekey_factor(context),
+                rng,
+            );
+        }
+        make_verifiable_rekey(encrypted, &self.session_rekey_factor(context), rng)
     }
     /// Generate a rekey info to rekey from a given [`EncryptionContext`] to another.
     pub fn rekey_info(
@@ -40,7 +186,36 @@ impl PEPSystem {
         from_enc: &EncryptionContext,
         to_enc: &EncryptionContext,
     ) -> RekeyInfo {
-        RekeyInfo::new(from_enc, to_enc, &self.rekeying_secret)
+        match &self.factor_cache {
+            Some(cache) => {
+                let (_, k_from_inv) = cache.rekey_factor(&self.rekeying_secret, from_enc);
+                let (k_to, _) = cache.rekey_factor(&self.rekeying_secret, to_enc);
+                RekeyInfo::from(k_from_inv.0 * k_to.0)
+            }
+            None => RekeyInfo::new(from_enc, to_enc, &self.rekeying_secret),
+        }
+    }
+    /// Generate a rekey info to rekey encrypted pseudonyms (without pseudonymizing them) from a
+    /// given [`EncryptionContext`] to another.
+    #[cfg(feature = "separate-session-keys")]
+    pub fn pseudonym_rekey_info(
+        &self,
+        from_enc: &EncryptionContext,
+        to_enc: &EncryptionContext,
+    ) -> RekeyInfo {
+        match &self.factor_cache {
+            Some(cache) => {
+                let (_, k_from_inv) = cache.pseudonym_rekey_factor(&self.rekeying_secret, from_enc);
+                let (k_to, _) = cache.pseudonym_rekey_factor(&self.rekeying_secret, to_enc);
+                RekeyInfo::from(k_from_inv.0 * k_to.0)
+            }
+            None => RekeyInfo::from(
+                make_pseudonym_rekey_factor(&self.rekeying_secret, from_enc)
+                    .0
+                    .invert()
+                    * make_pseudonym_rekey_factor(&self.rekeying_secret, to_enc).0,
+            ),
+        }
     }
     /// Generate a pseudonymization info to pseudonymize from a given [`PseudonymizationContext`]
     /// and [`EncryptionContext`] to another.
@@ -51,6 +226,17 @@ impl PEPSystem {
         from_enc: &EncryptionContext,
         to_enc: &EncryptionContext,
     ) -> PseudonymizationInfo {
+        if let Some(cache) = &self.factor_cache {
+            let (_, s_from_inv) =
+                cache.reshuffle_factor(&self.pseudonymisation_secret, from_pseudo);
+            let (s_to, _) = cache.reshuffle_factor(&self.pseudonymisation_secret, to_pseudo);
+            let (_, k_from_inv) = cache.pseudonym_rekey_factor(&self.rekeying_secret, from_enc);
+            let (k_to, _) = cache.pseudonym_rekey_factor(&self.rekeying_secret, to_enc);
+            return PseudonymizationInfo {
+                s: ReshuffleFactor::from(s_from_inv.0 * s_to.0),
+                k: RekeyFactor::from(k_from_inv.0 * k_to.0),
+            };
+        }
         PseudonymizationInfo::new(
             from_pseudo,
             to_pseudo,
@@ -60,6 +246,33 @@ impl PEPSystem {
             &self.rekeying_secret,
         )
     }
+    /// Generate a transcryption info to transcrypt from a given [`PseudonymizationContext`] and
+    /// [`EncryptionContext`] to another.
+    #[cfg(not(feature = "separate-session-keys"))]
+    pub fn transcryption_info(
+        &self,
+        from_pseudo: &PseudonymizationContext,
+        to_pseudo: &PseudonymizationContext,
+        from_enc: &EncryptionContext,
+        to_enc: &EncryptionContext,
+    ) -> TranscryptionInfo {
+        self.pseudonymization_info(from_pseudo, to_pseudo, from_enc, to_enc)
+    }
+    /// Generate a transcryption info to transcrypt from a given [`PseudonymizationContext`] and
+    /// [`EncryptionContext`] to another.
+    #[cfg(feature = "separate-session-keys")]
+    pub fn transcryption_info(
+        &self,
+        from_pseudo: &PseudonymizationContext,
+        to_pseudo: &PseudonymizationContext,
+        from_enc: &EncryptionContext,
+        to_enc: &EncryptionContext,
+    ) -> TranscryptionInfo {
+        TranscryptionInfo {
+            pseudonym: self.pseudonymization_info(from_pseudo, to_pseudo, from_enc, to_enc),
+            data: self.rekey_info(from_enc, to_enc),
+        }
+    }
     /// Rekey an [`EncryptedDataPoint`] from one encryption context to another, using [`RekeyInfo`].
     pub fn rekey(
         &self,
@@ -105,7 +318,7 @@ impl PEPSystem {
     pub fn transcrypt<E: Encrypted>(
         &self,
         encrypted: &E,
-        transcryption_info: &PseudonymizationInfo,
+        transcryption_info: &TranscryptionInfo,
     ) -> E {
         transcrypt(encrypted, transcryption_info)
     }
@@ -115,20 +328,101 @@ impl PEPSystem {
     pub fn transcrypt_batch<R: RngCore + CryptoRng>(
         &self,
         encrypted: &mut Box<[EncryptedEntityDataPair]>,
-        transcryption_info: &PseudonymizationInfo,
+        transcryption_info: &TranscryptionInfo,
         rng: &mut R,
     ) -> Box<[EncryptedEntityDataPair]> {
         transcrypt_batch(encrypted, transcryption_info, rng)
     }
 }
+
+/// The configuration of a [`PEPSystem`], e.g. as stored in a configuration file of a
+/// transcryptor.
+/// The secrets are used as raw bytes, and the blinding factor is hex encoded.
+/// The [`Debug`] output does not include the secrets and blinding factor.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct PEPSystemConfig {
+    pub pseudonymization_secret: String,
+    pub encryption_secret: String,
+    pub blinding_factor: BlindingFactor,
+    /// The capacity of the [`FactorCache`], if factors should be cached.
+    #[serde(default)]
+    pub factor_cache_capacity: Option<usize>,
+}
+impl std::fmt::Debug for PEPSystemConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PEPSystemConfig")
+            .field("pseudonymization_secret", &"<redacted>")
+            .field("encryption_secret", &"<redacted>")
+            .field("blinding_factor", &"<redacted>")
+            .field("factor_cache_capacity", &self.factor_cache_capacity)
+            .finish()
+    }
+}
+impl PEPSystemConfig {
+    /// Create the [`PEPSystem`] described by this configuration.
+    pub fn build(&self) -> PEPSystem {
+        let system = PEPSystem::new(
+            PseudonymizationSecret::from(self.pseudonymization_secret.as_bytes().to_vec()),
+            EncryptionSecret::from(self.encryption_secret.as_bytes().to_vec()),
+            self.blinding_factor,
+        );
+        match self.factor_cache_capacity {
+            Some(capacity) => system.with_factor_cache(capacity),
+            None => system,
+        }
+    }
+}
+
+/// Errors that can occur when verifying [`VerifiableSessionKeyShare`]s.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum SessionKeyShareError {
+    /// The number of shares does not match the number of blinding commitments.
+    WrongNumberOfShares { expected: usize, received: usize },
+    /// The share of the system with this index is not consistent with its blinding commitment.
+    /// A system that uses its committed blinding factor with a wrong rekey factor is not detected
+    /// (see [`VerifiableSessionKeyShare`]).
+    InvalidShare(usize),
+}
+
+fn verify_session_key_shares(
+    blinding_commitments: &[BlindingCommitment],
+    session_key_shares: &[VerifiableSessionKeyShare],
+) -> Result<Vec<SessionKeyShare>, SessionKeyShareError> {
+    if blinding_commitments.len() != session_key_shares.len() {
+        return Err(SessionKeyShareError::WrongNumberOfShares {
+            expected: blinding_commitments.len(),
+            received: session_key_shares.len(),
+        });
+    }
+    blinding_commitments
+        .iter()
+        .zip(session_key_shares)
+        .enumerate()
+        .map(|(i, (commitment, share))| {
+            if verify_session_key_share(share, commitment) {
+                Ok(share.share)
+            } else {
+                Err(SessionKeyShareError::InvalidShare(i))
+            }
+        })
+        .collect()
+}
+
 /// A PEP client that can encrypt and decrypt data, based on a session key pair.
+/// With the `separate-session-keys` feature enabled, the client has distinct session key pairs for
+/// pseudonyms and data points.
 #[derive(Clone)]
 pub struct PEPClient {
     pub session_public_key: SessionPublicKey,
     pub(crate) session_secret_key: SessionSecretKey,
+    #[cfg(feature = "separate-session-keys")]
+    pub pseudonym_session_public_key: SessionPublicKey,
+    #[cfg(feature = "separate-session-keys")]
+    pub(crate) pseudonym_session_secret_key: SessionSecretKey,
 }
 impl PEPClient {
     /// Create a new PEP client from the given session key shares.
+    #[cfg(not(feature = "separate-session-keys"))]
     pub fn new(
         blinded_global_private_key: BlindedGlobalSecretKey,
         session_key_shares: &[SessionKeyShare],
@@ -139,8 +433,104 @@ impl PEPClient {
             session_secret_key: secret,
         }
     }
+    /// Create a new PEP client from the given session key shares for data points and for
+    /// pseudonyms.
+    #[cfg(feature = "separate-session-keys")]
+    pub fn new(
+        blinded_global_private_key: BlindedGlobalSecretKey,
+        session_key_shares: &[SessionKeyShare],
+        pseudonym_session_key_shares: &[SessionKeyShare],
+    ) -> Self {
+        let (public, secret) = make_session_key(blinded_global_private_key, session_key_shares);
+        let (pseudonym_public, pseudonym_secret) =
+            make_session_key(blinded_global_private_key, pseudonym_session_key_shares);
+        Self {
+            session_public_key: public,
+            session_secret_key: secret,
+            pseudonym_session_public_key: pseudonym_public,
+            pseudonym_session_secret_key: pseudonym_secret,
+        }
+    }
+    /// Create a new PEP client from the given verifiable session key shares, after verifying them
+    /// against the [`BlindingCommitment`]s of the systems (in the same order).
+    /// Returns the index of the first system that provided a share inconsistent with its
+    /// commitment. This does not detect a system that uses a wrong rekey factor (see
+    /// [`VerifiableSessionKeyShare`]).
+    #[cfg(not(feature = "separate-session-keys"))]
+    pub fn new_verified(
+        blinded_global_private_key: BlindedGlobalSecretKey,
+        blinding_commitments: &[BlindingCommitment],
+        session_key_shares: &[VerifiableSessionKeyShare],
+    ) -> Result<Self, SessionKeyShareError> {
+        let shares = verify_session_key_shares(blinding_commitments, session_key_shares)?;
+        Ok(Self::new(blinded_global_private_key, &shares))
+    }
+    /// Create a new PEP client from the given verifiable session key shares for data points and
+    /// for pseudonyms, after verifying them against the [`BlindingCommitment`]s of the systems (in
+    /// the same order).
+    /// Returns the index of the first system that provided a share inconsistent with its
+    /// commitment. This does not detect a system that uses a wrong rekey factor (see
+    /// [`VerifiableSessionKeyShare`]).
+    #[cfg(feature = "separate-session-keys")]
+    pub fn new_verified(
+        blinded_global_private_key: BlindedGlobalSecretKey,
+        blinding_commitments: &[BlindingCommitment],
+        session_key_shares: &[VerifiableSessionKeyShare],
+        pseudonym_session_key_shares: &[VerifiableSessionKeyShare],
+    ) -> Result<Self, SessionKeyShareError> {
+        let shares = verify_session_key_shares(blinding_commitments, session_key_shares)?;
+        let pseudonym_shares =
+            verify_session_key_shares(blinding_commitments, pseudonym_session_key_shares)?;
+        Ok(Self::new(
+            blinded_global_private_key,
+            &shares,
+            &pseudonym_shares,
+        ))
+    }
+    /// Create a new PEP client from session key shares of (at least) *t* transcryptors in a
+    /// threshold system.
+    #[cfg(not(feature = "separate-session-keys"))]
+    pub fn new_threshold(
+        blinded_global_secret_key: BlindedGlobalSecretKey,
+        config: &ThresholdConfig,
+        session_key_shares: &[ThresholdSessionKeyShare],
+    ) -> Result<Self, ThresholdError> {
+        let (public, secret) =
+            make_threshold_session_key(blinded_global_secret_key, config, session_key_shares)?;
+        Ok(Self {
+            session_public_key: public,
+            session_secret_key: secret,
+        })
+    }
+    /// Create a new PEP client from session key shares for data points and for pseudonyms of (at
+    /// least) *t* transcryptors in a threshold system.
+    #[cfg(feature = "separate-session-keys")]
+    pub fn new_threshold(
+        blinded_global_secret_key: BlindedGlobalSecretKey,
+        config: &ThresholdConfig,
+        session_key_shares: &[ThresholdSessionKeyShare],
+        pseudonym_session_key_shares: &[ThresholdSessionKeyShare],
+    ) -> Result<Self, ThresholdError> {
+        let (public, secret) =
+            make_threshold_session_key(blinded_global_secret_key, config, session_key_shares)?;
+        let (pseudonym_public, pseudonym_secret) = make_threshold_session_key(
+            blinded_global_secret_key,
+            config,
+            pseudonym_session_key_shares,
+        )?;
+        Ok(Self {
+            session_public_key: public,
+            session_secret_key: secret,
+            pseudonym_session_public_key: pseudonym_public,
+            pseudonym_session_secret_key: pseudonym_secret,
+        })
+    }
     /// Decrypt an encrypted message.
     pub fn decrypt<E: Encrypted>(&self, encrypted: &E) -> E::UnencryptedType {
+        #[cfg(feature = "separate-session-keys")]
+        if E::IS_PSEUDONYM {
+            return decrypt(encrypted, &self.pseudonym_session_secret_key);
+        }
         decrypt(encrypted, &self.session_secret_key)
     }
     /// Encrypt a message with the session public key.
@@ -149,6 +539,10 @@ impl PEPClient {
         message: &E,
         rng: &mut R,
     ) -> E::EncryptedType {
+        #[cfg(feature = "separate-session-keys")]
+        if E::EncryptedType::IS_PSEUDONYM {
+            return encrypt(message, &(self.pseudonym_session_public_key), rng);
+        }
         encrypt(message, &(self.session_public_key), rng)
     }
 }
@@ -0,0 +1,431 @@
+//! Threshold (*t*-of-*n*) distributed trust, where any *t* out of *n* transcryptors suffice to
+//! provide session keys and perform transcryption, and the system remains secure as long as fewer
+//! than *t* transcryptors are corrupted.
+//!
+//! Shamir sharing with Lagrange interpolation (in the exponent) cannot be used here. Rekey and
+//! reshuffle factors are derived from contexts with HMAC and combined multiplicatively, and
+//! Lagrange interpolation would need every transcryptor to hold a Shamir share of every context's
+//! factor. Deriving such shares without interaction requires either a derivation that is linear
+//! in the secret (so that the factors of two contexts reveal the factor of any other context, and
+//! the session keys of two sessions reveal the global secret key), or one secret for every set of
+//! `n - t + 1` transcryptors (pseudorandom secret sharing), which is what is done here directly.
+//!
+//! We use *replicated* secret sharing: for every *share group* of `n - t + 1` transcryptors, a
+//! separate set of secrets and a [`BlindingFactor`] is generated, which is held by all members of
+//! that group.
+//! Every group acts as a regular n-PEP [`PEPSystem`], so session keys and transcryption work
+//! exactly like in the n-of-n case, with one contribution per group.
+//! Any *t* transcryptors are a member of every group, so together they can provide all
+//! contributions, while any *t - 1* transcryptors miss at least one group.
+//! The number of groups is `n` choose `t - 1`, so this is only intended for small consortia:
+//! configurations with more than [`MAX_SHARE_GROUPS`] groups are rejected.
+
+use crate::distributed::key_blinding::*;
+use crate::distributed::systems::PEPSystem;
+use crate::high_level::contexts::*;
+use crate::high_level::data_types::*;
+use crate::high_level::keys::*;
+use crate::high_level::ops::*;
+use crate::internal::arithmetic::ScalarNonZero;
+use rand_core::{CryptoRng, RngCore};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// The maximum number of share groups of a [`ThresholdConfig`], which bounds the number of secrets
+/// held by every transcryptor and the number of session key shares a client combines.
+pub const MAX_SHARE_GROUPS: usize = 1024;
+
+/// Errors that can occur when combining contributions of a subset of the transcryptors.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum ThresholdError {
+    /// No contributions were provided for these share groups, i.e. fewer than *t* transcryptors
+    /// were available.
+    MissingShareGroups(Vec<usize>),
+    /// Members of this share group provided different contributions, so at least one of them
+    /// misbehaves.
+    InconsistentShares(usize),
+    /// This transcryptor is not a member of this share group.
+    NotAMember(usize),
+    /// This share group does not exist in the configuration.
+    UnknownShareGroup(usize),
+}
+
+/// The configuration of a *t*-of-*n* threshold system.
+/// Deserialization checks the configuration in the same way as [`ThresholdConfig::new`].
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(try_from = "ThresholdParameters")]
+pub struct ThresholdConfig {
+    threshold: usize,
+    transcryptors: usize,
+}
+
+/// The unchecked fields of a serialized [`ThresholdConfig`].
+#[derive(Deserialize)]
+struct ThresholdParameters {
+    threshold: usize,
+    transcryptors: usize,
+}
+
+impl TryFrom<ThresholdParameters> for ThresholdConfig {
+    type Error = String;
+
+    fn try_from(parameters: ThresholdParameters) -> Result<Self, Self::Error> {
+        Self::new(parameters.threshold, parameters.transcryptors).ok_or_else(|| {
+            format!(
+                "invalid threshold configuration: {} of {} transcryptors",
+                parameters.threshold, parameters.transcryptors
+            )
+        })
+    }
+}
+
+impl ThresholdConfig {
+    /// Create a configuration for `threshold`-of-`transcryptors`.
+    /// Returns `None` unless `1 <= threshold <= transcryptors`, or if the configuration would have
+    /// more than [`MAX_SHARE_GROUPS`] share groups.
+    pub fn new(threshold: usize, transcryptors: usize) -> Option<Self> {
+        if threshold == 0 || threshold > transcryptors {
+            return None;
+        }
+        // n choose (t - 1), computed incrementally so that it stops before it can overflow.
+        let mut groups = 1usize;
+        for i in 0..threshold - 1 {
+            groups = groups * (transcryptors - i) / (i + 1);
+            if groups > MAX_SHARE_GROUPS {
+                return None;
+            }
+        }
+        Some(Self {
+            threshold,
+            transcryptors,
+        })
+    }
+    /// The number of transcryptors (*t*) needed to create session keys and transcrypt.
+    pub fn threshold(&self) -> usize {
+        self.threshold
+    }
+    /// The total number of transcryptors (*n*).
+    pub fn transcryptors(&self) -> usize {
+        self.transcryptors
+    }
+    /// The share groups of this configuration, in a fixed order: all sets of `n - t + 1`
+    /// transcryptors (identified by their index in `0..n`).
+    pub fn share_groups(&self) -> Vec<Vec<usize>> {
+        let size = self.transcryptors - self.threshold + 1;
+        let mut groups = vec![];
+        let mut group: Vec<usize> = (0..size).collect();
+        loop {
+            groups.push(group.clone());
+            // Advance to the next combination in lexicographic order
+            let mut i = size;
+            while i > 0 && group[i - 1] == self.transcryptors - size + i - 1 {
+                i -= 1;
+            }
+            if i == 0 {
+                return groups;
+            }
+            group[i - 1] += 1;
+            for j in i..size {
+                group[j] = group[j - 1] + 1;
+            }
+        }
+    }
+    /// The indices of the share groups that transcryptor `id` is a member of.
+    pub fn share_groups_of(&self, id: usize) -> Vec<usize> {
+        self.share_groups()
+            .iter()
+            .enumerate()
+            .filter(|(_, members)| members.contains(&id))
+            .map(|(group, _)| group)
+            .collect()
+    }
+    /// Assign every share group to one of the `available` transcryptors, so that they can
+    /// transcrypt together.
+    /// Every share group is assigned to its first member that is available.
+    /// Returns for each available transcryptor the share groups it should apply (possibly none).
+    pub fn assign_share_groups(
+        &self,
+        available: &[usize],
+    ) -> Result<BTreeMap<usize, Vec<usize>>, ThresholdError> {
+        let mut assignment: BTreeMap<usize, Vec<usize>> =
+            available.iter().map(|id| (*id, vec![])).collect();
+        let mut missing = vec![];
+        for (group, members) in self.share_groups().iter().enumerate() {
+            match members.iter().find(|id| assignment.contains_key(id)) {
+                Some(id) => assignment.get_mut(id).unwrap().push(group),
+                None => missing.push(group),
+            }
+        }
+        if !missing.is_empty() {
+            return Err(ThresholdError::MissingShareGroups(missing));
+        }
+        Ok(assignment)
+    }
+}
+
+/// The secrets of a single share group, which should securely be transferred to all its members.
+#[derive(Clone, Debug)]
+pub struct ShareGroupSecrets {
+    pub group: usize,
+    pub pseudonymisation_secret: PseudonymizationSecret,
+    pub rekeying_secret: EncryptionSecret,
+    pub blinding_factor: BlindingFactor,
+}
+
+/// A [`SessionKeyShare`] provided on behalf of a share group.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub struct ThresholdSessionKeyShare {
+    pub group: usize,
+    pub share: SessionKeyShare,
+}
+
+/// Setup a *t*-of-*n* threshold system with a global public key, a blinded global secret key and
+/// for every transcryptor the secrets of the share groups it is a member of.
+/// The secrets should securely be transferred to the transcryptors ([`ThresholdPEPSystem`]s), the
+/// global public key and blinded global secret key can be publicly shared with anyone and are
+/// required by [`PEPClient`]s.
+/// Returns `None` if the configuration is invalid.
+pub fn make_threshold_global_keys<R: RngCore + CryptoRng>(
+    threshold: usize,
+    n: usize,
+    rng: &mut R,
+) -> Option<(
+    GlobalPublicKey,
+    BlindedGlobalSecretKey,
+    Vec<Vec<ShareGroupSecrets>>,
+)> {
+    let config = ThresholdConfig::new(threshold, n)?;
+    let groups = config.share_groups();
+    let (pk, bsk, blinding_factors) = make_distributed_global_keys(groups.len(), rng);
+    let group_secrets: Vec<ShareGroupSecrets> = blinding_factors
+        .into_iter()
+        .enumerate()
+        .map(|(group, blinding_factor)| ShareGroupSecrets {
+            group,
+            pseudonymisation_secret: PseudonymizationSecret::from(random_secret(rng)),
+            rekeying_secret: EncryptionSecret::from(random_secret(rng)),
+            blinding_factor,
+        })
+        .collect();
+    let secrets = (0..n)
+        .map(|id| {
+            config
+                .share_groups_of(id)
+                .iter()
+                .map(|group| group_secrets[*group].clone())
+                .collect()
+        })
+        .collect();
+    Some((pk, bsk, secrets))
+}
+
+fn random_secret<R: RngCore + CryptoRng>(rng: &mut R) -> Vec<u8> {
+    let mut secret = vec![0u8; 64];
+    rng.fill_bytes(&mut secret);
+    secret
+}
+
+/// Combine [`ThresholdSessionKeyShare`]s from (at least) *t* transcryptors with the
+/// [`BlindedGlobalSecretKey`] into a session key pair.
+/// If multiple shares for the same group are provided, they must be equal, and all shares must be
+/// for a share group of the configuration.
+pub fn make_threshold_session_key(
+    blinded_global_secret_key: BlindedGlobalSecretKey,
+    config: &ThresholdConfig,
+    session_key_shares: &[ThresholdSessionKeyShare],
+) -> Result<(SessionPublicKey, SessionSecretKey), ThresholdError> {
+    let groups = config.share_groups().len();
+    let mut per_group: BTreeMap<usize, SessionKeyShare> = BTreeMap::new();
+    for share in session_key_shares {
+        if share.group >= groups {
+            return Err(ThresholdError::UnknownShareGroup(share.group));
+        }
+        match per_group.get(&share.group) {
+            Some(existing) if *existing != share.share => {
+                return Err(ThresholdError::InconsistentShares(share.group))
+            }
+            _ => {
+                per_group.insert(share.group, share.share);
+            }
+        }
+    }
+    let missing: Vec<usize> = (0..groups)
+        .filter(|group| !per_group.contains_key(group))
+        .collect();
+    if !missing.is_empty() {
+        return Err(ThresholdError::MissingShareGroups(missing));
+    }
+    let shares: Vec<SessionKeyShare> = per_group.into_values().collect();
+    Ok(make_session_key(blinded_global_secret_key, &shares))
+}
+
+/// A PEP transcryptor in a *t*-of-*n* threshold system, which acts as a [`PEPSystem`] for every
+/// share group it is a member of.
+#[derive(Clone)]
+pub struct ThresholdPEPSystem {
+    id: usize,
+    config: ThresholdConfig,
+    groups: BTreeMap<usize, PEPSystem>,
+}
+
+impl ThresholdPEPSystem {
+    /// Create a new threshold PEP system for transcryptor `id`, with the secrets of its share groups.
+    pub fn new(id: usize, config: ThresholdConfig, secrets: Vec<ShareGroupSecrets>) -> Self {
+        let groups = secrets
+            .into_iter()
+            .map(|secrets| {
+                (
+                    secrets.group,
+                    PEPSystem::new(
+                        secrets.pseudonymisation_secret,
+                        secrets.rekeying_secret,
+                        secrets.blinding_factor,
+                    ),
+                )
+            })
+            .collect();
+        Self { id, config, groups }
+    }
+    /// The index of this transcryptor.
+    pub fn id(&self) -> usize {
+        self.id
+    }
+    /// The configuration of the threshold system.
+    pub fn config(&self) -> &ThresholdConfig {
+        &self.config
+    }
+    /// The share groups this transcryptor holds the secrets of.
+    pub fn share_groups(&self) -> Vec<usize> {
+        self.groups.keys().copied().collect()
+    }
+    /// Generate a session key share for every share group this transcryptor is a member of, for
+    /// the given encryption context.
+    pub fn session_key_shares(&self, context: &EncryptionContext) -> Vec<ThresholdSessionKeyShare> {
+        self.groups
+            .iter()
+            .map(|(group, system)| ThresholdSessionKeyShare {
+                group: *group,
+                share: system.session_key_share(context),
+            })
+            .collect()
+    }
+    /// Generate a session key share for the session key for pseudonyms for every share group this
+    /// transcryptor is a member of, for the given encryption context.
+    #[cfg(feature = "separate-session-keys")]
+    pub fn pseudonym_session_key_shares(
+        &self,
+        context: &EncryptionContext,
+    ) -> Vec<ThresholdSessionKeyShare> {
+        self.groups
+            .iter()
+            .map(|(group, system)| ThresholdSessionKeyShare {
+                group: *group,
+                share: system.pseudonym_session_key_share(context),
+            })
+            .collect()
+    }
+    fn systems(&self, groups: &[usize]) -> Result<Vec<&PEPSystem>, ThresholdError> {
+        groups
+            .iter()
+            .map(|group| {
+                self.groups
+                    .get(group)
+                    .ok_or(ThresholdError::NotAMember(*group))
+            })
+            .collect()
+    }
+    /// Generate a rekey info to rekey from a given [`EncryptionContext`] to another, combining the
+    /// contributions of the given share groups (see [`ThresholdConfig::assign_share_groups`]).
+    pub fn rekey_info(
+        &self,
+        groups: &[usize],
+        from_enc: &EncryptionContext,
+        to_enc: &EncryptionContext,
+    ) -> Result<RekeyInfo, ThresholdError> {
+        Ok(self
+            .systems(groups)?
+            .iter()
+            .map(|system| system.rekey_info(from_enc, to_enc))
+            .fold(RekeyInfo::from(ScalarNonZero::one()), |acc, x| {
+                RekeyInfo::from(acc.0 * x.0)
+            }))
+    }
+    /// Generate a pseudonymization info to pseudonymize from a given [`PseudonymizationContext`]
+    /// and [`EncryptionContext`] to another, combining the contributions of the given share groups
+    /// (see [`ThresholdConfig::assign_share_groups`]).
+    pub fn pseudonymization_info(
+        &self,
+        groups: &[usize],
+        from_pseudo: &PseudonymizationContext,
+        to_pseudo: &PseudonymizationContext,
+        from_enc: &EncryptionContext,
+        to_enc: &EncryptionContext,
+    ) -> Result<PseudonymizationInfo, ThresholdError> {
+        let one = ScalarNonZero::one();
+        Ok(self
+            .systems(groups)?
+            .iter()
+            .map(|system| system.pseudonymization_info(from_pseudo, to_pseudo, from_enc, to_enc))
+            .fold(
+                PseudonymizationInfo {
+                    s: ReshuffleFactor::from(one),
+                    k: RekeyFactor::from(one),
+                },
+                |acc, x| PseudonymizationInfo {
+                    s: ReshuffleFactor::from(acc.s.0 * x.s.0),
+                    k: RekeyFactor::from(acc.k.0 * x.k.0),
+                },
+            ))
+    }
+    /// Generate a transcryption info to transcrypt from a given [`PseudonymizationContext`] and
+    /// [`EncryptionContext`] to another, combining the contributions of the given share groups
+    /// (see [`ThresholdConfig::assign_share_groups`]).
+    pub fn transcryption_info(
+        &self,
+        groups: &[usize],
+        from_pseudo: &PseudonymizationContext,
+        to_pseudo: &PseudonymizationContext,
+        from_enc: &EncryptionContext,
+        to_enc: &EncryptionContext,
+    ) -> Result<TranscryptionInfo, ThresholdError> {
+        let pseudonymization_info =
+            self.pseudonymization_info(groups, from_pseudo, to_pseudo, from_enc, to_enc)?;
+        #[cfg(not(feature = "separate-session-keys"))]
+        {
+            Ok(pseudonymization_info)
+        }
+        #[cfg(feature = "separate-session-keys")]
+        {
+            Ok(TranscryptionInfo {
+                pseudonym: pseudonymization_info,
+                data: self.rekey_info(groups, from_enc, to_enc)?,
+            })
+        }
+    }
+    /// Rekey an [`EncryptedDataPoint`] from one encryption context to another, using [`RekeyInfo`].
+    pub fn rekey(
+        &self,
+        encrypted: &EncryptedDataPoint,
+        rekey_info: &RekeyInfo,
+    ) -> EncryptedDataPoint {
+        rekey(encrypted, rekey_info)
+    }
+    /// Pseudonymize an [`EncryptedPseudonym`] from one pseudonymization and encryption context to
+    /// another, using [`PseudonymizationInfo`].
+    pub fn pseudonymize(
+        &self,
+        encrypted: &EncryptedPseudonym,
+        pseudonymization_info: &PseudonymizationInfo,
+    ) -> EncryptedPseudonym {
+        pseudonymize(encrypted, pseudonymization_info)
+    }
+    /// Transcrypt (rekey or pseudonymize) an encrypted message from one pseudonymization and
+    /// encryption context to another, using [`TranscryptionInfo`].
+    pub fn transcrypt<E: Encrypted>(
+        &self,
+        encrypted: &E,
+        transcryption_info: &TranscryptionInfo,
+    ) -> E {
+        transcrypt(encrypted, transcryption_info)
+    }
+}
@@ -3,10 +3,14 @@
 //! transcryption between different contexts.
 
 use crate::high_level::keys::{EncryptionSecret, PseudonymizationSecret};
-use crate::high_level::utils::{make_pseudonymisation_factor, make_rekey_factor};
-use crate::internal::arithmetic::ScalarNonZero;
+use crate::high_level::utils::{
+    make_pseudonym_rekey_factor, make_pseudonymisation_factor, make_rekey_factor,
+};
+use crate::internal::arithmetic::{ScalarNonZero, ScalarTraits};
 use derive_more::{Deref, From};
-use serde::{Deserialize, Serialize};
+use serde::de::{Error, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt::Formatter;
 
 /// Contexts are described by simple strings of arbitrary length.
 pub type Context = String;
@@ -98,8 +102,75 @@ pub struct ReshuffleFactor(pub(crate) ScalarNonZero);
 #[derive(Copy, Clone, Eq, PartialEq, Debug, From)]
 pub struct RekeyFactor(pub(crate) ScalarNonZero);
 
+impl Serialize for ReshuffleFactor {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(self.0.encode_as_hex().as_str())
+    }
+}
+impl<'de> Deserialize<'de> for ReshuffleFactor {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct ReshuffleFactorVisitor;
+        impl Visitor<'_> for ReshuffleFactorVisitor {
+            type Value = ReshuffleFactor;
+            fn expecting(&self, formatter: &mut Formatter) -> std::fmt::Result {
+                formatter.write_str("a hex encoded string representing a ReshuffleFactor")
+            }
+
+            fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+            where
+                E: Error,
+            {
+                ScalarNonZero::decode_from_hex(v)
+                    .map(ReshuffleFactor)
+                    .ok_or(E::custom(format!("invalid hex encoded string: {}", v)))
+            }
+        }
+
+        deserializer.deserialize_str(ReshuffleFactorVisitor)
+    }
+}
+impl Serialize for RekeyFactor {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(self.0.encode_as_hex().as_str())
+    }
+}
+impl<'de> Deserialize<'de> for RekeyFactor {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct RekeyFactorVisitor;
+        impl Visitor<'_> for RekeyFactorVisitor {
+            type Value = RekeyFactor;
+            fn expecting(&self, formatter: &mut Formatter) -> std::fmt::Result {
+                formatter.write_str("a hex encoded string representing a RekeyFactor")
+            }
+
+            fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+            where
+                E: Error,
+            {
+                ScalarNonZero::decode_from_hex(v)
+                    .map(RekeyFactor)
+                    .ok_or(E::custom(format!("invalid hex encoded string: {}", v)))
+            }
+        }
+
+        deserializer.deserialize_str(RekeyFactorVisitor)
+    }
+}
+
 /// High-level type for the factors used to [`rsk`](crate::low_level::primitives::rsk) an [ElGamal](crate::low_level::elgamal::ElGamal) ciphertext.
-#[derive(Eq, PartialEq, Clone, Copy, Debug, From)]
+#[derive(Eq, PartialEq, Clone, Copy, Debug, From, Serialize, Deserialize)]
 pub struct RSKFactors {
     pub s: ReshuffleFactor,
     pub k: RekeyFactor,
@@ -108,6 +179,8 @@ pub struct RSKFactors {
 /// The information required to perform n-PEP pseudonymization from one encryption and pseudonymization
 /// context to another.
 /// The pseudonymization info consists of a reshuffle and rekey factor.
+/// With the `separate-session-keys` feature enabled, the rekey factor is derived for pseudonyms,
+/// and cannot be used to rekey data points.
 /// For efficiency, we do not actually use the [`rsk2`](crate::low_level::primitives::rsk2) operation, but instead use the regular [`rsk`](crate::low_level::primitives::rsk) operation
 /// with precomputed reshuffle and rekey factors, which is equivalent but more efficient.
 pub type PseudonymizationInfo = RSKFactors;
@@ -129,7 +202,9 @@ impl PseudonymizationInfo {
         let s_from = make_pseudonymisation_factor(pseudonymization_secret, from_pseudo_context);
         let s_to = make_pseudonymisation_factor(pseudonymization_secret, to_pseudo_context);
         let reshuffle_factor = ReshuffleFactor::from(s_from.0.invert() * s_to.0);
-        let rekey_factor = RekeyInfo::new(from_enc_context, to_enc_context, encryption_secret);
+        let k_from = make_pseudonym_rekey_factor(encryption_secret, from_enc_context);
+        let k_to = make_pseudonym_rekey_factor(encryption_secret, to_enc_context);
+        let rekey_factor = RekeyFactor::from(k_from.0.invert() * k_to.0);
         Self {
             s: reshuffle_factor,
             k: rekey_factor,
@@ -147,7 +222,7 @@ impl PseudonymizationInfo {
         let s_from = make_pseudonymisation_factor(pseudonymization_secret, from_pseudo_context);
         let s_to = make_pseudonymisation_factor(pseudonymization_secret, to_pseudo_context);
         let reshuffle_factor = ReshuffleFactor::from(s_from.0.invert() * s_to.0);
-        let rekey_factor = RekeyInfo::new_from_global(to_enc_context, encryption_secret);
+        let rekey_factor = make_pseudonym_rekey_factor(encryption_secret, to_enc_context);
         Self {
             s: reshuffle_factor,
             k: rekey_factor,
@@ -165,7 +240,11 @@ impl PseudonymizationInfo {
         let s_from = make_pseudonymisation_factor(pseudonymization_secret, from_pseudo_context);
         let s_to = make_pseudonymisation_factor(pseudonymization_secret, to_pseudo_context);
         let reshuffle_factor = ReshuffleFactor::from(s_from.0.invert() * s_to.0);
-        let rekey_factor = RekeyInfo::new_to_global(from_enc_context, encryption_secret);
+        let rekey_factor = RekeyFactor::from(
+            make_pseudonym_rekey_factor(encryption_secret, from_enc_context)
+                .0
+                .invert(),
+        );
         Self {
             s: reshuffle_factor,
             k: rekey_factor,
@@ -213,6 +292,7 @@ impl RekeyInfo {
         Self::from(self.0.invert())
     }
 }
+#[cfg(not(feature = "separate-session-keys"))]
 impl From<PseudonymizationInfo> for RekeyInfo {
     fn from(x: PseudonymizationInfo) -> Self {
         x.k
@@ -220,4 +300,100 @@ impl From<PseudonymizationInfo> for RekeyInfo {
 }
 
 /// Type alias for transcryption info, which is equivalent to pseudonymization info.
+#[cfg(not(feature = "separate-session-keys"))]
 pub type TranscryptionInfo = PseudonymizationInfo;
+
+/// The information required to transcrypt both pseudonyms and data points from one pseudonymization
+/// and encryption context to another.
+/// With the `separate-session-keys` feature enabled, pseudonyms and data points are rekeyed with
+/// different factors, so transcryption info consists of both a [`PseudonymizationInfo`] and a
+/// [`RekeyInfo`].
+#[cfg(feature = "separate-session-keys")]
+#[derive(Eq, PartialEq, Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct TranscryptionInfo {
+    pub pseudonym: PseudonymizationInfo,
+    pub data: RekeyInfo,
+}
+#[cfg(feature = "separate-session-keys")]
+impl TranscryptionInfo {
+    /// Compute the transcryption info given pseudonymization and encryption contexts and secrets.
+    pub fn new(
+        from_pseudo_context: &PseudonymizationContext,
+        to_pseudo_context: &PseudonymizationContext,
+        from_enc_context: &EncryptionContext,
+        to_enc_context: &EncryptionContext,
+        pseudonymization_secret: &PseudonymizationSecret,
+        encryption_secret: &EncryptionSecret,
+    ) -> Self {
+        Self {
+            pseudonym: PseudonymizationInfo::new(
+                from_pseudo_context,
+                to_pseudo_context,
+                from_enc_context,
+                to_enc_context,
+                pseudonymization_secret,
+                encryption_secret,
+            ),
+            data: RekeyInfo::new(from_enc_context, to_enc_context, encryption_secret),
+        }
+    }
+    /// Compute the transcryption info given pseudonymization and encryption contexts and secrets,
+    /// assuming transcryption from a global encryption context.
+    pub fn new_from_global(
+        from_pseudo_context: &PseudonymizationContext,
+        to_pseudo_context: &PseudonymizationContext,
+        to_enc_context: &EncryptionContext,
+        pseudonymization_secret: &PseudonymizationSecret,
+        encryption_secret: &EncryptionSecret,
+    ) -> Self {
+        Self {
+            pseudonym: PseudonymizationInfo::new_from_global(
+                from_pseudo_context,
+                to_pseudo_context,
+                to_enc_context,
+                pseudonymization_secret,
+                encryption_secret,
+            ),
+            data: RekeyInfo::new_from_global(to_enc_context, encryption_secret),
+        }
+    }
+    /// Compute the transcryption info given pseudonymization and encryption contexts and secrets,
+    /// assuming transcryption to a global encryption context.
+    pub fn new_to_global(
+        from_pseudo_context: &PseudonymizationContext,
+        to_pseudo_context: &PseudonymizationContext,
+        from_enc_context: &EncryptionContext,
+        pseudonymization_secret: &PseudonymizationSecret,
+        encryption_secret: &EncryptionSecret,
+    ) -> Self {
+        Self {
+            pseudonym: PseudonymizationInfo::new_to_global(
+                from_pseudo_context,
+                to_pseudo_context,
+                from_enc_context,
+                pseudonymization_secret,
+                encryption_secret,
+            ),
+            data: RekeyInfo::new_to_global(from_enc_context, encryption_secret),
+        }
+    }
+    /// Reverse the transcryption info (i.e., switch the direction of the transcryption).
+    pub fn reverse(&self) -> Self {
+        Self {
+            pseudonym: self.pseudonym.reverse(),
+            data: self.data.reverse(),
+        }
+    }
+}
+#[cfg(feature = "separate-session-keys")]
+impl From<TranscryptionInfo> for RekeyInfo {
+    fn from(x: TranscryptionInfo) -> Self {
+        x.data
+    }
+}
+#[cfg(feature = "separate-session-keys")]
+impl From<TranscryptionInfo> for PseudonymizationInfo {
+    fn from(x: TranscryptionInfo) -> Self {
+        x.pseudonym
+    }
+}
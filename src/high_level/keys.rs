@@ -2,6 +2,8 @@
 //! and pseudonymization and rekeying secrets to be used for transcryption.
 
 use crate::high_level::contexts::EncryptionContext;
+#[cfg(feature = "separate-session-keys")]
+use crate::high_level::utils::make_pseudonym_rekey_factor;
 use crate::high_level::utils::make_rekey_factor;
 use crate::internal::arithmetic::{GroupElement, ScalarNonZero, G};
 use derive_more::{Deref, From};
@@ -137,6 +139,8 @@ pub fn make_global_keys<R: RngCore + CryptoRng>(rng: &mut R) -> (GlobalPublicKey
 }
 
 /// Generate session keys from a [`GlobalSecretKey`], an [`EncryptionContext`] and an [`EncryptionSecret`].
+/// With the `separate-session-keys` feature enabled, these keys are only used for data points (see
+/// [`make_pseudonym_session_keys`]).
 pub fn make_session_keys(
     global: &GlobalSecretKey,
     context: &EncryptionContext,
@@ -147,3 +151,18 @@ pub fn make_session_keys(
     let pk = sk * G;
     (SessionPublicKey(pk), SessionSecretKey(sk))
 }
+
+/// Generate session keys for pseudonyms from a [`GlobalSecretKey`], an [`EncryptionContext`] and an
+/// [`EncryptionSecret`].
+/// These are distinct from the session keys for data points (see [`make_session_keys`]).
+#[cfg(feature = "separate-session-keys")]
+pub fn make_pseudonym_session_keys(
+    global: &GlobalSecretKey,
+    context: &EncryptionContext,
+    secret: &EncryptionSecret,
+) -> (SessionPublicKey, SessionSecretKey) {
+    let k = make_pseudonym_rekey_factor(secret, context);
+    let sk = k.0 * global.0;
+    let pk = sk * G;
+    (SessionPublicKey(pk), SessionSecretKey(sk))
+}
@@ -1,13 +1,16 @@
 //! High-level n-PEP operations for [encrypt]ion, [decrypt]ion and [transcrypt]ion, including batch
-//! transcryption and rerandomization.
+//! transcryption, multi-recipient encryption ([encrypt_multi]) and rerandomization.
 
 use crate::high_level::contexts::*;
 use crate::high_level::data_types::*;
 use crate::high_level::keys::*;
 use crate::internal::arithmetic::ScalarNonZero;
+use crate::low_level::elgamal::MultiElGamal;
 use crate::low_level::primitives::rsk;
 use rand::seq::SliceRandom;
 use rand_core::{CryptoRng, RngCore};
+use serde::{Deserialize, Serialize};
+use std::marker::PhantomData;
 
 /// Encrypt an [`Encryptable] message (a [Pseudonym] or [DataPoint]) using a [`SessionPublicKey`].
 pub fn encrypt<R: RngCore + CryptoRng, E: Encryptable>(
@@ -45,6 +48,69 @@ pub fn encrypt_global<R: RngCore + CryptoRng, E: Encryptable>(
     ))
 }
 
+/// A message encrypted for several recipients at once with [`encrypt_multi`], which can be split
+/// into an encrypted message per recipient.
+/// This is a convenience wrapper: it holds a separate ciphertext for every recipient, so it is not
+/// smaller than encrypting the message for every recipient separately.
+#[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(bound = "")]
+pub struct EncryptedBundle<E: Encrypted> {
+    pub value: MultiElGamal,
+    #[serde(skip)]
+    encrypted_type: PhantomData<E>,
+}
+
+impl<E: Encrypted> EncryptedBundle<E> {
+    /// Create from a [`MultiElGamal`] ciphertext.
+    pub fn from_value(value: MultiElGamal) -> Self {
+        Self {
+            value,
+            encrypted_type: PhantomData,
+        }
+    }
+    /// The number of recipients.
+    pub fn len(&self) -> usize {
+        self.value.len()
+    }
+    /// Whether there are no recipients.
+    pub fn is_empty(&self) -> bool {
+        self.value.is_empty()
+    }
+    /// The encrypted message for the recipient with this index.
+    pub fn get(&self, index: usize) -> Option<E> {
+        self.value.get(index).map(E::from_value)
+    }
+    /// Split into an encrypted message per recipient, in the order of the public keys.
+    pub fn split(&self) -> Vec<E> {
+        self.value.split().into_iter().map(E::from_value).collect()
+    }
+    /// Encode as a base64 string.
+    pub fn encode_as_base64(&self) -> String {
+        self.value.encode_as_base64()
+    }
+    /// Decode from a base64 string.
+    pub fn decode_from_base64(s: &str) -> Option<Self> {
+        MultiElGamal::decode_from_base64(s).map(Self::from_value)
+    }
+}
+
+/// Encrypt a message for several public keys at once (e.g. one or more [`SessionPublicKey`]s and
+/// the [`GlobalPublicKey`]), with fresh randomness for every public key.
+/// See [`encrypt_multi`](crate::low_level::elgamal::encrypt_multi) for why randomness is not shared,
+/// which also means that the result is no more compact than separate encryptions.
+pub fn encrypt_multi<R: RngCore + CryptoRng, E: Encryptable>(
+    message: &E,
+    public_keys: &[&dyn PublicKey],
+    rng: &mut R,
+) -> EncryptedBundle<E::EncryptedType> {
+    let gys: Vec<_> = public_keys.iter().map(|pk| *pk.value()).collect();
+    EncryptedBundle::from_value(crate::low_level::elgamal::encrypt_multi(
+        message.value(),
+        &gys,
+        rng,
+    ))
+}
+
 /// Decrypt using a global key (notice that for most applications, this key should be discarded and thus never exist).
 #[cfg(feature = "insecure-methods")]
 pub fn decrypt_global<E: Encrypted>(
@@ -125,17 +191,24 @@ pub fn rekey(encrypted: &EncryptedDataPoint, rekey_info: &RekeyInfo) -> Encrypte
 /// using [`TranscryptionInfo`].
 /// When an [`EncryptedPseudonym`] is transcrypted, the result is a pseudonymized pseudonym,
 /// and when an [`EncryptedDataPoint`] is transcrypted, the result is a rekeyed data point.
+/// With the `separate-session-keys` feature enabled, pseudonyms and data points are rekeyed with
+/// their own factor.
 pub fn transcrypt<E: Encrypted>(encrypted: &E, transcryption_info: &TranscryptionInfo) -> E {
+    #[cfg(not(feature = "separate-session-keys"))]
+    let (pseudonymization_info, rekey_info) = (transcryption_info, &transcryption_info.k);
+    #[cfg(feature = "separate-session-keys")]
+    let (pseudonymization_info, rekey_info) =
+        (&transcryption_info.pseudonym, &transcryption_info.data);
     if E::IS_PSEUDONYM {
         E::from_value(rsk(
             encrypted.value(),
-            &transcryption_info.s.0,
-            &transcryption_info.k.0,
+            &pseudonymization_info.s.0,
+            &pseudonymization_info.k.0,
         ))
     } else {
         E::from_value(crate::low_level::primitives::rekey(
             encrypted.value(),
-            &transcryption_info.k.0,
+            &rekey_info.0,
         ))
     }
 }
@@ -175,13 +248,17 @@ pub fn transcrypt_batch<R: RngCore + CryptoRng>(
     transcryption_info: &TranscryptionInfo,
     rng: &mut R,
 ) -> Box<[EncryptedEntityDataPair]> {
+    #[cfg(not(feature = "separate-session-keys"))]
+    let pseudonymization_info = transcryption_info;
+    #[cfg(feature = "separate-session-keys")]
+    let pseudonymization_info = &transcryption_info.pseudonym;
     encrypted.shuffle(rng); // Shuffle the order to avoid linking
     encrypted
         .iter_mut()
         .map(|(pseudonyms, data_points)| {
             let pseudonyms = pseudonyms
                 .iter()
-                .map(|x| pseudonymize(x, transcryption_info))
+                .map(|x| pseudonymize(x, pseudonymization_info))
                 .collect();
             let data_points = data_points
                 .iter()
@@ -22,6 +22,28 @@ pub fn make_rekey_factor(secret: &EncryptionSecret, context: &EncryptionContext)
     RekeyFactor::from(make_factor("rekey", &secret.0, context))
 }
 
+/// Derive a rekey factor for pseudonyms from a secret and a context.
+/// Without the `separate-session-keys` feature, this is the same as [`make_rekey_factor`].
+#[cfg(not(feature = "separate-session-keys"))]
+pub fn make_pseudonym_rekey_factor(
+    secret: &EncryptionSecret,
+    context: &EncryptionContext,
+) -> RekeyFactor {
+    make_rekey_factor(secret, context)
+}
+/// Derive a rekey factor for pseudonyms from a secret and a context, which is independent of the
+/// rekey factor for data points derived by [`make_rekey_factor`].
+#[cfg(all(
+    feature = "separate-session-keys",
+    not(feature = "legacy-pep-repo-compatible")
+))]
+pub fn make_pseudonym_rekey_factor(
+    secret: &EncryptionSecret,
+    context: &EncryptionContext,
+) -> RekeyFactor {
+    RekeyFactor::from(make_factor("rekey-pseudonym", &secret.0, context))
+}
+
 /// Derive a factor from a secret and a context.
 #[cfg(not(feature = "legacy-pep-repo-compatible"))]
 fn make_factor(typ: &str, secret: &Secret, context: &Context) -> ScalarNonZero {
@@ -58,6 +80,24 @@ pub fn make_rekey_factor(secret: &EncryptionSecret, context: &EncryptionContext)
     ))
 }
 
+/// Derive a rekey factor for pseudonyms from a secret and a context (using the legacy PEP repo
+/// method), which is independent of the rekey factor for data points derived by [`make_rekey_factor`].
+#[cfg(all(
+    feature = "separate-session-keys",
+    feature = "legacy-pep-repo-compatible"
+))]
+pub fn make_pseudonym_rekey_factor(
+    secret: &EncryptionSecret,
+    context: &EncryptionContext,
+) -> RekeyFactor {
+    RekeyFactor::from(make_factor(
+        &secret.0,
+        0x03,
+        context.audience_type,
+        &context.payload,
+    ))
+}
+
 /// Derive a factor from a secret and a context (using the legacy PEP repo method).
 #[cfg(feature = "legacy-pep-repo-compatible")]
 fn make_factor(secret: &Secret, typ: u32, audience_type: u32, context: &Context) -> ScalarNonZero {
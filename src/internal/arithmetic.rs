@@ -24,6 +24,7 @@ use rand_core::{CryptoRng, RngCore};
 use serde::de::{Error, Visitor};
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use sha2::Sha256;
+use zeroize::Zeroize;
 
 /// The base point constant so that a [ScalarNonZero]/[ScalarCanBeZero] s can be converted to a [GroupElement] by performing `s * G`.
 pub const G: GroupElement =
@@ -188,6 +189,13 @@ impl ScalarNonZero {
     pub fn invert(&self) -> Self {
         Self(self.0.invert())
     }
+
+    /// Overwrite the scalar with zeroes. Only to be used on values that are about to be dropped,
+    /// as the result is no longer a valid [ScalarNonZero]. Not public, so that callers cannot
+    /// break the invariant of the type.
+    pub(crate) fn zeroize(&mut self) {
+        self.0.zeroize();
+    }
 }
 
 /// Scalar, can be zero.
@@ -43,6 +43,7 @@ pub mod low_level {
     //! a more user-friendly and safer interface.
     pub mod elgamal;
     pub mod primitives;
+    pub mod proofs;
 }
 pub mod high_level {
     //! High-level API specifying [Pseudonyms](data_types::Pseudonym) and [DataPoints](data_types::DataPoint),
@@ -63,9 +64,21 @@ pub mod distributed {
     //! This module is intended for use cases where transcryption is performed by *n* parties and
     //! trust is distributed among them (i.e. no single party is trusted but the system remains secure
     //! as long as at least 1 party remains honest).
+    //! Alternatively, [threshold] trust can be used, where any *t* out of *n* parties suffice.
 
+    pub mod chain;
+    pub mod client;
+    pub mod decryption;
+    pub mod dkg;
+    pub mod factor_cache;
+    #[cfg(feature = "http-client")]
+    pub mod http;
     pub mod key_blinding;
+    pub mod migration;
+    pub mod protocol;
+    pub mod session_public_key;
     pub mod systems;
+    pub mod threshold;
 }
 #[cfg(feature = "wasm")]
 mod wasm {
@@ -89,4 +102,5 @@ mod tests {
     #[cfg(feature = "legacy-pep-repo-compatible")]
     mod legacy_pep_repo;
     mod primitives;
+    mod proofs;
 }
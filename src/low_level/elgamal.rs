@@ -1,4 +1,4 @@
-//! ElGamal [encrypt]ion and [decrypt]ion.
+//! ElGamal [encrypt]ion and [decrypt]ion, and multi-recipient encryption ([encrypt_multi]).
 
 use crate::internal::arithmetic::*;
 use base64::engine::general_purpose;
@@ -86,6 +86,85 @@ pub fn encrypt<R: RngCore + CryptoRng>(
     }
 }
 
+/// A multi-recipient ElGamal ciphertext: the encryption of a single message for several public
+/// keys, with fresh randomness (and thus a separate `r * G`) for every recipient.
+/// It can be [split](MultiElGamal::split) into a regular [`ElGamal`] ciphertext per recipient, and
+/// is exactly as large as those ciphertexts together.
+#[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub struct MultiElGamal {
+    pub ciphertexts: Vec<ElGamal>,
+}
+
+impl MultiElGamal {
+    /// The number of recipients.
+    pub fn len(&self) -> usize {
+        self.ciphertexts.len()
+    }
+    /// Whether there are no recipients.
+    pub fn is_empty(&self) -> bool {
+        self.ciphertexts.is_empty()
+    }
+    /// The [`ElGamal`] ciphertext for the recipient with this index.
+    pub fn get(&self, index: usize) -> Option<ElGamal> {
+        self.ciphertexts.get(index).copied()
+    }
+    /// Split into an [`ElGamal`] ciphertext per recipient, in the order of the public keys.
+    pub fn split(&self) -> Vec<ElGamal> {
+        self.ciphertexts.clone()
+    }
+    /// Encode as bytes: the encoded [`ElGamal`] ciphertext of every recipient, one after another.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut retval = Vec::with_capacity(self.len() * ELGAMAL_LENGTH);
+        for ciphertext in &self.ciphertexts {
+            retval.extend_from_slice(&ciphertext.encode());
+        }
+        retval
+    }
+    /// Decode from bytes, as encoded by [`MultiElGamal::encode`].
+    pub fn decode_from_slice(v: &[u8]) -> Option<Self> {
+        if v.is_empty() || !v.len().is_multiple_of(ELGAMAL_LENGTH) {
+            return None;
+        }
+        Some(Self {
+            ciphertexts: v
+                .chunks(ELGAMAL_LENGTH)
+                .map(ElGamal::decode_from_slice)
+                .collect::<Option<Vec<_>>>()?,
+        })
+    }
+    /// Encode as a base64 string.
+    pub fn encode_as_base64(&self) -> String {
+        general_purpose::URL_SAFE.encode(self.encode())
+    }
+    /// Decode from a base64 string.
+    pub fn decode_from_base64(s: &str) -> Option<Self> {
+        general_purpose::URL_SAFE
+            .decode(s)
+            .ok()
+            .and_then(|v| Self::decode_from_slice(&v))
+    }
+}
+
+/// Encrypt message [`GroupElement`] `gm` for all public keys `gys` at once, to a [`MultiElGamal`]
+/// ciphertext, which is the same as a separate [`encrypt`] for every public key.
+///
+/// Every recipient gets fresh randomness. Sharing `r * G` between recipients would only be secure
+/// for independent public keys, and the public keys in PEP are related: session keys are `k * y * G`
+/// and the global key is `y * G`, and the ratio `k` between two of them is a rekey factor that is
+/// known to transcryptors (and others). Given `gm + r * gy` and `gm + r * k * gy`, anyone who knows
+/// `k` can compute `r * gy` and thus `gm`, without any secret key.
+///
+/// Encryption may **not** be done with public key [`GroupElement::identity`], which is checked with an assertion.
+pub fn encrypt_multi<R: RngCore + CryptoRng>(
+    gm: &GroupElement,
+    gys: &[GroupElement],
+    rng: &mut R,
+) -> MultiElGamal {
+    MultiElGamal {
+        ciphertexts: gys.iter().map(|gy| encrypt(gm, gy, rng)).collect(),
+    }
+}
+
 /// Decrypt ElGamal ciphertext (encrypted using `y * G`) using secret key [`ScalarNonZero`] `y`.
 /// With the `elgamal3` feature, the secret key is checked against the public key used for encryption.
 pub fn decrypt(encrypted: &ElGamal, y: &ScalarNonZero) -> GroupElement {
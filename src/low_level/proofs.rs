@@ -0,0 +1,140 @@
+//! Non-interactive zero-knowledge [proofs](DleqProof) of equality of discrete logarithms
+//! (Chaum-Pedersen proofs, made non-interactive using the Fiat-Shamir heuristic).
+//! These are used to verify that a party applied the same secret scalar to different group elements,
+//! without revealing the scalar.
+
+use crate::internal::arithmetic::*;
+use base64::engine::general_purpose;
+use base64::Engine;
+use rand_core::{CryptoRng, RngCore};
+use serde::de::{Error, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use sha2::{Digest, Sha512};
+use std::fmt::Formatter;
+
+/// Length of an encoded [`DleqProof`] in bytes.
+pub const DLEQ_PROOF_LENGTH: usize = 96;
+
+/// A proof that `a = x * g` and `b = x * h` for the same (secret) scalar `x`.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct DleqProof {
+    /// Commitment `r * g` for a random nonce `r`.
+    pub rg: GroupElement,
+    /// Commitment `r * h` for the same random nonce `r`.
+    pub rh: GroupElement,
+    /// Response `r + c * x`, where `c` is the challenge.
+    pub s: ScalarCanBeZero,
+}
+
+fn challenge(
+    g: &GroupElement,
+    a: &GroupElement,
+    h: &GroupElement,
+    b: &GroupElement,
+    rg: &GroupElement,
+    rh: &GroupElement,
+) -> ScalarNonZero {
+    let mut hasher = Sha512::default();
+    hasher.update(b"libpep-dleq");
+    for x in [g, a, h, b, rg, rh] {
+        hasher.update(x.encode());
+    }
+    let mut bytes = [0u8; 64];
+    bytes.copy_from_slice(hasher.finalize().as_slice());
+    ScalarNonZero::decode_from_hash(&bytes)
+}
+
+/// Prove that `x * g` and `x * h` are multiplied by the same scalar `x`.
+pub fn prove_dleq<R: RngCore + CryptoRng>(
+    x: &ScalarNonZero,
+    g: &GroupElement,
+    h: &GroupElement,
+    rng: &mut R,
+) -> DleqProof {
+    let r = ScalarNonZero::random(rng);
+    let rg = r * g;
+    let rh = r * h;
+    let c = challenge(g, &(x * g), h, &(x * h), &rg, &rh);
+    let s = ScalarCanBeZero::from(r) + ScalarCanBeZero::from(c * x);
+    DleqProof { rg, rh, s }
+}
+
+/// Verify a [`DleqProof`] that `a = x * g` and `b = x * h` for the same scalar `x`.
+pub fn verify_dleq(
+    proof: &DleqProof,
+    g: &GroupElement,
+    a: &GroupElement,
+    h: &GroupElement,
+    b: &GroupElement,
+) -> bool {
+    let c = challenge(g, a, h, b, &proof.rg, &proof.rh);
+    proof.s * g == proof.rg + c * a && proof.s * h == proof.rh + c * b
+}
+
+impl DleqProof {
+    /// Encode the proof as a byte array.
+    pub fn encode(&self) -> [u8; DLEQ_PROOF_LENGTH] {
+        let mut retval = [0u8; DLEQ_PROOF_LENGTH];
+        retval[0..32].copy_from_slice(&self.rg.encode());
+        retval[32..64].copy_from_slice(&self.rh.encode());
+        retval[64..96].copy_from_slice(&self.s.encode());
+        retval
+    }
+    /// Decode a proof from a byte array.
+    pub fn decode(v: &[u8; DLEQ_PROOF_LENGTH]) -> Option<Self> {
+        Some(Self {
+            rg: GroupElement::decode_from_slice(&v[0..32])?,
+            rh: GroupElement::decode_from_slice(&v[32..64])?,
+            s: ScalarCanBeZero::decode_from_slice(&v[64..96])?,
+        })
+    }
+    /// Decode a proof from a slice of bytes.
+    pub fn decode_from_slice(v: &[u8]) -> Option<Self> {
+        let arr: &[u8; DLEQ_PROOF_LENGTH] = v.try_into().ok()?;
+        Self::decode(arr)
+    }
+    /// Encode the proof as a base64 string.
+    pub fn encode_as_base64(&self) -> String {
+        general_purpose::URL_SAFE.encode(self.encode())
+    }
+    /// Decode a proof from a base64 string.
+    pub fn decode_from_base64(s: &str) -> Option<Self> {
+        general_purpose::URL_SAFE
+            .decode(s)
+            .ok()
+            .and_then(|v| Self::decode_from_slice(&v))
+    }
+}
+
+impl Serialize for DleqProof {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(self.encode_as_base64().as_str())
+    }
+}
+impl<'de> Deserialize<'de> for DleqProof {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct DleqProofVisitor;
+        impl Visitor<'_> for DleqProofVisitor {
+            type Value = DleqProof;
+            fn expecting(&self, formatter: &mut Formatter) -> std::fmt::Result {
+                formatter.write_str("a base64 encoded string representing a DleqProof")
+            }
+
+            fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+            where
+                E: Error,
+            {
+                DleqProof::decode_from_base64(v)
+                    .ok_or(E::custom(format!("invalid base64 encoded string: {}", v)))
+            }
+        }
+
+        deserializer.deserialize_str(DleqProofVisitor)
+    }
+}
@@ -1,10 +1,28 @@
-use crate::distributed::key_blinding::{make_blinded_global_secret_key, BlindingFactor};
-use crate::distributed::systems::{PEPClient, PEPSystem};
+use crate::distributed::chain::*;
+use crate::distributed::client::*;
+use crate::distributed::decryption::*;
+use crate::distributed::dkg::*;
+use crate::distributed::key_blinding::{
+    make_blinded_global_secret_key, make_distributed_global_keys, make_session_key,
+    make_verifiable_session_key_share, run_blinding_refresh, run_transcryptor_join,
+    run_transcryptor_leave, verify_session_key_share, BlindedGlobalSecretKey, BlindingFactor,
+    BlindingRefresh, BlindingRefreshError, BlindingRefreshMode, BlindingRefreshShare, SafeScalar,
+};
+use crate::distributed::migration::*;
+use crate::distributed::protocol::*;
+use crate::distributed::session_public_key::*;
+use crate::distributed::systems::{
+    OfflinePEPClient, PEPClient, PEPSystem, PEPSystemConfig, SessionKeyShareError,
+};
+use crate::distributed::threshold::*;
 use crate::high_level::contexts::*;
 use crate::high_level::data_types::*;
 use crate::high_level::keys::*;
-use crate::internal::arithmetic::ScalarNonZero;
-use rand_core::OsRng;
+use crate::high_level::ops::{decrypt, encrypt, rekey, EncryptedEntityDataPair};
+use crate::internal::arithmetic::{ScalarNonZero, G};
+use rand_core::{CryptoRng, OsRng, RngCore};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
 
 #[test]
 fn n_pep() {
@@ -57,8 +75,26 @@ fn n_pep() {
         .collect::<Vec<_>>();
 
     // Create clients
+    #[cfg(not(feature = "separate-session-keys"))]
     let client_a = PEPClient::new(blinded_global_secret_key, &sks_a1);
+    #[cfg(not(feature = "separate-session-keys"))]
     let client_b = PEPClient::new(blinded_global_secret_key, &sks_b1);
+    #[cfg(feature = "separate-session-keys")]
+    let client_a = {
+        let pseudonym_sks_a1 = systems
+            .iter()
+            .map(|system| system.pseudonym_session_key_share(&ec_a1))
+            .collect::<Vec<_>>();
+        PEPClient::new(blinded_global_secret_key, &sks_a1, &pseudonym_sks_a1)
+    };
+    #[cfg(feature = "separate-session-keys")]
+    let client_b = {
+        let pseudonym_sks_b1 = systems
+            .iter()
+            .map(|system| system.pseudonym_session_key_share(&ec_b1))
+            .collect::<Vec<_>>();
+        PEPClient::new(blinded_global_secret_key, &sks_b1, &pseudonym_sks_b1)
+    };
 
     // Session walkthrough
     let pseudonym = Pseudonym::random(rng);
@@ -68,8 +104,8 @@ fn n_pep() {
     let enc_data = client_a.encrypt(&data, rng);
 
     let transcrypted_pseudo = systems.iter().fold(enc_pseudo, |acc, system| {
-        let pseudo_info = system.pseudonymization_info(&pc_a, &pc_b, &ec_a1, &ec_b1);
-        system.transcrypt(&acc, &pseudo_info)
+        let transcryption_info = system.transcryption_info(&pc_a, &pc_b, &ec_a1, &ec_b1);
+        system.transcrypt(&acc, &transcryption_info)
     });
 
     let transcrypted_data = systems.iter().fold(enc_data, |acc, system| {
@@ -96,3 +132,1426 @@ fn n_pep() {
     let rev_dec_pseudo = client_a.decrypt(&rev_pseudonymized);
     assert_eq!(pseudonym, rev_dec_pseudo);
 }
+
+#[test]
+fn factor_cache() {
+    let rng = &mut OsRng;
+    let pseudonymization_secret = PseudonymizationSecret::from("ps-secret".as_bytes().into());
+    let encryption_secret = EncryptionSecret::from("es-secret".as_bytes().into());
+    let blinding_factor = BlindingFactor::random(rng);
+    let system = PEPSystem::new(pseudonymization_secret, encryption_secret, blinding_factor);
+    // Two pseudonymization contexts and two encryption contexts (with separate rekey factors for
+    // pseudonyms if enabled) are used below
+    let capacity = if cfg!(feature = "separate-session-keys") {
+        6
+    } else {
+        4
+    };
+    let cached_system = system.clone().with_factor_cache(capacity);
+    let cache = cached_system.factor_cache().unwrap();
+
+    let pc_a = PseudonymizationContext::from("user-a");
+    let pc_b = PseudonymizationContext::from("user-b");
+    let ec_a1 = EncryptionContext::from("session-a1");
+    let ec_b1 = EncryptionContext::from("session-b1");
+
+    for _ in 0..2 {
+        assert_eq!(
+            system.pseudonymization_info(&pc_a, &pc_b, &ec_a1, &ec_b1),
+            cached_system.pseudonymization_info(&pc_a, &pc_b, &ec_a1, &ec_b1)
+        );
+        assert_eq!(
+            system.rekey_info(&ec_a1, &ec_b1),
+            cached_system.rekey_info(&ec_a1, &ec_b1)
+        );
+        assert_eq!(
+            system.session_key_share(&ec_a1),
+            cached_system.session_key_share(&ec_a1)
+        );
+    }
+    assert_eq!(cache.len(), capacity);
+    assert_eq!(cache.stats(), (14 - capacity as u64, capacity as u64));
+
+    let ec_c1 = EncryptionContext::from("session-c1");
+    cached_system.session_key_share(&ec_c1);
+    assert_eq!(cache.len(), capacity);
+    assert!(!cache.invalidate_pseudonymization_context(&pc_a));
+    assert!(cache.invalidate_encryption_context(&ec_c1));
+    assert_eq!(cache.len(), capacity - 1);
+
+    cache.clear();
+    assert!(cache.is_empty());
+    assert!(cached_system.clone().factor_cache().unwrap().is_empty());
+}
+
+#[test]
+fn system_config() {
+    let blinding_factor = BlindingFactor::random(&mut OsRng);
+    let config = PEPSystemConfig {
+        pseudonymization_secret: "ps-secret".to_string(),
+        encryption_secret: "es-secret".to_string(),
+        blinding_factor,
+        factor_cache_capacity: Some(16),
+    };
+    let json = serde_json::to_string(&config).unwrap();
+    let system = serde_json::from_str::<PEPSystemConfig>(&json)
+        .unwrap()
+        .build();
+    assert_eq!(system.factor_cache().unwrap().capacity(), 16);
+
+    // The secrets are not printed
+    let debug = format!("{:?}", config);
+    assert!(!debug.contains("ps-secret"));
+    assert!(!debug.contains("es-secret"));
+    assert!(!debug.contains(&blinding_factor.encode_as_hex()));
+}
+
+#[test]
+fn threshold_n_pep() {
+    let rng = &mut OsRng;
+    let config = ThresholdConfig::new(3, 5).unwrap();
+    assert_eq!(config.share_groups().len(), 10);
+    assert!(ThresholdConfig::new(0, 5).is_none());
+    assert!(ThresholdConfig::new(6, 5).is_none());
+    assert!(ThresholdConfig::new(3, 20).is_some());
+    assert!(ThresholdConfig::new(5, 20).is_none());
+    // Deserialized configurations are checked as well
+    let json = serde_json::to_string(&config).unwrap();
+    assert_eq!(
+        serde_json::from_str::<ThresholdConfig>(&json).unwrap(),
+        config
+    );
+    for (threshold, transcryptors) in [(0, 5), (6, 5), (5, 20)] {
+        let json = format!(
+            r#"{{"threshold":{},"transcryptors":{}}}"#,
+            threshold, transcryptors
+        );
+        assert!(serde_json::from_str::<ThresholdConfig>(&json).is_err());
+    }
+
+    let (_global_public, blinded_global_secret_key, secrets) =
+        make_threshold_global_keys(config.threshold(), config.transcryptors(), rng).unwrap();
+    let systems = secrets
+        .into_iter()
+        .enumerate()
+        .map(|(id, secrets)| ThresholdPEPSystem::new(id, config, secrets))
+        .collect::<Vec<_>>();
+
+    let pc_a = PseudonymizationContext::from("user-a");
+    let pc_b = PseudonymizationContext::from("user-b");
+    let ec_a1 = EncryptionContext::from("session-a1");
+    let ec_b1 = EncryptionContext::from("session-b1");
+
+    let make_client = |available: &[usize], context: &EncryptionContext| {
+        let shares = available
+            .iter()
+            .flat_map(|id| systems[*id].session_key_shares(context))
+            .collect::<Vec<_>>();
+        #[cfg(not(feature = "separate-session-keys"))]
+        let client = PEPClient::new_threshold(blinded_global_secret_key, &config, &shares);
+        #[cfg(feature = "separate-session-keys")]
+        let client = {
+            let pseudonym_shares = available
+                .iter()
+                .flat_map(|id| systems[*id].pseudonym_session_key_shares(context))
+                .collect::<Vec<_>>();
+            PEPClient::new_threshold(
+                blinded_global_secret_key,
+                &config,
+                &shares,
+                &pseudonym_shares,
+            )
+        };
+        client
+    };
+    let transcrypt_with = |available: &[usize], encrypted: EncryptedPseudonym| {
+        let assignment = config.assign_share_groups(available).unwrap();
+        assignment.iter().fold(encrypted, |acc, (id, groups)| {
+            let info = systems[*id]
+                .transcryption_info(groups, &pc_a, &pc_b, &ec_a1, &ec_b1)
+                .unwrap();
+            systems[*id].transcrypt(&acc, &info)
+        })
+    };
+
+    // Only two transcryptors are not sufficient
+    assert!(matches!(
+        make_client(&[0, 1], &ec_a1),
+        Err(ThresholdError::MissingShareGroups(_))
+    ));
+    assert!(config.assign_share_groups(&[3, 4]).is_err());
+    // A share for a group that does not exist is rejected instead of changing the session key
+    let mut shares = [0, 2, 4]
+        .iter()
+        .flat_map(|id| systems[*id].session_key_shares(&ec_a1))
+        .collect::<Vec<_>>();
+    shares.push(ThresholdSessionKeyShare {
+        group: config.share_groups().len(),
+        share: shares[0].share,
+    });
+    assert_eq!(
+        make_threshold_session_key(blinded_global_secret_key, &config, &shares).err(),
+        Some(ThresholdError::UnknownShareGroup(
+            config.share_groups().len()
+        ))
+    );
+
+    let client_a = make_client(&[0, 2, 4], &ec_a1).unwrap();
+    let client_b = make_client(&[1, 2, 3], &ec_b1).unwrap();
+    let client_b_all = make_client(&[0, 1, 2, 3, 4], &ec_b1).unwrap();
+    assert_eq!(client_b.session_public_key, client_b_all.session_public_key);
+
+    let pseudonym = Pseudonym::random(rng);
+    let enc_pseudo = client_a.encrypt(&pseudonym, rng);
+
+    // Different sets of transcryptors result in the same pseudonym
+    let dec_pseudo_1 = client_b.decrypt(&transcrypt_with(&[0, 1, 2], enc_pseudo));
+    let dec_pseudo_2 = client_b.decrypt(&transcrypt_with(&[4, 3, 1], enc_pseudo));
+    assert_ne!(pseudonym, dec_pseudo_1);
+    assert_eq!(dec_pseudo_1, dec_pseudo_2);
+
+    let data = DataPoint::random(rng);
+    let enc_data = client_a.encrypt(&data, rng);
+    let assignment = config.assign_share_groups(&[2, 3, 4]).unwrap();
+    let rekeyed = assignment.iter().fold(enc_data, |acc, (id, groups)| {
+        let info = systems[*id].rekey_info(groups, &ec_a1, &ec_b1).unwrap();
+        systems[*id].rekey(&acc, &info)
+    });
+    assert_eq!(data, client_b.decrypt(&rekeyed));
+}
+
+#[test]
+fn dealerless_key_generation() {
+    let n = 3;
+    let rng = &mut OsRng;
+
+    let mut participants = (0..n)
+        .map(|i| DkgParticipant::new(i, n, rng))
+        .collect::<Vec<_>>();
+    // Messages are serialized as if they were sent over the network
+    let commitments = participants
+        .iter()
+        .map(|p| serde_json::to_string(&p.commitment()).unwrap())
+        .collect::<Vec<_>>();
+    for participant in participants.iter_mut() {
+        assert!(participant.chain_step(rng).is_err());
+        for (i, commitment) in commitments.iter().enumerate() {
+            if i != participant.party() {
+                participant
+                    .receive_commitment(serde_json::from_str(commitment).unwrap())
+                    .unwrap();
+            }
+        }
+    }
+    assert!(participants[1].chain_step(rng).is_err());
+    for i in 0..n {
+        let step = participants[i].chain_step(rng).unwrap();
+        let step: DkgChainStep =
+            serde_json::from_str(&serde_json::to_string(&step).unwrap()).unwrap();
+        for participant in participants.iter_mut() {
+            if participant.party() != i {
+                participant.receive_chain_step(step).unwrap();
+            }
+        }
+    }
+    let results = participants
+        .into_iter()
+        .map(|p| p.finish().unwrap())
+        .collect::<Vec<_>>();
+    let (global_public_key, blinded_global_secret_key, _) = results[0];
+    for (pk, bsk, _) in results.iter() {
+        assert_eq!(global_public_key, *pk);
+        assert_eq!(blinded_global_secret_key, *bsk);
+    }
+
+    // The keys can be used for a regular n-PEP setup
+    let encryption_secrets = (0..n)
+        .map(|i| EncryptionSecret::from(format!("es-secret-{}", i).as_bytes().into()))
+        .collect::<Vec<_>>();
+    let systems = results
+        .iter()
+        .enumerate()
+        .map(|(i, (_, _, blinding_factor))| {
+            PEPSystem::new(
+                PseudonymizationSecret::from(format!("ps-secret-{}", i).as_bytes().into()),
+                encryption_secrets[i].clone(),
+                *blinding_factor,
+            )
+        })
+        .collect::<Vec<_>>();
+    let ec = EncryptionContext::from("session");
+    let shares = systems
+        .iter()
+        .map(|system| system.session_key_share(&ec))
+        .collect::<Vec<_>>();
+    #[cfg(not(feature = "separate-session-keys"))]
+    let client = PEPClient::new(blinded_global_secret_key, &shares);
+    #[cfg(feature = "separate-session-keys")]
+    let client = PEPClient::new(blinded_global_secret_key, &shares, &shares);
+
+    let data = DataPoint::random(rng);
+    let encrypted = OfflinePEPClient::new(global_public_key).encrypt(&data, rng);
+    let rekeyed = encryption_secrets.iter().fold(encrypted, |acc, secret| {
+        rekey(&acc, &RekeyInfo::new_from_global(&ec, secret))
+    });
+    assert_eq!(data, client.decrypt(&rekeyed));
+
+    // A tampered commitment is rejected
+    let mut a = DkgParticipant::new(0, 2, rng);
+    let b = DkgParticipant::new(1, 2, rng);
+    let mut commitment = b.commitment();
+    commitment.blinded_contribution = a.commitment().blinded_contribution;
+    assert_eq!(
+        a.receive_commitment(commitment),
+        Err(DkgError::InvalidCommitment(1))
+    );
+
+    let (pk, bsk, blinding_factors) = run_dkg(n, rng).unwrap();
+    assert_eq!(blinding_factors.len(), n);
+    assert_ne!(pk, global_public_key);
+    assert_ne!(bsk, blinded_global_secret_key);
+}
+
+#[test]
+fn verifiable_session_key_shares() {
+    let n = 3;
+    let rng = &mut OsRng;
+    let (_global_public, blinded_global_secret_key, blinding_factors) =
+        make_distributed_global_keys(n, rng);
+    let make_system = |i: usize, blinding_factor: BlindingFactor| {
+        PEPSystem::new(
+            PseudonymizationSecret::from(format!("ps-secret-{}", i).as_bytes().into()),
+            EncryptionSecret::from(format!("es-secret-{}", i).as_bytes().into()),
+            blinding_factor,
+        )
+    };
+    let mut systems = (0..n)
+        .map(|i| make_system(i, blinding_factors[i]))
+        .collect::<Vec<_>>();
+    let commitments = systems
+        .iter()
+        .map(|system| system.blinding_commitment())
+        .collect::<Vec<_>>();
+    let ec = EncryptionContext::from("session");
+
+    let verifiable_shares = |systems: &[PEPSystem], rng: &mut OsRng| {
+        systems
+            .iter()
+            .map(|system| system.verifiable_session_key_share(&ec, rng))
+            .collect::<Vec<_>>()
+    };
+    let shares = verifiable_shares(&systems, rng);
+    assert_eq!(
+        shares[0].share,
+        systems[0].session_key_share(&ec),
+        "verifiable share differs from regular share"
+    );
+    let json = serde_json::to_string(&shares[0]).unwrap();
+    assert_eq!(shares[0], serde_json::from_str(&json).unwrap());
+
+    #[cfg(not(feature = "separate-session-keys"))]
+    let client = PEPClient::new_verified(blinded_global_secret_key, &commitments, &shares);
+    #[cfg(feature = "separate-session-keys")]
+    let client = PEPClient::new_verified(
+        blinded_global_secret_key,
+        &commitments,
+        &shares,
+        &systems
+            .iter()
+            .map(|system| system.verifiable_pseudonym_session_key_share(&ec, rng))
+            .collect::<Vec<_>>(),
+    );
+    let client = client.unwrap();
+    assert_eq!(
+        client.session_public_key,
+        make_session_key(
+            blinded_global_secret_key,
+            &shares.iter().map(|x| x.share).collect::<Vec<_>>()
+        )
+        .0
+    );
+
+    // A system using another blinding factor than it committed to is detected
+    systems[1] = make_system(1, BlindingFactor::random(rng));
+    let bad_shares = verifiable_shares(&systems, rng);
+    #[cfg(not(feature = "separate-session-keys"))]
+    let result = PEPClient::new_verified(blinded_global_secret_key, &commitments, &bad_shares);
+    #[cfg(feature = "separate-session-keys")]
+    let result = PEPClient::new_verified(
+        blinded_global_secret_key,
+        &commitments,
+        &bad_shares,
+        &bad_shares,
+    );
+    assert_eq!(result.err(), Some(SessionKeyShareError::InvalidShare(1)));
+
+    // As is a tampered share
+    let mut tampered = shares.clone();
+    tampered[2].share = shares[0].share;
+    #[cfg(not(feature = "separate-session-keys"))]
+    let result = PEPClient::new_verified(blinded_global_secret_key, &commitments, &tampered);
+    #[cfg(feature = "separate-session-keys")]
+    let result = PEPClient::new_verified(
+        blinded_global_secret_key,
+        &commitments,
+        &tampered,
+        &tampered,
+    );
+    assert_eq!(result.err(), Some(SessionKeyShareError::InvalidShare(2)));
+
+    // But a system that uses its committed blinding factor with a wrong rekey factor is not, as
+    // the rekey commitment is not bound to anything
+    let mut cheating = shares.clone();
+    cheating[0] =
+        make_verifiable_session_key_share(&ScalarNonZero::random(rng), &blinding_factors[0], rng);
+    assert!(verify_session_key_share(&cheating[0], &commitments[0]));
+    #[cfg(not(feature = "separate-session-keys"))]
+    let result = PEPClient::new_verified(blinded_global_secret_key, &commitments, &cheating);
+    #[cfg(feature = "separate-session-keys")]
+    let result = PEPClient::new_verified(
+        blinded_global_secret_key,
+        &commitments,
+        &cheating,
+        &cheating,
+    );
+    assert_ne!(
+        result.unwrap().session_public_key,
+        client.session_public_key
+    );
+
+    #[cfg(not(feature = "separate-session-keys"))]
+    let result = PEPClient::new_verified(blinded_global_secret_key, &commitments, &shares[..2]);
+    #[cfg(feature = "separate-session-keys")]
+    let result = PEPClient::new_verified(
+        blinded_global_secret_key,
+        &commitments,
+        &shares[..2],
+        &shares[..2],
+    );
+    assert_eq!(
+        result.err(),
+        Some(SessionKeyShareError::WrongNumberOfShares {
+            expected: 3,
+            received: 2
+        })
+    );
+}
+
+/// A transcryptor that can be switched off, to test error reporting of a [`TranscryptionChain`].
+struct FlakyTranscryptor {
+    system: PEPSystem,
+    available: bool,
+}
+
+impl FlakyTranscryptor {
+    fn check(&self) -> Result<(), &'static str> {
+        if self.available {
+            Ok(())
+        } else {
+            Err("unavailable")
+        }
+    }
+}
+
+impl Transcryptor for FlakyTranscryptor {
+    type Error = &'static str;
+
+    fn pseudonymize(
+        &self,
+        encrypted: &EncryptedPseudonym,
+        from_pseudo: &PseudonymizationContext,
+        to_pseudo: &PseudonymizationContext,
+        from_enc: &EncryptionContext,
+        to_enc: &EncryptionContext,
+    ) -> Result<EncryptedPseudonym, Self::Error> {
+        self.check()?;
+        Ok(Transcryptor::pseudonymize(
+            &self.system,
+            encrypted,
+            from_pseudo,
+            to_pseudo,
+            from_enc,
+            to_enc,
+        )
+        .unwrap())
+    }
+
+    fn rekey(
+        &self,
+        encrypted: &EncryptedDataPoint,
+        from_enc: &EncryptionContext,
+        to_enc: &EncryptionContext,
+    ) -> Result<EncryptedDataPoint, Self::Error> {
+        self.check()?;
+        Ok(Transcryptor::rekey(&self.system, encrypted, from_enc, to_enc).unwrap())
+    }
+
+    fn transcrypt<E: Encrypted>(
+        &self,
+        encrypted: &E,
+        from_pseudo: &PseudonymizationContext,
+        to_pseudo: &PseudonymizationContext,
+        from_enc: &EncryptionContext,
+        to_enc: &EncryptionContext,
+    ) -> Result<E, Self::Error> {
+        self.check()?;
+        Ok(Transcryptor::transcrypt(
+            &self.system,
+            encrypted,
+            from_pseudo,
+            to_pseudo,
+            from_enc,
+            to_enc,
+        )
+        .unwrap())
+    }
+
+    fn pseudonymize_batch<R: RngCore + CryptoRng>(
+        &self,
+        encrypted: &mut [EncryptedPseudonym],
+        from_pseudo: &PseudonymizationContext,
+        to_pseudo: &PseudonymizationContext,
+        from_enc: &EncryptionContext,
+        to_enc: &EncryptionContext,
+        rng: &mut R,
+    ) -> Result<Box<[EncryptedPseudonym]>, Self::Error> {
+        self.check()?;
+        Ok(Transcryptor::pseudonymize_batch(
+            &self.system,
+            encrypted,
+            from_pseudo,
+            to_pseudo,
+            from_enc,
+            to_enc,
+            rng,
+        )
+        .unwrap())
+    }
+
+    fn rekey_batch<R: RngCore + CryptoRng>(
+        &self,
+        encrypted: &mut [EncryptedDataPoint],
+        from_enc: &EncryptionContext,
+        to_enc: &EncryptionContext,
+        rng: &mut R,
+    ) -> Result<Box<[EncryptedDataPoint]>, Self::Error> {
+        self.check()?;
+        Ok(Transcryptor::rekey_batch(&self.system, encrypted, from_enc, to_enc, rng).unwrap())
+    }
+
+    fn transcrypt_batch<R: RngCore + CryptoRng>(
+        &self,
+        encrypted: &mut Box<[EncryptedEntityDataPair]>,
+        from_pseudo: &PseudonymizationContext,
+        to_pseudo: &PseudonymizationContext,
+        from_enc: &EncryptionContext,
+        to_enc: &EncryptionContext,
+        rng: &mut R,
+    ) -> Result<Box<[EncryptedEntityDataPair]>, Self::Error> {
+        self.check()?;
+        Ok(Transcryptor::transcrypt_batch(
+            &self.system,
+            encrypted,
+            from_pseudo,
+            to_pseudo,
+            from_enc,
+            to_enc,
+            rng,
+        )
+        .unwrap())
+    }
+}
+
+#[test]
+fn transcryption_chain() {
+    let n = 3;
+    let rng = &mut OsRng;
+    let (_global_public, blinded_global_secret_key, blinding_factors) =
+        make_distributed_global_keys(n, rng);
+    let systems = (0..n)
+        .map(|i| {
+            PEPSystem::new(
+                PseudonymizationSecret::from(format!("ps-secret-{}", i).as_bytes().into()),
+                EncryptionSecret::from(format!("es-secret-{}", i).as_bytes().into()),
+                blinding_factors[i],
+            )
+        })
+        .collect::<Vec<_>>();
+
+    let pc_a = PseudonymizationContext::from("user-a");
+    let pc_b = PseudonymizationContext::from("user-b");
+    let ec_a = EncryptionContext::from("session-a");
+    let ec_b = EncryptionContext::from("session-b");
+
+    #[cfg(not(feature = "separate-session-keys"))]
+    let make_client = |ec: &EncryptionContext| {
+        let shares = systems
+            .iter()
+            .map(|system| system.session_key_share(ec))
+            .collect::<Vec<_>>();
+        PEPClient::new(blinded_global_secret_key, &shares)
+    };
+    #[cfg(feature = "separate-session-keys")]
+    let make_client = |ec: &EncryptionContext| {
+        let shares = systems
+            .iter()
+            .map(|system| system.session_key_share(ec))
+            .collect::<Vec<_>>();
+        let pseudonym_shares = systems
+            .iter()
+            .map(|system| system.pseudonym_session_key_share(ec))
+            .collect::<Vec<_>>();
+        PEPClient::new(blinded_global_secret_key, &shares, &pseudonym_shares)
+    };
+    let client_a = make_client(&ec_a);
+    let client_b = make_client(&ec_b);
+
+    let chain = TranscryptionChain::new(systems.clone());
+    assert_eq!(chain.len(), n);
+
+    let pseudonym = Pseudonym::random(rng);
+    let data = DataPoint::random(rng);
+    let enc_pseudo = client_a.encrypt(&pseudonym, rng);
+    let enc_data = client_a.encrypt(&data, rng);
+
+    // The chain gives the same result as applying all systems manually
+    let expected_pseudo = systems.iter().fold(enc_pseudo, |acc, system| {
+        let info = system.pseudonymization_info(&pc_a, &pc_b, &ec_a, &ec_b);
+        system.pseudonymize(&acc, &info)
+    });
+    let pseudonymized = chain
+        .pseudonymize(&enc_pseudo, &pc_a, &pc_b, &ec_a, &ec_b)
+        .unwrap();
+    assert_eq!(
+        client_b.decrypt(&expected_pseudo),
+        client_b.decrypt(&pseudonymized)
+    );
+    let transcrypted = chain
+        .transcrypt(&enc_pseudo, &pc_a, &pc_b, &ec_a, &ec_b)
+        .unwrap();
+    assert_eq!(
+        client_b.decrypt(&pseudonymized),
+        client_b.decrypt(&transcrypted)
+    );
+    assert_ne!(pseudonym, client_b.decrypt(&pseudonymized));
+    let rekeyed = chain.rekey(&enc_data, &ec_a, &ec_b).unwrap();
+    assert_eq!(data, client_b.decrypt(&rekeyed));
+
+    // Batches
+    let pseudonyms = (0..10).map(|_| Pseudonym::random(rng)).collect::<Vec<_>>();
+    let encrypted = pseudonyms
+        .iter()
+        .map(|p| client_a.encrypt(p, rng))
+        .collect::<Vec<_>>();
+    let expected = encrypted
+        .iter()
+        .map(|p| client_b.decrypt(&chain.pseudonymize(p, &pc_a, &pc_b, &ec_a, &ec_b).unwrap()))
+        .collect::<Vec<_>>();
+    let mut result = chain
+        .pseudonymize_batch(&encrypted, &pc_a, &pc_b, &ec_a, &ec_b, rng)
+        .unwrap()
+        .iter()
+        .map(|p| client_b.decrypt(p))
+        .collect::<Vec<_>>();
+    assert_eq!(result.len(), expected.len());
+    result.retain(|p| expected.contains(p));
+    assert_eq!(result.len(), expected.len());
+
+    let data_points = (0..10).map(|_| DataPoint::random(rng)).collect::<Vec<_>>();
+    let encrypted = data_points
+        .iter()
+        .map(|d| client_a.encrypt(d, rng))
+        .collect::<Vec<_>>();
+    let result = chain.rekey_batch(&encrypted, &ec_a, &ec_b, rng).unwrap();
+    for d in result.iter() {
+        assert!(data_points.contains(&client_b.decrypt(d)));
+    }
+
+    let entities: Vec<EncryptedEntityDataPair> = (0..5)
+        .map(|i| {
+            (
+                vec![client_a.encrypt(&pseudonyms[i], rng)].into_boxed_slice(),
+                vec![client_a.encrypt(&data_points[i], rng)].into_boxed_slice(),
+            )
+        })
+        .collect();
+    let result = chain
+        .transcrypt_batch(&entities, &pc_a, &pc_b, &ec_a, &ec_b, rng)
+        .unwrap();
+    for (pseudonyms, data) in result.iter() {
+        let i = data_points
+            .iter()
+            .position(|d| *d == client_b.decrypt(&data[0]))
+            .unwrap();
+        assert_eq!(expected[i], client_b.decrypt(&pseudonyms[0]));
+    }
+
+    // A failing hop is reported
+    let flaky_chain = TranscryptionChain::new(
+        systems
+            .iter()
+            .enumerate()
+            .map(|(i, system)| FlakyTranscryptor {
+                system: system.clone(),
+                available: i != 1,
+            })
+            .collect(),
+    );
+    assert_eq!(
+        flaky_chain.rekey(&enc_data, &ec_a, &ec_b),
+        Err(HopError {
+            hop: 1,
+            error: "unavailable"
+        })
+    );
+    assert_eq!(
+        flaky_chain
+            .pseudonymize_batch(
+                &encrypted_pseudonyms(&client_a, &pseudonyms, rng),
+                &pc_a,
+                &pc_b,
+                &ec_a,
+                &ec_b,
+                rng
+            )
+            .err()
+            .map(|e| e.hop),
+        Some(1)
+    );
+}
+
+fn encrypted_pseudonyms(
+    client: &PEPClient,
+    pseudonyms: &[Pseudonym],
+    rng: &mut OsRng,
+) -> Vec<EncryptedPseudonym> {
+    pseudonyms.iter().map(|p| client.encrypt(p, rng)).collect()
+}
+
+#[test]
+fn blinding_refresh() {
+    let n = 3;
+    let rng = &mut OsRng;
+    let (global_public_key, blinded_global_secret_key, blinding_factors) =
+        make_distributed_global_keys(n, rng);
+    let mut systems = (0..n)
+        .map(|i| {
+            PEPSystem::new(
+                PseudonymizationSecret::from(format!("ps-secret-{}", i).as_bytes().into()),
+                EncryptionSecret::from(format!("es-secret-{}", i).as_bytes().into()),
+                blinding_factors[i],
+            )
+        })
+        .collect::<Vec<_>>();
+    let ec = EncryptionContext::from("session");
+    let session_key = |systems: &[PEPSystem], bsk| {
+        let shares = systems
+            .iter()
+            .map(|system| system.session_key_share(&ec))
+            .collect::<Vec<_>>();
+        make_session_key(bsk, &shares)
+    };
+    let (session_public_key, _) = session_key(&systems, blinded_global_secret_key);
+    let old_shares = systems
+        .iter()
+        .map(|system| system.session_key_share(&ec))
+        .collect::<Vec<_>>();
+
+    // Keeping the blinded global secret key
+    let (bsk, new_blinding_factors) = run_blinding_refresh(
+        &blinding_factors,
+        &blinded_global_secret_key,
+        BlindingRefreshMode::KeepBlindedGlobalSecretKey,
+        rng,
+    )
+    .unwrap();
+    assert_eq!(bsk, blinded_global_secret_key);
+    for (system, b) in systems.iter_mut().zip(new_blinding_factors.iter()) {
+        system.set_blinding_factor(*b);
+    }
+    assert_ne!(old_shares[0], systems[0].session_key_share(&ec));
+    assert_eq!(session_public_key, session_key(&systems, bsk).0);
+
+    // An old share no longer combines with new shares
+    let mut mixed = systems
+        .iter()
+        .map(|system| system.session_key_share(&ec))
+        .collect::<Vec<_>>();
+    mixed[0] = old_shares[0];
+    assert_ne!(session_public_key, make_session_key(bsk, &mixed).0);
+
+    // Publishing a new blinded global secret key, using the messages as if they were sent
+    let mode = BlindingRefreshMode::NewBlindedGlobalSecretKey;
+    let mut parties = (0..n)
+        .map(|i| BlindingRefresh::new(i, n, mode, rng))
+        .collect::<Vec<_>>();
+    let shares = parties
+        .iter()
+        .flat_map(|p| p.shares())
+        .map(|s| serde_json::to_string(&s).unwrap())
+        .collect::<Vec<_>>();
+    let key_refreshes = parties
+        .iter()
+        .map(|p| serde_json::to_string(&p.blinded_global_secret_key_refresh().unwrap()).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        parties[0].receive_share(serde_json::from_str(&shares[0]).unwrap()),
+        Err(BlindingRefreshError::WrongRecipient(1))
+    );
+    for share in shares.iter() {
+        let share: BlindingRefreshShare = serde_json::from_str(share).unwrap();
+        parties[share.to].receive_share(share).unwrap();
+    }
+    for (i, refresh) in key_refreshes.iter().enumerate() {
+        for (j, party) in parties.iter_mut().enumerate() {
+            if i != j {
+                party
+                    .receive_blinded_global_secret_key_refresh(
+                        serde_json::from_str(refresh).unwrap(),
+                    )
+                    .unwrap();
+            }
+        }
+    }
+    let results = parties
+        .into_iter()
+        .zip(new_blinding_factors.iter())
+        .map(|(p, b)| p.finish(b, &bsk).unwrap())
+        .collect::<Vec<_>>();
+    let new_bsk = results[0].1;
+    assert_ne!(new_bsk, bsk);
+    for ((b, party_bsk), system) in results.iter().zip(systems.iter_mut()) {
+        assert_eq!(new_bsk, *party_bsk);
+        system.set_blinding_factor(*b);
+    }
+    assert_eq!(session_public_key, session_key(&systems, new_bsk).0);
+
+    // Global ciphertexts remain valid
+    let data = DataPoint::random(rng);
+    let encrypted = OfflinePEPClient::new(global_public_key).encrypt(&data, rng);
+    let rekeyed = (0..n).fold(encrypted, |acc, i| {
+        rekey(
+            &acc,
+            &RekeyInfo::new_from_global(
+                &ec,
+                &EncryptionSecret::from(format!("es-secret-{}", i).as_bytes().into()),
+            ),
+        )
+    });
+    let (_, session_secret_key) = session_key(&systems, new_bsk);
+    assert_eq!(data, decrypt(&rekeyed, &session_secret_key));
+
+    // Incomplete refreshes cannot be finished
+    let party = BlindingRefresh::new(0, n, BlindingRefreshMode::KeepBlindedGlobalSecretKey, rng);
+    assert_eq!(
+        party.finish(&new_blinding_factors[0], &bsk).err(),
+        Some(BlindingRefreshError::MissingMessages(vec![1, 2]))
+    );
+}
+
+#[test]
+fn transcryptor_join_and_leave() {
+    let rng = &mut OsRng;
+    let (global_public_key, blinded_global_secret_key, blinding_factors) =
+        make_distributed_global_keys(3, rng);
+    let encryption_secret =
+        |i: usize| EncryptionSecret::from(format!("es-secret-{}", i).as_bytes().into());
+    let data = DataPoint::random(rng);
+    let encrypted = OfflinePEPClient::new(global_public_key).encrypt(&data, rng);
+    let ec = EncryptionContext::from("session");
+
+    // Check that a global ciphertext can be decrypted by a client with a session key derived from
+    // the setup, where `ids` are the secrets used by the transcryptors
+    let check =
+        |ids: &[usize], bsk: BlindedGlobalSecretKey, blinding_factors: &[BlindingFactor]| {
+            assert_eq!(ids.len(), blinding_factors.len());
+            let implied_public_key = blinding_factors.iter().fold(bsk.0, |acc, b| acc * b.0) * G;
+            assert_eq!(global_public_key.0, implied_public_key);
+            let shares = ids
+                .iter()
+                .zip(blinding_factors)
+                .map(|(i, b)| {
+                    PEPSystem::new(
+                        PseudonymizationSecret::from(format!("ps-secret-{}", i).as_bytes().into()),
+                        encryption_secret(*i),
+                        *b,
+                    )
+                    .session_key_share(&ec)
+                })
+                .collect::<Vec<_>>();
+            let (_, session_secret_key) = make_session_key(bsk, &shares);
+            let rekeyed = ids.iter().fold(encrypted, |acc, i| {
+                rekey(
+                    &acc,
+                    &RekeyInfo::new_from_global(&ec, &encryption_secret(*i)),
+                )
+            });
+            assert_eq!(data, decrypt(&rekeyed, &session_secret_key));
+        };
+    check(&[0, 1, 2], blinded_global_secret_key, &blinding_factors);
+
+    let (joined_bsk, joined_factors) =
+        run_transcryptor_join(&blinding_factors, &blinded_global_secret_key, rng).unwrap();
+    assert_ne!(joined_bsk, blinded_global_secret_key);
+    assert_eq!(joined_factors.len(), 4);
+    check(&[0, 1, 2, 3], joined_bsk, &joined_factors);
+
+    let (left_bsk, left_factors) =
+        run_transcryptor_leave(&joined_factors, &joined_bsk, 1, rng).unwrap();
+    assert_ne!(left_bsk, joined_bsk);
+    assert_eq!(left_factors.len(), 3);
+    check(&[0, 2, 3], left_bsk, &left_factors);
+
+    assert_eq!(
+        run_transcryptor_leave(&left_factors, &left_bsk, 3, rng).err(),
+        Some(BlindingRefreshError::UnknownParty(3))
+    );
+    assert_eq!(
+        run_transcryptor_leave(&left_factors[..1], &left_bsk, 0, rng).err(),
+        Some(BlindingRefreshError::LastParty(0))
+    );
+}
+
+#[test]
+fn distributed_decryption() {
+    let n = 3;
+    let rng = &mut OsRng;
+    let (global_public_key, blinded_global_secret_key, blinding_factors) =
+        make_distributed_global_keys(n, rng);
+    let systems = (0..n)
+        .map(|i| {
+            PEPSystem::new(
+                PseudonymizationSecret::from(format!("ps-secret-{}", i).as_bytes().into()),
+                EncryptionSecret::from(format!("es-secret-{}", i).as_bytes().into()),
+                blinding_factors[i],
+            )
+        })
+        .collect::<Vec<_>>();
+    let commitments = systems
+        .iter()
+        .map(|system| system.blinding_commitment())
+        .collect::<Vec<_>>();
+    let ec = EncryptionContext::from("audit");
+    let shares = systems
+        .iter()
+        .map(|system| system.verifiable_session_key_share(&ec, rng))
+        .collect::<Vec<_>>();
+    #[cfg(not(feature = "separate-session-keys"))]
+    let pseudonym_shares = shares.clone();
+    #[cfg(feature = "separate-session-keys")]
+    let pseudonym_shares = systems
+        .iter()
+        .map(|system| system.verifiable_pseudonym_session_key_share(&ec, rng))
+        .collect::<Vec<_>>();
+
+    let offline_client = OfflinePEPClient::new(global_public_key);
+    let data = DataPoint::random(rng);
+    let pseudonym = Pseudonym::random(rng);
+    let encrypted_data = offline_client.encrypt(&data, rng);
+    let encrypted_pseudonym = offline_client.encrypt(&pseudonym, rng);
+
+    let mut data_rekeys = vec![];
+    let mut current = encrypted_data;
+    for system in systems.iter() {
+        let step = system.verifiable_rekey_from_global(&current, &ec, rng);
+        // Steps are serializable, to be sent to the recipient
+        let step: VerifiableRekey<EncryptedDataPoint> =
+            serde_json::from_str(&serde_json::to_string(&step).unwrap()).unwrap();
+        current = step.result;
+        data_rekeys.push(step);
+    }
+    assert_eq!(
+        Ok(data),
+        decrypt_distributed(
+            &encrypted_data,
+            blinded_global_secret_key,
+            &commitments,
+            &shares,
+            &data_rekeys
+        )
+    );
+
+    let pseudonym_rekeys = systems
+        .iter()
+        .scan(encrypted_pseudonym, |current, system| {
+            let step = system.verifiable_rekey_from_global(current, &ec, rng);
+            *current = step.result;
+            Some(step)
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        Ok(pseudonym),
+        decrypt_distributed(
+            &encrypted_pseudonym,
+            blinded_global_secret_key,
+            &commitments,
+            &pseudonym_shares,
+            &pseudonym_rekeys
+        )
+    );
+
+    // A system that rekeys with another factor than in its session key share is detected
+    let mut bad_rekeys = data_rekeys.clone();
+    bad_rekeys[1] = systems[1].verifiable_rekey_from_global(
+        &data_rekeys[0].result,
+        &EncryptionContext::from("other"),
+        rng,
+    );
+    assert_eq!(
+        Err(DistributedDecryptionError::InvalidRekey(1)),
+        decrypt_distributed(
+            &encrypted_data,
+            blinded_global_secret_key,
+            &commitments,
+            &shares,
+            &bad_rekeys
+        )
+    );
+    assert_eq!(
+        Err(DistributedDecryptionError::WrongNumberOfRekeys {
+            expected: 3,
+            received: 2
+        }),
+        decrypt_distributed(
+            &encrypted_data,
+            blinded_global_secret_key,
+            &commitments,
+            &shares,
+            &data_rekeys[..2]
+        )
+    );
+}
+
+#[test]
+fn distributed_session_public_key() {
+    let n = 3;
+    let rng = &mut OsRng;
+    let (_global_public_key, blinded_global_secret_key, blinding_factors) =
+        make_distributed_global_keys(n, rng);
+    let systems = (0..n)
+        .map(|i| {
+            PEPSystem::new(
+                PseudonymizationSecret::from(format!("ps-secret-{}", i).as_bytes().into()),
+                EncryptionSecret::from(format!("es-secret-{}", i).as_bytes().into()),
+                blinding_factors[i],
+            )
+        })
+        .collect::<Vec<_>>();
+    let commitments = systems
+        .iter()
+        .map(|system| system.blinding_commitment())
+        .collect::<Vec<_>>();
+    let ec = EncryptionContext::from("researcher-session");
+
+    // A data source derives the session public key of the researcher
+    let steps = systems
+        .iter()
+        .scan(
+            session_public_key_base(&blinded_global_secret_key),
+            |previous, system| {
+                let step = system.session_public_key_step(&ec, previous, rng);
+                let step: SessionPublicKeyStep =
+                    serde_json::from_str(&serde_json::to_string(&step).unwrap()).unwrap();
+                *previous = step.point;
+                Some(step)
+            },
+        )
+        .collect::<Vec<_>>();
+    let session_public_key =
+        verify_session_public_key_steps(&blinded_global_secret_key, &commitments, &steps).unwrap();
+
+    // The researcher gathers its shares as usual
+    let shares = systems
+        .iter()
+        .map(|system| system.session_key_share(&ec))
+        .collect::<Vec<_>>();
+    #[cfg(not(feature = "separate-session-keys"))]
+    let researcher = PEPClient::new(blinded_global_secret_key, &shares);
+    #[cfg(feature = "separate-session-keys")]
+    let researcher = PEPClient::new(
+        blinded_global_secret_key,
+        &shares,
+        &systems
+            .iter()
+            .map(|system| system.pseudonym_session_key_share(&ec))
+            .collect::<Vec<_>>(),
+    );
+    assert_eq!(session_public_key, researcher.session_public_key);
+
+    let data = DataPoint::random(rng);
+    let encrypted = encrypt(&data, &session_public_key, rng);
+    assert_eq!(data, researcher.decrypt(&encrypted));
+
+    // Incorrect steps are detected
+    let mut bad_steps = steps.clone();
+    bad_steps[2] = systems[2].session_public_key_step(&ec, &steps[0].point, rng);
+    assert_eq!(
+        verify_session_public_key_steps(&blinded_global_secret_key, &commitments, &bad_steps),
+        Err(SessionPublicKeyError::InvalidStep(2))
+    );
+    let mut swapped = commitments.clone();
+    swapped.swap(0, 1);
+    assert_eq!(
+        verify_session_public_key_steps(&blinded_global_secret_key, &swapped, &steps),
+        Err(SessionPublicKeyError::InvalidStep(0))
+    );
+
+    // But a system that uses its committed blinding factor with a wrong rekey factor is not, as
+    // the rekey commitment is not bound to anything
+    let mut cheating = steps.clone();
+    cheating[2] = make_session_public_key_step(
+        &steps[1].point,
+        &ScalarNonZero::random(rng),
+        &blinding_factors[2],
+        rng,
+    );
+    let wrong_key =
+        verify_session_public_key_steps(&blinded_global_secret_key, &commitments, &cheating)
+            .unwrap();
+    assert_ne!(wrong_key, researcher.session_public_key);
+    // It can only be detected by comparing its rekey commitment with another one for the session
+    let share = systems[2].verifiable_session_key_share(&ec, rng);
+    assert_eq!(share.rekey_commitment, steps[2].rekey_commitment);
+    assert_ne!(share.rekey_commitment, cheating[2].rekey_commitment);
+}
+
+#[test]
+fn protocol() {
+    let n = 3;
+    let rng = &mut OsRng;
+    let (_global_public_key, blinded_global_secret_key, blinding_factors) =
+        make_distributed_global_keys(n, rng);
+    let remotes = (0..n)
+        .map(|i| {
+            let system = PEPSystem::new(
+                PseudonymizationSecret::from(format!("ps-secret-{}", i).as_bytes().into()),
+                EncryptionSecret::from(format!("es-secret-{}", i).as_bytes().into()),
+                blinding_factors[i],
+            );
+            RemoteTranscryptor::new(InMemoryTransport::new(PEPSystemServer::new(system)))
+        })
+        .collect::<Vec<_>>();
+
+    let pc_a = PseudonymizationContext::from("user-a");
+    let pc_b = PseudonymizationContext::from("user-b");
+    let ec_a = EncryptionContext::from("session-a");
+    let ec_b = EncryptionContext::from("session-b");
+
+    // Messages are serializable
+    let request = Request::PseudonymizationInfo {
+        from_pseudo: pc_a.clone(),
+        to_pseudo: pc_b.clone(),
+        from_enc: ec_a.clone(),
+        to_enc: ec_b.clone(),
+    };
+    let json = serde_json::to_string(&request).unwrap();
+    assert!(json.starts_with(r#"{"type":"pseudonymization_info","#));
+    assert_eq!(request, serde_json::from_str(&json).unwrap());
+    let response = remotes[0].transport().send(&request).unwrap();
+    assert_eq!(
+        response,
+        serde_json::from_str(&serde_json::to_string(&response).unwrap()).unwrap()
+    );
+    assert_eq!(
+        response,
+        Response::PseudonymizationInfo(
+            remotes[0]
+                .transport()
+                .server()
+                .system()
+                .pseudonymization_info(&pc_a, &pc_b, &ec_a, &ec_b)
+        )
+    );
+    let error = Response::Error(ServerError::Refused("too large".to_string()));
+    assert_eq!(
+        error,
+        serde_json::from_str(&serde_json::to_string(&error).unwrap()).unwrap()
+    );
+
+    // Clients retrieve their (verified) session keys remotely
+    let commitments = remotes
+        .iter()
+        .map(|remote| remote.blinding_commitment().unwrap())
+        .collect::<Vec<_>>();
+    let make_client = |ec: &EncryptionContext| {
+        let shares = remotes
+            .iter()
+            .map(|remote| remote.verifiable_session_key_share(ec).unwrap())
+            .collect::<Vec<_>>();
+        #[cfg(not(feature = "separate-session-keys"))]
+        let client = PEPClient::new_verified(blinded_global_secret_key, &commitments, &shares);
+        #[cfg(feature = "separate-session-keys")]
+        let client = PEPClient::new_verified(
+            blinded_global_secret_key,
+            &commitments,
+            &shares,
+            &remotes
+                .iter()
+                .map(|remote| remote.verifiable_pseudonym_session_key_share(ec).unwrap())
+                .collect::<Vec<_>>(),
+        );
+        client.unwrap()
+    };
+    let client_a = make_client(&ec_a);
+    let client_b = make_client(&ec_b);
+    assert_eq!(
+        client_a.session_public_key,
+        make_session_key(
+            blinded_global_secret_key,
+            &remotes
+                .iter()
+                .map(|remote| remote.session_key_share(&ec_a).unwrap())
+                .collect::<Vec<_>>()
+        )
+        .0
+    );
+
+    // Remote transcryptors can be chained
+    let chain = TranscryptionChain::new(remotes);
+    let pseudonym = Pseudonym::random(rng);
+    let data = DataPoint::random(rng);
+    let enc_pseudo = client_a.encrypt(&pseudonym, rng);
+    let enc_data = client_a.encrypt(&data, rng);
+    let transcrypted_data = chain
+        .transcrypt(&enc_data, &pc_a, &pc_b, &ec_a, &ec_b)
+        .unwrap();
+    assert_eq!(data, client_b.decrypt(&transcrypted_data));
+    let pseudonymized = chain
+        .pseudonymize(&enc_pseudo, &pc_a, &pc_b, &ec_a, &ec_b)
+        .unwrap();
+    let pseudonymized = client_b.decrypt(&pseudonymized);
+    assert_ne!(pseudonym, pseudonymized);
+
+    let entities: Vec<EncryptedEntityDataPair> = vec![(
+        vec![enc_pseudo].into_boxed_slice(),
+        vec![enc_data].into_boxed_slice(),
+    )];
+    let result = chain
+        .transcrypt_batch(&entities, &pc_a, &pc_b, &ec_a, &ec_b, rng)
+        .unwrap();
+    assert_eq!(pseudonymized, client_b.decrypt(&result[0].0[0]));
+    assert_eq!(data, client_b.decrypt(&result[0].1[0]));
+    let result = chain
+        .rekey_batch(&[enc_data, enc_data], &ec_a, &ec_b, rng)
+        .unwrap();
+    assert_eq!(data, client_b.decrypt(&result[1]));
+    let result = chain
+        .pseudonymize_batch(&[enc_pseudo], &pc_a, &pc_b, &ec_a, &ec_b, rng)
+        .unwrap();
+    assert_eq!(pseudonymized, client_b.decrypt(&result[0]));
+}
+
+/// A transport that fails a number of times, with a timeout if `timeout` is set.
+struct UnreliableTransport {
+    inner: InMemoryTransport,
+    failures: AtomicUsize,
+    timeout: bool,
+}
+
+impl Transport for UnreliableTransport {
+    type Error = &'static str;
+    fn send(&self, request: &Request) -> Result<Response, Self::Error> {
+        if self
+            .failures
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |x| x.checked_sub(1))
+            .is_ok()
+        {
+            return Err(if self.timeout {
+                "timed out"
+            } else {
+                "unavailable"
+            });
+        }
+        Ok(self.inner.send(request).unwrap())
+    }
+    fn is_timeout(error: &Self::Error) -> bool {
+        *error == "timed out"
+    }
+}
+
+#[test]
+fn client_builder() {
+    let n = 3;
+    let rng = &mut OsRng;
+    let (_global_public_key, blinded_global_secret_key, blinding_factors) =
+        make_distributed_global_keys(n, rng);
+    let systems = (0..n)
+        .map(|i| {
+            PEPSystem::new(
+                PseudonymizationSecret::from(format!("ps-secret-{}", i).as_bytes().into()),
+                EncryptionSecret::from(format!("es-secret-{}", i).as_bytes().into()),
+                blinding_factors[i],
+            )
+        })
+        .collect::<Vec<_>>();
+    let transports = |failures: usize, timeout: bool| {
+        systems
+            .iter()
+            .map(|system| UnreliableTransport {
+                inner: InMemoryTransport::new(PEPSystemServer::new(system.clone())),
+                failures: AtomicUsize::new(failures),
+                timeout,
+            })
+            .collect::<Vec<_>>()
+    };
+    let ec_a = EncryptionContext::from("session-a");
+    let expected = PEPClient::new_verified(
+        blinded_global_secret_key,
+        &systems
+            .iter()
+            .map(|s| s.blinding_commitment())
+            .collect::<Vec<_>>(),
+        &systems
+            .iter()
+            .map(|s| s.verifiable_session_key_share(&ec_a, rng))
+            .collect::<Vec<_>>(),
+        #[cfg(feature = "separate-session-keys")]
+        &systems
+            .iter()
+            .map(|s| s.verifiable_pseudonym_session_key_share(&ec_a, rng))
+            .collect::<Vec<_>>(),
+    )
+    .unwrap();
+
+    // Transport errors are retried, and blinding commitments are kept after the first build
+    let mut builder = PEPClientBuilder::new(blinded_global_secret_key, transports(2, false))
+        .with_retries(2, Duration::from_millis(1));
+    assert!(builder.blinding_commitments().is_none());
+    let client = builder.build(&ec_a).unwrap();
+    assert_eq!(expected.session_public_key, client.session_public_key);
+    assert_eq!(
+        builder.blinding_commitments().unwrap()[1],
+        systems[1].blinding_commitment()
+    );
+
+    let mut builder = PEPClientBuilder::new(blinded_global_secret_key, transports(3, false))
+        .with_retries(2, Duration::from_millis(1));
+    assert_eq!(
+        builder.build(&ec_a).err().unwrap(),
+        ClientBuilderError::Fetch {
+            transcryptor: 0,
+            error: FetchError::Protocol(ProtocolError::Transport("unavailable")),
+        }
+    );
+
+    // Timeouts of the transport are retried and reported as such
+    let mut builder = PEPClientBuilder::new(blinded_global_secret_key, transports(1, true))
+        .with_retries(1, Duration::ZERO);
+    assert!(builder.build(&ec_a).is_ok());
+    let mut builder = PEPClientBuilder::new(blinded_global_secret_key, transports(1, true))
+        .with_retries(0, Duration::ZERO);
+    assert!(matches!(
+        builder.build(&ec_a),
+        Err(ClientBuilderError::Fetch {
+            error: FetchError::Timeout,
+            ..
+        })
+    ));
+
+    // Shares are verified against pinned blinding commitments
+    let mut commitments = systems
+        .iter()
+        .map(|s| s.blinding_commitment())
+        .collect::<Vec<_>>();
+    commitments.swap(0, 2);
+    let mut builder = PEPClientBuilder::new(blinded_global_secret_key, transports(0, false))
+        .with_blinding_commitments(commitments.clone());
+    assert_eq!(
+        builder.build(&ec_a).err().unwrap(),
+        ClientBuilderError::InvalidShare(SessionKeyShareError::InvalidShare(0))
+    );
+    let mut builder = PEPClientBuilder::new(blinded_global_secret_key, transports(0, false))
+        .with_blinding_commitments(commitments[..2].to_vec());
+    assert_eq!(
+        builder.build(&ec_a).err().unwrap(),
+        ClientBuilderError::WrongNumberOfCommitments {
+            expected: 3,
+            received: 2
+        }
+    );
+
+    // The client is renewed when the session rolls over
+    let session = AtomicUsize::new(0);
+    let mut client = RenewingPEPClient::new(
+        PEPClientBuilder::new(blinded_global_secret_key, transports(0, false)),
+        || EncryptionContext::from(format!("session-{}", session.load(Ordering::SeqCst)).as_str()),
+    );
+    assert!(client.context().is_none());
+    let first = client.client().unwrap().session_public_key;
+    assert_eq!(first, client.client().unwrap().session_public_key);
+    session.store(1, Ordering::SeqCst);
+    let second = client.client().unwrap().session_public_key;
+    assert_ne!(first, second);
+    assert_eq!(
+        client.context(),
+        Some(&EncryptionContext::from("session-1"))
+    );
+    let data = DataPoint::random(rng);
+    let renewed = client.client().unwrap();
+    assert_eq!(data, renewed.decrypt(&renewed.encrypt(&data, rng)));
+}
+
+#[test]
+fn session_migration() {
+    let n = 3;
+    let rng = &mut OsRng;
+    let (_global_public_key, blinded_global_secret_key, blinding_factors) =
+        make_distributed_global_keys(n, rng);
+    let systems = (0..n)
+        .map(|i| {
+            PEPSystem::new(
+                PseudonymizationSecret::from(format!("ps-secret-{}", i).as_bytes().into()),
+                EncryptionSecret::from(format!("es-secret-{}", i).as_bytes().into()),
+                blinding_factors[i],
+            )
+        })
+        .collect::<Vec<_>>();
+    let mut builder = PEPClientBuilder::new(
+        blinded_global_secret_key,
+        systems
+            .iter()
+            .map(|s| InMemoryTransport::new(PEPSystemServer::new(s.clone())))
+            .collect(),
+    );
+    let ec_old = EncryptionContext::from("session-1");
+    let ec_new = EncryptionContext::from("session-2");
+    let old_client = builder.build(&ec_old).unwrap();
+    let new_client = builder.build(&ec_new).unwrap();
+
+    let pseudonyms = (0..10).map(|_| Pseudonym::random(rng)).collect::<Vec<_>>();
+    let data = (0..3).map(|_| DataPoint::random(rng)).collect::<Vec<_>>();
+    let mut enc_pseudonyms = pseudonyms
+        .iter()
+        .map(|x| old_client.encrypt(x, rng))
+        .collect::<Vec<_>>();
+    let mut enc_data = data
+        .iter()
+        .map(|x| old_client.encrypt(x, rng))
+        .collect::<Vec<_>>();
+
+    let remotes = systems
+        .iter()
+        .map(|s| RemoteTranscryptor::new(InMemoryTransport::new(PEPSystemServer::new(s.clone()))))
+        .collect::<Vec<_>>();
+    let migration = SessionMigration::request(&remotes, &ec_old, &ec_new).unwrap();
+    assert_eq!(
+        migration,
+        SessionMigration::from_systems(&systems, &ec_old, &ec_new)
+    );
+    assert!(migration.verify(&old_client, &new_client));
+    assert!(!migration.verify(&new_client, &old_client));
+
+    let mut reports = Vec::new();
+    migration.migrate_all(&mut enc_pseudonyms, 4, |p| reports.push(p.done));
+    assert_eq!(reports, vec![4, 8, 10]);
+    migration.migrate_all(&mut enc_data, 100, |_| {});
+    for (pseudonym, encrypted) in pseudonyms.iter().zip(&enc_pseudonyms) {
+        assert_eq!(*pseudonym, new_client.decrypt(encrypted));
+    }
+    for (data, encrypted) in data.iter().zip(&enc_data) {
+        assert_eq!(*data, new_client.decrypt(encrypted));
+    }
+    let mut reports = Vec::new();
+    migration.migrate_all::<EncryptedDataPoint, _>(&mut [], 100, |p| reports.push(p));
+    assert_eq!(reports, vec![MigrationProgress { done: 0, total: 0 }]);
+}
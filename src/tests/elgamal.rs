@@ -1,5 +1,7 @@
-use crate::internal::arithmetic::{GroupElement, ScalarNonZero, G};
-use crate::low_level::elgamal::{decrypt, encrypt, ElGamal};
+use crate::internal::arithmetic::{GroupElement, ScalarCanBeZero, ScalarNonZero, G};
+use crate::low_level::elgamal::{
+    decrypt, encrypt, encrypt_multi, ElGamal, MultiElGamal, ELGAMAL_LENGTH,
+};
 use rand_core::OsRng;
 
 #[test]
@@ -36,3 +38,75 @@ fn decode_encode() {
     let encoded = decoded.encode_as_base64();
     assert_eq!(original, encoded);
 }
+
+#[test]
+fn multi_recipient_encryption() {
+    let mut rng = OsRng;
+    let ys = (0..3)
+        .map(|_| ScalarNonZero::random(&mut rng))
+        .collect::<Vec<_>>();
+    let gys = ys.iter().map(|y| y * G).collect::<Vec<_>>();
+    let m = GroupElement::random(&mut rng);
+    let encrypted = encrypt_multi(&m, &gys, &mut rng);
+    assert_eq!(3, encrypted.len());
+    let split = encrypted.split();
+    for (y, x) in ys.iter().zip(&split) {
+        assert_eq!(m, decrypt(x, y));
+    }
+    // Every recipient has its own randomness
+    assert_ne!(split[0].gb, split[1].gb);
+    assert_eq!(Some(split[1]), encrypted.get(1));
+    assert_eq!(None, encrypted.get(3));
+
+    let encoded = encrypted.encode();
+    assert_eq!(3 * ELGAMAL_LENGTH, encoded.len());
+    assert_eq!(&split[2].encode()[..], &encoded[2 * ELGAMAL_LENGTH..]);
+    assert_eq!(
+        Some(encrypted.clone()),
+        MultiElGamal::decode_from_base64(&encrypted.encode_as_base64())
+    );
+    assert_eq!(None, MultiElGamal::decode_from_slice(&encoded[..32]));
+    assert_eq!(None, MultiElGamal::decode_from_slice(&encoded[1..]));
+}
+
+/// Recover the message from the ciphertexts `gc_a` and `gc_b` for public keys `gy_a` and
+/// `rho * gy_a`, assuming they share their randomness `r`: `r * gy_a = (gc_b - gc_a) / (rho - 1)`.
+fn recover_with_shared_randomness(
+    gc_a: &GroupElement,
+    gc_b: &GroupElement,
+    rho: &ScalarNonZero,
+) -> GroupElement {
+    let rho_minus_one: ScalarNonZero = (ScalarCanBeZero::from(*rho) - ScalarCanBeZero::one())
+        .try_into()
+        .unwrap();
+    let r_gy_a = rho_minus_one.invert() * (gc_b - gc_a);
+    gc_a - r_gy_a
+}
+
+#[test]
+fn multi_recipient_encryption_related_keys() {
+    let mut rng = OsRng;
+    // Public keys with a known ratio, like two session keys (or a session key and the global key)
+    let y = ScalarNonZero::random(&mut rng);
+    let rho = ScalarNonZero::random(&mut rng);
+    let gys = [y * G, rho * y * G];
+    let m = GroupElement::random(&mut rng);
+
+    // With shared randomness, the message can be recovered without a secret key
+    let r = ScalarNonZero::random(&mut rng);
+    assert_eq!(
+        m,
+        recover_with_shared_randomness(&(m + r * gys[0]), &(m + r * gys[1]), &rho)
+    );
+
+    // But not from a multi-recipient ciphertext
+    let encrypted = encrypt_multi(&m, &gys, &mut rng);
+    assert_ne!(
+        m,
+        recover_with_shared_randomness(
+            &encrypted.ciphertexts[0].gc,
+            &encrypted.ciphertexts[1].gc,
+            &rho
+        )
+    );
+}
@@ -2,6 +2,7 @@ use crate::high_level::contexts::*;
 use crate::high_level::data_types::*;
 use crate::high_level::keys::*;
 use crate::high_level::ops::*;
+use crate::internal::arithmetic::{ScalarCanBeZero, ScalarNonZero};
 use rand_core::OsRng;
 
 #[test]
@@ -20,14 +21,23 @@ fn test_high_level_flow() {
         make_session_keys(&global_secret, &enc_context1, &enc_secret);
     let (_session2_public, session2_secret) =
         make_session_keys(&global_secret, &enc_context2, &enc_secret);
+    // With separate session keys, pseudonyms have session keys of their own.
+    #[cfg(feature = "separate-session-keys")]
+    let make_pseudo_session_keys = make_pseudonym_session_keys;
+    #[cfg(not(feature = "separate-session-keys"))]
+    let make_pseudo_session_keys = make_session_keys;
+    let (pseudo_session1_public, pseudo_session1_secret) =
+        make_pseudo_session_keys(&global_secret, &enc_context1, &enc_secret);
+    let (_pseudo_session2_public, pseudo_session2_secret) =
+        make_pseudo_session_keys(&global_secret, &enc_context2, &enc_secret);
 
     let pseudo = Pseudonym::random(rng);
-    let enc_pseudo = encrypt(&pseudo, &session1_public, rng);
+    let enc_pseudo = encrypt(&pseudo, &pseudo_session1_public, rng);
 
     let data = DataPoint::random(rng);
     let enc_data = encrypt(&data, &session1_public, rng);
 
-    let dec_pseudo = decrypt(&enc_pseudo, &session1_secret);
+    let dec_pseudo = decrypt(&enc_pseudo, &pseudo_session1_secret);
     let dec_data = decrypt(&enc_data, &session1_secret);
 
     assert_eq!(pseudo, dec_pseudo);
@@ -41,14 +51,14 @@ fn test_high_level_flow() {
         assert_ne!(enc_pseudo, rr_pseudo);
         assert_ne!(enc_data, rr_data);
 
-        let rr_dec_pseudo = decrypt(&rr_pseudo, &session1_secret);
+        let rr_dec_pseudo = decrypt(&rr_pseudo, &pseudo_session1_secret);
         let rr_dec_data = decrypt(&rr_data, &session1_secret);
 
         assert_eq!(pseudo, rr_dec_pseudo);
         assert_eq!(data, rr_dec_data);
     }
 
-    let pseudo_info = PseudonymizationInfo::new(
+    let pseudo_info = TranscryptionInfo::new(
         &pseudo_context1,
         &pseudo_context2,
         &enc_context1,
@@ -64,12 +74,12 @@ fn test_high_level_flow() {
     assert_eq!(data, rekeyed_dec);
 
     let pseudonymized = transcrypt(&enc_pseudo, &pseudo_info);
-    let pseudonymized_dec = decrypt(&pseudonymized, &session2_secret);
+    let pseudonymized_dec = decrypt(&pseudonymized, &pseudo_session2_secret);
 
     assert_ne!(pseudo, pseudonymized_dec);
 
     let rev_pseudonymized = transcrypt(&pseudonymized, &pseudo_info.reverse());
-    let rev_pseudonymized_dec = decrypt(&rev_pseudonymized, &session1_secret);
+    let rev_pseudonymized_dec = decrypt(&rev_pseudonymized, &pseudo_session1_secret);
 
     assert_eq!(pseudo, rev_pseudonymized_dec);
 }
@@ -109,7 +119,11 @@ fn test_batch() {
     let rekey_info = RekeyInfo::from(transcryption_info);
 
     let _rekeyed = rekey_batch(&mut data_points, &rekey_info, rng);
-    let _pseudonymized = pseudonymize_batch(&mut pseudonyms, &transcryption_info, rng);
+    let _pseudonymized = pseudonymize_batch(
+        &mut pseudonyms,
+        &PseudonymizationInfo::from(transcryption_info),
+        rng,
+    );
 
     let mut data = vec![];
     for _ in 0..10 {
@@ -126,3 +140,123 @@ fn test_batch() {
 
     // TODO check that the batch is indeed shuffled
 }
+
+#[test]
+#[cfg(feature = "separate-session-keys")]
+fn test_separate_session_keys() {
+    let rng = &mut OsRng;
+    let (_global_public, global_secret) = make_global_keys(rng);
+    let pseudo_secret = PseudonymizationSecret::from("secret".into());
+    let enc_secret = EncryptionSecret::from("secret".into());
+
+    let pseudo_context1 = PseudonymizationContext::from("context1");
+    let enc_context1 = EncryptionContext::from("session1");
+    let pseudo_context2 = PseudonymizationContext::from("context2");
+    let enc_context2 = EncryptionContext::from("session2");
+
+    let (data1_public, data1_secret) =
+        make_session_keys(&global_secret, &enc_context1, &enc_secret);
+    let (pseudo1_public, pseudo1_secret) =
+        make_pseudonym_session_keys(&global_secret, &enc_context1, &enc_secret);
+    let (_data2_public, data2_secret) =
+        make_session_keys(&global_secret, &enc_context2, &enc_secret);
+    let (_pseudo2_public, pseudo2_secret) =
+        make_pseudonym_session_keys(&global_secret, &enc_context2, &enc_secret);
+    assert_ne!(data1_public, pseudo1_public);
+
+    let pseudo = Pseudonym::random(rng);
+    let enc_pseudo = encrypt(&pseudo, &pseudo1_public, rng);
+    let data = DataPoint::random(rng);
+    let enc_data = encrypt(&data, &data1_public, rng);
+
+    assert_eq!(pseudo, decrypt(&enc_pseudo, &pseudo1_secret));
+    // With elgamal3, decrypting with the wrong key panics, so compare the public keys instead.
+    #[cfg(not(feature = "elgamal3"))]
+    assert_ne!(pseudo, decrypt(&enc_pseudo, &data1_secret));
+    #[cfg(feature = "elgamal3")]
+    assert_ne!(enc_pseudo.value.gy, data1_public.0);
+    assert_eq!(data, decrypt(&enc_data, &data1_secret));
+
+    let transcryption_info = TranscryptionInfo::new(
+        &pseudo_context1,
+        &pseudo_context2,
+        &enc_context1,
+        &enc_context2,
+        &pseudo_secret,
+        &enc_secret,
+    );
+    let transcrypted_data = transcrypt(&enc_data, &transcryption_info);
+    assert_eq!(data, decrypt(&transcrypted_data, &data2_secret));
+
+    let transcrypted_pseudo = transcrypt(&enc_pseudo, &transcryption_info);
+    let rev_pseudo = transcrypt(&transcrypted_pseudo, &transcryption_info.reverse());
+    assert_eq!(pseudo, decrypt(&rev_pseudo, &pseudo1_secret));
+    // The pseudonym is transcrypted to the pseudonym session key, not the data point session key.
+    #[cfg(not(feature = "elgamal3"))]
+    assert_ne!(
+        decrypt(&transcrypted_pseudo, &pseudo2_secret),
+        decrypt(&transcrypted_pseudo, &data2_secret)
+    );
+    #[cfg(feature = "elgamal3")]
+    {
+        assert_eq!(transcrypted_pseudo.value.gy, _pseudo2_public.0);
+        assert_ne!(transcrypted_pseudo.value.gy, _data2_public.0);
+        let _ = decrypt(&transcrypted_pseudo, &pseudo2_secret);
+    }
+}
+
+#[test]
+fn multi_recipient_encryption() {
+    let rng = &mut OsRng;
+    let (global_public, global_secret) = make_global_keys(rng);
+    let enc_secret = EncryptionSecret::from("secret".into());
+    let (storage_public, storage_secret) = make_session_keys(
+        &global_secret,
+        &EncryptionContext::from("storage"),
+        &enc_secret,
+    );
+    let (audit_public, audit_secret) = make_session_keys(
+        &global_secret,
+        &EncryptionContext::from("audit"),
+        &enc_secret,
+    );
+
+    let data = DataPoint::random(rng);
+    let bundle = encrypt_multi(
+        &data,
+        &[&storage_public, &audit_public, &global_public],
+        rng,
+    );
+    let decoded =
+        EncryptedBundle::<EncryptedDataPoint>::decode_from_base64(&bundle.encode_as_base64())
+            .unwrap();
+    assert_eq!(bundle, decoded);
+    let json = serde_json::to_string(&bundle).unwrap();
+    assert_eq!(
+        bundle,
+        serde_json::from_str::<EncryptedBundle<EncryptedDataPoint>>(&json).unwrap()
+    );
+
+    let split = bundle.split();
+    assert_eq!(3, split.len());
+    assert_eq!(data, decrypt(&split[0], &storage_secret));
+    assert_eq!(data, decrypt(&bundle.get(1).unwrap(), &audit_secret));
+    assert_eq!(
+        data,
+        decrypt(&split[2], &SessionSecretKey::from(global_secret.0))
+    );
+
+    // Knowing the rekey factor between two of the session keys does not help to decrypt, as the
+    // ciphertexts do not share their randomness
+    let rho = RekeyInfo::new(
+        &EncryptionContext::from("storage"),
+        &EncryptionContext::from("audit"),
+        &enc_secret,
+    );
+    assert_eq!(audit_public.0, rho.0 * storage_public.0);
+    let rho_minus_one: ScalarNonZero = (ScalarCanBeZero::from(rho.0) - ScalarCanBeZero::one())
+        .try_into()
+        .unwrap();
+    let r_storage_public = rho_minus_one.invert() * (split[1].value.gc - split[0].value.gc);
+    assert_ne!(data.value, split[0].value.gc - r_storage_public);
+}
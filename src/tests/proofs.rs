@@ -0,0 +1,31 @@
+use crate::internal::arithmetic::*;
+use crate::low_level::proofs::*;
+use rand_core::OsRng;
+
+#[test]
+fn dleq_proof() {
+    let mut rng = OsRng;
+    let x = ScalarNonZero::random(&mut rng);
+    let h = GroupElement::random(&mut rng);
+    let a = x * G;
+    let b = x * h;
+
+    let proof = prove_dleq(&x, &G, &h, &mut rng);
+    assert!(verify_dleq(&proof, &G, &a, &h, &b));
+
+    // A different scalar for one of both points is rejected
+    let y = ScalarNonZero::random(&mut rng);
+    assert!(!verify_dleq(&proof, &G, &a, &h, &(y * h)));
+    assert!(!verify_dleq(&proof, &G, &(y * G), &h, &b));
+    let wrong_proof = prove_dleq(&y, &G, &h, &mut rng);
+    assert!(!verify_dleq(&wrong_proof, &G, &a, &h, &b));
+
+    let encoded = proof.encode();
+    assert_eq!(Some(proof), DleqProof::decode(&encoded));
+    assert_eq!(
+        Some(proof),
+        DleqProof::decode_from_base64(&proof.encode_as_base64())
+    );
+    let json = serde_json::to_string(&proof).unwrap();
+    assert_eq!(proof, serde_json::from_str::<DleqProof>(&json).unwrap());
+}
@@ -177,6 +177,12 @@ impl WASMPEPSystem {
     pub fn wasm_session_key_share(&self, context: &str) -> WASMSessionKeyShare {
         WASMSessionKeyShare(self.session_key_share(&EncryptionContext::from(context)))
     }
+    /// Generate a session key share for the session key for pseudonyms for the given encryption context.
+    #[cfg(feature = "separate-session-keys")]
+    #[wasm_bindgen(js_name = pseudonymSessionKeyShare)]
+    pub fn wasm_pseudonym_session_key_share(&self, context: &str) -> WASMSessionKeyShare {
+        WASMSessionKeyShare(self.pseudonym_session_key_share(&EncryptionContext::from(context)))
+    }
     /// Generate a rekey info to rekey from a given encryption context to another.
     #[wasm_bindgen(js_name = rekeyInfo)]
     pub fn wasm_rekey_info(&self, from_enc: &str, to_enc: &str) -> WASMRekeyInfo {
@@ -233,6 +239,7 @@ pub struct WASMPEPClient(PEPClient);
 #[wasm_bindgen(js_class = PEPClient)]
 impl WASMPEPClient {
     /// Create a new PEP client from the given session key shares.
+    #[cfg(not(feature = "separate-session-keys"))]
     #[wasm_bindgen(constructor)]
     pub fn new(
         blinded_global_private_key: &WASMBlindedGlobalSecretKey,
@@ -251,6 +258,29 @@ impl WASMPEPClient {
             &session_key_shares,
         ))
     }
+    /// Create a new PEP client from the given session key shares for data points and for pseudonyms.
+    #[cfg(feature = "separate-session-keys")]
+    #[wasm_bindgen(constructor)]
+    pub fn new(
+        blinded_global_private_key: &WASMBlindedGlobalSecretKey,
+        session_key_shares: Vec<WASMSessionKeyShare>,
+        pseudonym_session_key_shares: Vec<WASMSessionKeyShare>,
+    ) -> Self {
+        let session_key_shares: Vec<SessionKeyShare> = session_key_shares
+            .into_iter()
+            .map(|x| SessionKeyShare(x.0 .0))
+            .collect();
+        let pseudonym_session_key_shares: Vec<SessionKeyShare> = pseudonym_session_key_shares
+            .into_iter()
+            .map(|x| SessionKeyShare(x.0 .0))
+            .collect();
+        let blinded_key = blinded_global_private_key.0;
+        Self(PEPClient::new(
+            BlindedGlobalSecretKey(blinded_key.0),
+            &session_key_shares,
+            &pseudonym_session_key_shares,
+        ))
+    }
     /// Decrypt an encrypted pseudonym.
     #[wasm_bindgen(js_name = decryptPseudonym)]
     pub fn wasm_decrypt_pseudonym(&self, encrypted: &WASMEncryptedPseudonym) -> WASMPseudonym {
@@ -0,0 +1,1055 @@
+#![cfg(feature = "build-binary")]
+
+use serde_json::Value;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+/// Run `peppy --json` with the given arguments and input on stdin, and return its exit code and
+/// the JSON objects it printed (one per line).
+fn peppy_with_input(args: &[&str], input: &str) -> (i32, Vec<Value>) {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_peppy"))
+        .arg("--json")
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    let objects = String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    (output.status.code().unwrap(), objects)
+}
+
+/// Run `peppy --json` with the given arguments, which must succeed, and return its output.
+fn peppy(args: &[&str]) -> Value {
+    let (code, mut objects) = peppy_with_input(args, "");
+    assert_eq!(code, 0, "peppy {:?} failed: {:?}", args, objects);
+    assert_eq!(objects.len(), 1);
+    objects.remove(0)
+}
+
+/// Run `peppy --json` with the given arguments, which must fail, and return the kind of error.
+fn peppy_error(args: &[&str], code: i32) -> String {
+    let (actual, objects) = peppy_with_input(args, "");
+    assert_eq!(actual, code, "peppy {:?}: {:?}", args, objects);
+    objects[0]["error"]["kind"].as_str().unwrap().to_string()
+}
+
+fn field(value: &Value, name: &str) -> String {
+    value[name]
+        .as_str()
+        .unwrap_or_else(|| panic!("no field {} in {}", name, value))
+        .to_string()
+}
+
+fn temp_file(name: &str, contents: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("peppy-test-{}-{}", std::process::id(), name));
+    std::fs::write(&path, contents).unwrap();
+    path
+}
+
+struct Keys {
+    global_public_key: String,
+    global_secret_key: String,
+}
+
+fn global_keys() -> Keys {
+    let keys = peppy(&["generate-global-keys"]);
+    Keys {
+        global_public_key: field(&keys, "global_public_key"),
+        global_secret_key: field(&keys, "global_secret_key"),
+    }
+}
+
+/// The public and secret key of a session.
+fn session_keys(keys: &Keys, session: &str) -> (String, String) {
+    let session_keys = peppy(&[
+        "generate-session-keys",
+        "--global-secret-key",
+        &keys.global_secret_key,
+        "--encryption-secret",
+        "encryption-secret",
+        "--session",
+        session,
+    ]);
+    (
+        field(&session_keys, "session_public_key"),
+        field(&session_keys, "session_secret_key"),
+    )
+}
+
+/// The public and secret key of a session for pseudonyms, which are separate keys with the
+/// `separate-session-keys` feature.
+fn pseudonym_session_keys(keys: &Keys, session: &str) -> (String, String) {
+    #[cfg(feature = "separate-session-keys")]
+    {
+        let session_keys = peppy(&[
+            "generate-session-keys",
+            "--global-secret-key",
+            &keys.global_secret_key,
+            "--encryption-secret",
+            "encryption-secret",
+            "--session",
+            session,
+        ]);
+        (
+            field(&session_keys, "pseudonym_session_public_key"),
+            field(&session_keys, "pseudonym_session_secret_key"),
+        )
+    }
+    #[cfg(not(feature = "separate-session-keys"))]
+    session_keys(keys, session)
+}
+
+/// The session keys for a pseudonym (or data point).
+fn message_session_keys(keys: &Keys, session: &str, data_point: bool) -> (String, String) {
+    if data_point {
+        session_keys(keys, session)
+    } else {
+        pseudonym_session_keys(keys, session)
+    }
+}
+
+/// The option of a session key for a pseudonym (or data point).
+fn key_option(kind: &'static str, data_point: bool) -> &'static str {
+    match (
+        kind,
+        data_point || cfg!(not(feature = "separate-session-keys")),
+    ) {
+        ("public", true) => "--public-key",
+        ("public", false) => "--pseudonym-public-key",
+        (_, true) => "--secret-key",
+        (_, false) => "--pseudonym-secret-key",
+    }
+}
+
+/// The key options of `encrypt-csv`, with a separate key for pseudonyms with the
+/// `separate-session-keys` feature.
+fn csv_key_args<'a>(public_key: &'a str, pseudonym_public_key: &'a str) -> Vec<&'a str> {
+    let mut args = vec!["--public-key", public_key];
+    if cfg!(feature = "separate-session-keys") {
+        args.extend(["--pseudonym-public-key", pseudonym_public_key]);
+    }
+    args
+}
+
+fn encrypt(public_key: &str, message: &str, data_point: bool) -> String {
+    let mut args = vec!["encrypt", key_option("public", data_point), public_key];
+    if data_point {
+        args.push("-d");
+    }
+    args.extend(["--", message]);
+    field(&peppy(&args), "ciphertext")
+}
+
+fn decrypt(secret_key: &str, ciphertext: &str, data_point: bool) -> String {
+    let mut args = vec!["decrypt", key_option("secret", data_point), secret_key];
+    if data_point {
+        args.push("-d");
+    }
+    args.extend(["--", ciphertext]);
+    field(&peppy(&args), "plaintext")
+}
+
+const SECRETS: [&str; 4] = [
+    "--pseudonymization-secret",
+    "pseudonymization-secret",
+    "--encryption-secret",
+    "encryption-secret",
+];
+const PSEUDONYMIZATION_CONTEXTS: [&str; 4] = [
+    "--pseudonymization-from",
+    "domain-a",
+    "--pseudonymization-to",
+    "domain-b",
+];
+
+#[test]
+fn test_session_keys() {
+    let keys = global_keys();
+    let (public_key, secret_key) = session_keys(&keys, "session-1");
+    assert_eq!(session_keys(&keys, "session-1"), (public_key, secret_key));
+    assert_ne!(
+        session_keys(&keys, "session-2").0,
+        session_keys(&keys, "session-1").0
+    );
+}
+
+#[test]
+fn test_pseudonym_origin() {
+    let pseudonym = field(&peppy(&["pseudonym-from-origin", "patient42"]), "pseudonym");
+    let origin = peppy(&["pseudonym-to-origin", &pseudonym]);
+    assert_eq!(field(&origin, "origin"), "patient42");
+    // The default mode is pkcs7.
+    assert_eq!(
+        pseudonym,
+        field(
+            &peppy(&["pseudonym-from-origin", "--mode", "pkcs7", "patient42"]),
+            "pseudonym"
+        )
+    );
+    assert_eq!(
+        peppy_error(
+            &["pseudonym-from-origin", "an identifier that is too long"],
+            1
+        ),
+        "invalid_input"
+    );
+
+    let random = field(&peppy(&["random-pseudonym"]), "pseudonym");
+    assert_eq!(random.len(), 64);
+    assert_ne!(random, field(&peppy(&["random-pseudonym"]), "pseudonym"));
+}
+
+#[test]
+fn test_pseudonym_origin_modes() {
+    let from_origin = |mode: &str, origin: &str| -> Value {
+        peppy(&["pseudonym-from-origin", "--mode", mode, origin])
+    };
+    let to_origin = |mode: &str, pseudonyms: &[String]| -> String {
+        let mut args = vec!["pseudonym-to-origin", "--mode", mode];
+        args.extend(pseudonyms.iter().map(String::as_str));
+        field(&peppy(&args), "origin")
+    };
+
+    let pseudonym = field(&from_origin("pkcs7", "abc"), "pseudonym");
+    assert_ne!(pseudonym, field(&from_origin("zero", "abc"), "pseudonym"));
+    assert_eq!(to_origin("pkcs7", std::slice::from_ref(&pseudonym)), "abc");
+    // A single block is the same in both modes.
+    assert_eq!(to_origin("blocks", &[pseudonym]), "abc");
+    assert_eq!(
+        peppy_error(
+            &[
+                "pseudonym-from-origin",
+                "--mode",
+                "pkcs7",
+                "0123456789abcdef"
+            ],
+            1
+        ),
+        "invalid_input"
+    );
+
+    let origin = "an identifier that is longer than 16 bytes";
+    let pseudonyms: Vec<String> = from_origin("blocks", origin)["pseudonyms"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|x| x.as_str().unwrap().to_string())
+        .collect();
+    assert_eq!(pseudonyms.len(), 3);
+    assert_eq!(to_origin("blocks", &pseudonyms), origin);
+    assert_eq!(
+        peppy_error(
+            &["pseudonym-to-origin", "--mode", "pkcs7", &pseudonyms[0]],
+            1
+        ),
+        "invalid_input"
+    );
+
+    let hashed = field(&from_origin("hash", origin), "pseudonym");
+    assert_eq!(hashed, field(&from_origin("hash", origin), "pseudonym"));
+    let labelled = peppy(&[
+        "pseudonym-from-origin",
+        "--mode",
+        "hash",
+        "--label",
+        "other",
+        origin,
+    ]);
+    assert_ne!(hashed, field(&labelled, "pseudonym"));
+    assert_eq!(
+        peppy_error(&["pseudonym-to-origin", "--mode", "hash", &hashed], 2),
+        "usage"
+    );
+    assert_eq!(
+        peppy_error(&["pseudonym-from-origin", "--mode", "other", origin], 2),
+        "usage"
+    );
+}
+
+#[test]
+fn test_data_points() {
+    let data_point = field(&peppy(&["data-point-from-string", "hello"]), "data_point");
+    let string = peppy(&["data-point-to-string", &data_point]);
+    assert_eq!(field(&string, "string"), "hello");
+    for string in ["", "fifteen bytes!!", "ends in \u{1}"] {
+        let data_point = field(&peppy(&["data-point-from-string", string]), "data_point");
+        let result = peppy(&["data-point-to-string", &data_point]);
+        assert_eq!(field(&result, "string"), string);
+    }
+    assert_eq!(
+        peppy_error(&["data-point-from-string", "sixteen bytes!!!"], 1),
+        "invalid_input"
+    );
+
+    let bytes = "000102030405060708090a0b0c0d0e0f";
+    let from_bytes = field(&peppy(&["data-point-from-bytes", bytes]), "data_point");
+    assert_eq!(
+        from_bytes,
+        field(&peppy(&["data-point-from-bytes", bytes]), "data_point")
+    );
+    assert_eq!(
+        peppy_error(&["data-point-from-bytes", "0001"], 1),
+        "invalid_input"
+    );
+
+    let from_hash = field(&peppy(&["data-point-from-hash", "hello"]), "data_point");
+    assert_ne!(from_hash, data_point);
+    assert_eq!(
+        peppy_error(&["data-point-to-string", &from_hash], 1),
+        "invalid_input"
+    );
+
+    let random = field(&peppy(&["random-data-point"]), "data_point");
+    assert_ne!(random, field(&peppy(&["random-data-point"]), "data_point"));
+}
+
+#[test]
+fn test_encrypt_decrypt() {
+    let keys = global_keys();
+    let (public_key, secret_key) = session_keys(&keys, "session");
+    let (pseudonym_public_key, pseudonym_secret_key) = pseudonym_session_keys(&keys, "session");
+    let pseudonym = field(&peppy(&["random-pseudonym"]), "pseudonym");
+    let data_point = field(&peppy(&["random-data-point"]), "data_point");
+
+    let ciphertext = encrypt(&pseudonym_public_key, &pseudonym, false);
+    assert_eq!(
+        decrypt(&pseudonym_secret_key, &ciphertext, false),
+        pseudonym
+    );
+    let ciphertext = encrypt(&public_key, &data_point, true);
+    assert_eq!(decrypt(&secret_key, &ciphertext, true), data_point);
+}
+
+#[test]
+fn test_encrypt_global() {
+    let keys = global_keys();
+    let data_point = field(&peppy(&["random-data-point"]), "data_point");
+    let ciphertext = field(
+        &peppy(&[
+            "encrypt-global",
+            "-d",
+            "--public-key",
+            &keys.global_public_key,
+            &data_point,
+        ]),
+        "ciphertext",
+    );
+    assert_eq!(
+        decrypt(&keys.global_secret_key, &ciphertext, true),
+        data_point
+    );
+}
+
+#[test]
+fn test_rerandomize() {
+    let keys = global_keys();
+    let (public_key, secret_key) = pseudonym_session_keys(&keys, "session");
+    let pseudonym = field(&peppy(&["random-pseudonym"]), "pseudonym");
+    let ciphertext = encrypt(&public_key, &pseudonym, false);
+
+    #[cfg(not(feature = "elgamal3"))]
+    let rerandomized = peppy(&[
+        "rerandomize",
+        "--public-key",
+        &public_key,
+        "--",
+        &ciphertext,
+    ]);
+    #[cfg(feature = "elgamal3")]
+    let rerandomized = peppy(&["rerandomize", "--", &ciphertext]);
+    let rerandomized = field(&rerandomized, "ciphertext");
+    assert_ne!(rerandomized, ciphertext);
+    assert_eq!(decrypt(&secret_key, &rerandomized, false), pseudonym);
+}
+
+/// Transcrypting from global gives the same result as transcrypting a ciphertext of the same
+/// message from a session, and transcrypting to global can be decrypted with the global key.
+fn check_transcrypt(data_point: bool) {
+    let keys = global_keys();
+    let (public_key_1, _) = message_session_keys(&keys, "session-1", data_point);
+    let (_, secret_key_2) = message_session_keys(&keys, "session-2", data_point);
+    let message = if data_point {
+        field(&peppy(&["random-data-point"]), "data_point")
+    } else {
+        field(&peppy(&["random-pseudonym"]), "pseudonym")
+    };
+    let transcrypt = |subcommand: &str, sessions: &[&str], ciphertext: &str| -> String {
+        let mut args = vec![subcommand];
+        if data_point {
+            args.push("-d");
+        }
+        args.extend(SECRETS);
+        args.extend(PSEUDONYMIZATION_CONTEXTS);
+        args.extend(sessions);
+        args.extend(["--", ciphertext]);
+        field(&peppy(&args), "ciphertext")
+    };
+
+    let ciphertext = encrypt(&public_key_1, &message, data_point);
+    let transcrypted = transcrypt(
+        "transcrypt",
+        &["--session-from", "session-1", "--session-to", "session-2"],
+        &ciphertext,
+    );
+    let expected = decrypt(&secret_key_2, &transcrypted, data_point);
+    if data_point {
+        assert_eq!(expected, message);
+    } else {
+        assert_ne!(expected, message);
+    }
+
+    let mut args = vec!["encrypt-global", "--public-key", &keys.global_public_key];
+    if data_point {
+        args.push("-d");
+    }
+    args.push(&message);
+    let global_ciphertext = field(&peppy(&args), "ciphertext");
+    let transcrypted = transcrypt(
+        "transcrypt-from-global",
+        &["--session-to", "session-2"],
+        &global_ciphertext,
+    );
+    assert_eq!(decrypt(&secret_key_2, &transcrypted, data_point), expected);
+
+    let transcrypted = transcrypt(
+        "transcrypt-to-global",
+        &["--session-from", "session-1"],
+        &ciphertext,
+    );
+    assert_eq!(
+        decrypt(&keys.global_secret_key, &transcrypted, data_point),
+        expected
+    );
+}
+
+#[test]
+fn test_transcrypt_pseudonym() {
+    check_transcrypt(false);
+}
+
+#[test]
+fn test_transcrypt_data_point() {
+    check_transcrypt(true);
+}
+
+#[test]
+fn test_rekey() {
+    let keys = global_keys();
+    let (public_key_1, _) = session_keys(&keys, "session-1");
+    let (_, secret_key_2) = session_keys(&keys, "session-2");
+    let data_point = field(&peppy(&["random-data-point"]), "data_point");
+    let ciphertext = encrypt(&public_key_1, &data_point, true);
+    let secret = ["--encryption-secret", "encryption-secret"];
+
+    let mut args = vec!["rekey"];
+    args.extend(secret);
+    args.extend(["--session-from", "session-1", "--session-to", "session-2"]);
+    args.extend(["--", &ciphertext]);
+    let rekeyed = field(&peppy(&args), "ciphertext");
+    assert_eq!(decrypt(&secret_key_2, &rekeyed, true), data_point);
+
+    let mut args = vec!["rekey-to-global"];
+    args.extend(secret);
+    args.extend(["--session-from", "session-1", "--", &ciphertext]);
+    let global = field(&peppy(&args), "ciphertext");
+    assert_eq!(decrypt(&keys.global_secret_key, &global, true), data_point);
+
+    let mut args = vec!["rekey-from-global"];
+    args.extend(secret);
+    args.extend(["--session-to", "session-2", "--", &global]);
+    let rekeyed = field(&peppy(&args), "ciphertext");
+    assert_eq!(decrypt(&secret_key_2, &rekeyed, true), data_point);
+}
+
+#[test]
+fn test_batch() {
+    let keys = global_keys();
+    let (public_key_1, _) = session_keys(&keys, "session-1");
+    let (_, secret_key_2) = session_keys(&keys, "session-2");
+    let data_points: Vec<String> = (0..3)
+        .map(|_| field(&peppy(&["random-data-point"]), "data_point"))
+        .collect();
+    let lines = |objects: Vec<Value>, name: &str| -> String {
+        objects
+            .iter()
+            .map(|x| field(x, name) + "\n")
+            .collect::<String>()
+    };
+
+    let (code, encrypted) = peppy_with_input(
+        &["encrypt-batch", "-d", "--public-key", &public_key_1],
+        &(data_points.join("\n") + "\n"),
+    );
+    assert_eq!(code, 0);
+    assert_eq!(encrypted.len(), 3);
+
+    #[cfg(not(feature = "elgamal3"))]
+    let args = ["rerandomize-batch", "--public-key", &public_key_1];
+    #[cfg(feature = "elgamal3")]
+    let args = ["rerandomize-batch"];
+    let (code, rerandomized) = peppy_with_input(&args, &lines(encrypted, "ciphertext"));
+    assert_eq!(code, 0);
+
+    let mut args = vec!["transcrypt-batch", "-d"];
+    args.extend(SECRETS);
+    args.extend(PSEUDONYMIZATION_CONTEXTS);
+    args.extend(["--session-from", "session-1", "--session-to", "session-2"]);
+    let (code, transcrypted) = peppy_with_input(&args, &lines(rerandomized, "ciphertext"));
+    assert_eq!(code, 0);
+
+    let (code, decrypted) = peppy_with_input(
+        &["decrypt-batch", "-d", "--secret-key", &secret_key_2],
+        &lines(transcrypted, "ciphertext"),
+    );
+    assert_eq!(code, 0);
+    let decrypted: Vec<String> = decrypted.iter().map(|x| field(x, "plaintext")).collect();
+    assert_eq!(decrypted, data_points);
+
+    let (code, objects) = peppy_with_input(
+        &["decrypt-batch", "-d", "--secret-key", &secret_key_2],
+        "invalid\n",
+    );
+    assert_eq!(code, 1);
+    assert_eq!(objects[0]["error"]["kind"], "invalid_input");
+}
+
+#[test]
+fn test_csv() {
+    let keys = global_keys();
+    let (public_key_1, _) = session_keys(&keys, "session-1");
+    let (pseudonym_public_key_1, _) = pseudonym_session_keys(&keys, "session-1");
+    let (_, secret_key_2) = session_keys(&keys, "session-2");
+    let table = "id,name,year\npatient1,a name longer than sixteen bytes,1970\n";
+    let input = temp_file("table.csv", table);
+
+    let output = Command::new(env!("CARGO_BIN_EXE_peppy"))
+        .args(["encrypt-csv", "-p", "id", "-k", "year", "-i"])
+        .arg(&input)
+        .args(csv_key_args(&public_key_1, &pseudonym_public_key_1))
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let encrypted = temp_file("encrypted.csv", &String::from_utf8(output.stdout).unwrap());
+
+    let mut args = vec!["transcrypt-csv", "-p", "id", "-k", "year"];
+    args.extend(SECRETS);
+    args.extend(PSEUDONYMIZATION_CONTEXTS);
+    args.extend([
+        "--session-from",
+        "session-1",
+        "--session-to",
+        "session-2",
+        "-i",
+    ]);
+    let output = Command::new(env!("CARGO_BIN_EXE_peppy"))
+        .args(args)
+        .arg(&encrypted)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let transcrypted = String::from_utf8(output.stdout).unwrap();
+    let rows: Vec<&str> = transcrypted.lines().collect();
+    assert_eq!(rows[0], "id,name,year");
+    let values: Vec<&str> = rows[1].split(',').collect();
+    assert_eq!(values[2], "1970");
+
+    let mut args = vec!["data-point-to-string".to_string()];
+    args.extend(
+        values[1]
+            .split(':')
+            .map(|block| decrypt(&secret_key_2, block, true)),
+    );
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    assert_eq!(
+        field(&peppy(&args), "string"),
+        "a name longer than sixteen bytes"
+    );
+
+    std::fs::remove_file(input).unwrap();
+    std::fs::remove_file(encrypted).unwrap();
+}
+
+#[test]
+fn test_csv_modes() {
+    let keys = global_keys();
+    let (public_key, secret_key) = session_keys(&keys, "session");
+    let (pseudonym_public_key, pseudonym_secret_key) = pseudonym_session_keys(&keys, "session");
+    // A value of exactly 16 bytes and one ending in a zero byte, which must both round-trip.
+    let table = "id,value\npatient1,sixteen bytes!!!\npatient2,ends in zero\u{0}\n";
+    let input = temp_file("modes.csv", table);
+    let input = input.to_str().unwrap();
+    let encrypt_csv = |extra: &[&str]| -> Vec<Vec<String>> {
+        let output = Command::new(env!("CARGO_BIN_EXE_peppy"))
+            .args(["encrypt-csv", "-p", "id", "-i", input])
+            .args(csv_key_args(&public_key, &pseudonym_public_key))
+            .args(extra)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        String::from_utf8(output.stdout)
+            .unwrap()
+            .lines()
+            .skip(1)
+            .map(|row| row.split(',').map(str::to_string).collect())
+            .collect()
+    };
+
+    for (extra, mode, label) in [
+        (&["--mode", "pkcs7"][..], "pkcs7", None),
+        (&["--mode", "hash"][..], "hash", None),
+        (
+            &["--mode", "hash", "--label", "study"][..],
+            "hash",
+            Some("study"),
+        ),
+    ] {
+        let rows = encrypt_csv(extra);
+        for (row, (id, value)) in rows.iter().zip([
+            ("patient1", "sixteen bytes!!!"),
+            ("patient2", "ends in zero\u{0}"),
+        ]) {
+            let mut args = vec!["pseudonym-from-origin", "--mode", mode];
+            if let Some(label) = label {
+                args.extend(["--label", label]);
+            }
+            args.push(id);
+            assert_eq!(
+                decrypt(&pseudonym_secret_key, &row[0], false),
+                field(&peppy(&args), "pseudonym")
+            );
+
+            let mut args = vec!["data-point-to-string".to_string()];
+            args.extend(
+                row[1]
+                    .split(':')
+                    .map(|block| decrypt(&secret_key, block, true)),
+            );
+            let args: Vec<&str> = args.iter().map(String::as_str).collect();
+            assert_eq!(field(&peppy(&args), "string"), value);
+        }
+    }
+    // A value of 16 bytes is padded with a full block.
+    assert_eq!(encrypt_csv(&["--mode", "hash"])[0][1].split(':').count(), 2);
+
+    let mut args = vec!["encrypt-csv", "-p", "id", "-i", input];
+    args.extend(csv_key_args(&public_key, &pseudonym_public_key));
+    let with = |extra: &[&'static str]| -> Vec<&str> {
+        args.iter().copied().chain(extra.iter().copied()).collect()
+    };
+    assert_eq!(peppy_error(&with(&["--mode", "blocks"]), 2), "usage");
+    assert_eq!(
+        peppy_error(&with(&["--mode", "pkcs7", "--label", "study"]), 2),
+        "usage"
+    );
+    let long = temp_file("long.csv", "id\nan identifier of more than 15 bytes\n");
+    let output = Command::new(env!("CARGO_BIN_EXE_peppy"))
+        .args(["encrypt-csv", "-p", "id", "--mode", "pkcs7", "-i"])
+        .arg(&long)
+        .args(csv_key_args(&public_key, &pseudonym_public_key))
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+
+    std::fs::remove_file(input).unwrap();
+    std::fs::remove_file(long).unwrap();
+}
+
+#[test]
+fn test_distributed() {
+    let setup = peppy(&["setup-distributed", "2"]);
+    let blinded_global_secret_key = field(&setup, "blinded_global_secret_key");
+    let blinding_factors: Vec<String> = setup["blinding_factors"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|x| x.as_str().unwrap().to_string())
+        .collect();
+    assert_eq!(blinding_factors.len(), 2);
+
+    let configs: Vec<PathBuf> = blinding_factors
+        .iter()
+        .enumerate()
+        .map(|(i, blinding_factor)| {
+            let config = serde_json::json!({
+                "pseudonymization_secret": format!("pseudonymization-secret-{}", i),
+                "encryption_secret": format!("encryption-secret-{}", i),
+                "blinding_factor": blinding_factor,
+            });
+            temp_file(&format!("system-{}.json", i), &config.to_string())
+        })
+        .collect();
+    // The blinded global secret key can be given like a secret.
+    let key_file = temp_file("blinded-global-secret-key", &blinded_global_secret_key);
+    let blinded_global_secret_key_file = format!("file:{}", key_file.to_str().unwrap());
+    // The keys of a session for pseudonyms.
+    let session_keys = |session: &str| -> (String, String) {
+        let shares: Vec<Value> = configs
+            .iter()
+            .map(|config| {
+                peppy(&[
+                    "--config",
+                    config.to_str().unwrap(),
+                    "session-key-share",
+                    "--session",
+                    session,
+                ])
+            })
+            .collect();
+        let mut args = vec![
+            "combine-session-key-shares".to_string(),
+            "--blinded-global-secret-key".to_string(),
+            blinded_global_secret_key_file.clone(),
+        ];
+        if cfg!(feature = "separate-session-keys") {
+            for share in &shares {
+                args.push("-p".to_string());
+                args.push(field(share, "pseudonym_session_key_share"));
+            }
+        }
+        args.extend(shares.iter().map(|share| field(share, "session_key_share")));
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        let keys = peppy(&args);
+        let prefix = if cfg!(feature = "separate-session-keys") {
+            "pseudonym_"
+        } else {
+            ""
+        };
+        (
+            field(&keys, &format!("{}session_public_key", prefix)),
+            field(&keys, &format!("{}session_secret_key", prefix)),
+        )
+    };
+    let (public_key_1, _) = session_keys("session-1");
+    let (_, secret_key_2) = session_keys("session-2");
+
+    let pseudonym = field(&peppy(&["random-pseudonym"]), "pseudonym");
+    let mut ciphertext = encrypt(&public_key_1, &pseudonym, false);
+    for config in &configs {
+        let mut args = vec!["--config", config.to_str().unwrap(), "system-transcrypt"];
+        args.extend(PSEUDONYMIZATION_CONTEXTS);
+        args.extend(["--session-from", "session-1", "--session-to", "session-2"]);
+        args.extend(["--", &ciphertext]);
+        ciphertext = field(&peppy(&args), "ciphertext");
+    }
+    let decrypted = decrypt(&secret_key_2, &ciphertext, false);
+    assert_eq!(decrypted.len(), 64);
+    assert_ne!(decrypted, pseudonym);
+
+    for config in configs {
+        std::fs::remove_file(config).unwrap();
+    }
+    std::fs::remove_file(key_file).unwrap();
+}
+
+#[test]
+fn test_inspect() {
+    let keys = global_keys();
+    let pseudonym = field(&peppy(&["pseudonym-from-origin", "patient42"]), "pseudonym");
+    let inspected = peppy(&["inspect", &pseudonym]);
+    let candidate = &inspected["interpretations"][0]["candidates"][0];
+    assert_eq!(candidate["type"], "group_element");
+    assert_eq!(candidate["lizard_text"], "patient42");
+
+    let ciphertext = encrypt(&keys.global_public_key, &pseudonym, false);
+    let inspected = peppy(&[
+        "inspect",
+        "--secret-key",
+        &keys.global_secret_key,
+        "--",
+        &ciphertext,
+    ]);
+    let candidate = &inspected["interpretations"][0]["candidates"][0];
+    assert_eq!(candidate["type"], "elgamal");
+    assert_eq!(candidate["decrypted"]["value"], pseudonym.as_str());
+
+    assert_eq!(peppy_error(&["inspect", "not-a-value"], 1), "invalid_input");
+}
+
+#[test]
+fn test_argument_errors() {
+    // Missing options are usage errors.
+    assert_eq!(
+        peppy_error(&["transcrypt", "--encryption-secret", "secret", "x"], 2),
+        "usage"
+    );
+    assert_eq!(peppy_error(&["encrypt", "00"], 2), "usage");
+    // So are command lines that cannot be parsed.
+    assert_eq!(peppy_error(&["encrypt", "--bogus", "x"], 2), "usage");
+    assert_eq!(peppy_error(&["encrypt"], 2), "usage");
+    assert_eq!(peppy_error(&[], 2), "usage");
+    // Invalid values are invalid input.
+    assert_eq!(
+        peppy_error(&["encrypt", "-d", "--public-key", "00", "00"], 1),
+        "invalid_input"
+    );
+    assert_eq!(
+        peppy_error(&["decrypt", "-d", "--secret-key", "00", "x"], 1),
+        "invalid_input"
+    );
+    let keys = global_keys();
+    let mut args = vec!["rekey"];
+    args.extend(["--encryption-secret", "secret", "--session-from", "a"]);
+    args.extend(["--session-to", "b", "invalid"]);
+    assert_eq!(peppy_error(&args, 1), "invalid_input");
+    // Files that cannot be read are I/O errors.
+    assert_eq!(
+        peppy_error(
+            &[
+                "generate-session-keys",
+                "--global-secret-key",
+                &keys.global_secret_key,
+                "--encryption-secret",
+                "file:/nonexistent/secret",
+                "--session",
+                "session",
+            ],
+            3
+        ),
+        "io"
+    );
+    assert_eq!(
+        peppy_error(
+            &["--config", "/nonexistent/config.json", "random-pseudonym"],
+            3
+        ),
+        "io"
+    );
+}
+
+#[test]
+fn test_command_line_errors() {
+    let cases: &[(&[&str], &str)] = &[
+        (&["encrypt", "--bogus", "x"], "Unknown option --bogus."),
+        (&["encrypt", "-d"], "Missing message-hex for encrypt."),
+        (&[], "Missing subcommand (see peppy --help)."),
+        (&["bogus"], "Unknown subcommand bogus (see peppy --help)."),
+        (
+            &["pseudonym-from-origin", "a", "b"],
+            "Too many values for pseudonym-from-origin: expected at most 1 origin, not 2.",
+        ),
+        (
+            &["encrypt-batch", "x"],
+            "Unexpected value x for encrypt-batch.",
+        ),
+        (
+            &["encrypt", "-d", "--public-key"],
+            "Missing value of option --public-key.",
+        ),
+        (&["--bogus", "random-pseudonym"], "Unknown option --bogus."),
+    ];
+    for (args, message) in cases {
+        let (code, objects) = peppy_with_input(args, "");
+        assert_eq!(code, 2, "peppy {:?}", args);
+        assert_eq!(objects[0]["error"]["kind"], "usage");
+        assert_eq!(objects[0]["error"]["message"], *message);
+        // Without --json, the message is written to stderr.
+        let output = Command::new(env!("CARGO_BIN_EXE_peppy"))
+            .args(*args)
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(2), "peppy {:?}", args);
+        assert!(output.stdout.is_empty());
+        assert_eq!(String::from_utf8(output.stderr).unwrap().trim(), *message);
+    }
+}
+
+#[test]
+fn test_options_from_config() {
+    let keys = global_keys();
+    let setup = peppy(&["setup-distributed", "1"]);
+    let config = temp_file(
+        "config.json",
+        &serde_json::json!({
+            "pseudonymization_secret": "pseudonymization-secret",
+            "encryption_secret": "encryption-secret",
+            "blinding_factor": setup["blinding_factors"][0],
+            "factor_cache_capacity": 16,
+            "contexts": {"session_to": "session"},
+        })
+        .to_string(),
+    );
+    let from_config = peppy(&[
+        "--config",
+        config.to_str().unwrap(),
+        "generate-session-keys",
+        "--global-secret-key",
+        &keys.global_secret_key,
+    ]);
+    assert_eq!(
+        (
+            field(&from_config, "session_public_key"),
+            field(&from_config, "session_secret_key")
+        ),
+        session_keys(&keys, "session")
+    );
+    // Options that are not in the configuration file are still required.
+    assert_eq!(
+        peppy_error(
+            &[
+                "--config",
+                config.to_str().unwrap(),
+                "transcrypt",
+                "--",
+                "x"
+            ],
+            2
+        ),
+        "usage"
+    );
+    // The configuration file of a transcryptor is complete.
+    let incomplete = temp_file(
+        "incomplete.json",
+        &serde_json::json!({"encryption_secret": "encryption-secret"}).to_string(),
+    );
+    assert_eq!(
+        peppy_error(
+            &["--config", incomplete.to_str().unwrap(), "random-pseudonym"],
+            1
+        ),
+        "invalid_input"
+    );
+    std::fs::remove_file(config).unwrap();
+    std::fs::remove_file(incomplete).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn test_serve() {
+    use std::io::{BufRead, BufReader};
+    use std::os::unix::fs::PermissionsExt;
+    use std::os::unix::net::UnixStream;
+
+    let setup = peppy(&["setup-distributed", "1"]);
+    let blinded_global_secret_key = field(&setup, "blinded_global_secret_key");
+    let config = temp_file(
+        "serve.json",
+        &serde_json::json!({
+            "pseudonymization_secret": "pseudonymization-secret",
+            "encryption_secret": "encryption-secret",
+            "blinding_factor": setup["blinding_factors"][0],
+        })
+        .to_string(),
+    );
+    let config = config.to_str().unwrap();
+    let shares = peppy(&[
+        "--config",
+        config,
+        "session-key-share",
+        "--session",
+        "session",
+    ]);
+    let share = field(&shares, "session_key_share");
+    let mut share_args = vec!["--session-key-share".to_string(), share.clone()];
+    if cfg!(feature = "separate-session-keys") {
+        share_args.push("-p".to_string());
+        share_args.push(field(&shares, "pseudonym_session_key_share"));
+    }
+    let socket = std::env::temp_dir().join(format!("peppy-test-{}.sock", std::process::id()));
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_peppy"))
+        .args(["--config", config, "serve", "--socket"])
+        .arg(&socket)
+        .args(["--blinded-global-secret-key", &blinded_global_secret_key])
+        .args(&share_args)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    let mut line = String::new();
+    BufReader::new(child.stdout.take().unwrap())
+        .read_line(&mut line)
+        .unwrap();
+    assert_eq!(line.trim(), socket.to_str().unwrap());
+    let mode = std::fs::metadata(&socket).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o600);
+    let directory = format!("{}.{}.tmp", socket.to_str().unwrap(), child.id());
+    assert!(!std::path::Path::new(&directory).exists());
+
+    let stream = UnixStream::connect(&socket).unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut request = |request: Value| -> Value {
+        writeln!(&stream, "{}", request).unwrap();
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        serde_json::from_str(&line).unwrap()
+    };
+
+    let response = request(serde_json::json!({"type": "session_key_share", "session": "session"}));
+    assert_eq!(field(&response, "session_key_share"), share);
+
+    let pseudonym = field(&peppy(&["random-pseudonym"]), "pseudonym");
+    let ciphertext = field(
+        &request(serde_json::json!({"type": "encrypt", "message": pseudonym})),
+        "ciphertext",
+    );
+    let response = request(serde_json::json!({"type": "decrypt", "ciphertext": ciphertext}));
+    assert_eq!(field(&response, "plaintext"), pseudonym);
+
+    let data_point = field(&peppy(&["random-data-point"]), "data_point");
+    let response = request(serde_json::json!({
+        "type": "encrypt",
+        "message": data_point,
+        "data_point": true,
+    }));
+    let response = request(serde_json::json!({
+        "type": "decrypt",
+        "ciphertext": field(&response, "ciphertext"),
+        "data_point": true,
+    }));
+    assert_eq!(field(&response, "plaintext"), data_point);
+
+    // Transcrypting with the daemon gives the same result as with the command line.
+    let contexts = serde_json::json!({
+        "type": "transcrypt",
+        "ciphertext": ciphertext,
+        "pseudonymization_from": "domain-a",
+        "pseudonymization_to": "domain-b",
+        "session_from": "session",
+        "session_to": "session",
+    });
+    let transcrypted = field(&request(contexts), "ciphertext");
+    let response = request(serde_json::json!({"type": "decrypt", "ciphertext": transcrypted}));
+    let mut args = vec!["--config", config, "system-transcrypt"];
+    args.extend(PSEUDONYMIZATION_CONTEXTS);
+    args.extend(["--session-from", "session", "--session-to", "session"]);
+    args.extend(["--", &ciphertext]);
+    let expected = field(&peppy(&args), "ciphertext");
+    let expected = request(serde_json::json!({"type": "decrypt", "ciphertext": expected}));
+    assert_eq!(response, expected);
+
+    let response = request(serde_json::json!({"type": "decrypt", "ciphertext": "invalid"}));
+    assert_eq!(response["error"]["kind"], "invalid_input");
+    // A ciphertext for another key cannot be decrypted.
+    #[cfg(feature = "elgamal3")]
+    {
+        let keys = global_keys();
+        let other = encrypt(&keys.global_public_key, &pseudonym, false);
+        let response = request(serde_json::json!({"type": "decrypt", "ciphertext": other}));
+        assert_eq!(response["error"]["kind"], "invalid_input");
+    }
+    let response = request(serde_json::json!({"type": "unknown"}));
+    assert_eq!(response["error"]["kind"], "invalid_request");
+
+    // The socket is removed on shutdown.
+    let status = Command::new("kill")
+        .arg(child.id().to_string())
+        .status()
+        .unwrap();
+    assert!(status.success());
+    child.wait().unwrap();
+    assert!(!socket.exists());
+    std::fs::remove_file(config).unwrap();
+}
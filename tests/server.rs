@@ -0,0 +1,232 @@
+#![cfg(all(feature = "server", feature = "http-client", unix))]
+
+use libpep::distributed::chain::TranscryptionChain;
+use libpep::distributed::client::PEPClientBuilder;
+use libpep::distributed::http::HttpTransport;
+use libpep::distributed::key_blinding::{make_distributed_global_keys, BlindingFactor};
+use libpep::distributed::protocol::*;
+use libpep::distributed::systems::PEPSystemConfig;
+use libpep::high_level::contexts::*;
+use libpep::high_level::data_types::*;
+use libpep::high_level::ops::EncryptedEntityDataPair;
+use rand_core::OsRng;
+use std::io::{BufRead, BufReader};
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::time::Duration;
+
+struct ServerProcess {
+    child: Child,
+    address: String,
+    config: PathBuf,
+}
+
+impl ServerProcess {
+    fn start(name: &str, blinding_factor: BlindingFactor, extra_args: &[&str]) -> Self {
+        let config = std::env::temp_dir().join(format!(
+            "peppy-server-test-{}-{}.json",
+            std::process::id(),
+            name
+        ));
+        let contents = PEPSystemConfig {
+            pseudonymization_secret: format!("ps-secret-{}", name),
+            encryption_secret: format!("es-secret-{}", name),
+            blinding_factor,
+            factor_cache_capacity: Some(16),
+        };
+        std::fs::write(&config, serde_json::to_string(&contents).unwrap()).unwrap();
+        let mut child = Command::new(env!("CARGO_BIN_EXE_peppy-server"))
+            .args([
+                "--config",
+                config.to_str().unwrap(),
+                "--bind",
+                "127.0.0.1:0",
+            ])
+            .args(extra_args)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+        let mut line = String::new();
+        BufReader::new(child.stdout.take().unwrap())
+            .read_line(&mut line)
+            .unwrap();
+        Self {
+            child,
+            address: format!("http://{}", line.trim()),
+            config,
+        }
+    }
+
+    fn terminate(mut self) -> bool {
+        Command::new("kill")
+            .args(["-TERM", &self.child.id().to_string()])
+            .status()
+            .unwrap();
+        self.child.wait().unwrap().success()
+    }
+}
+
+impl Drop for ServerProcess {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        let _ = std::fs::remove_file(&self.config);
+    }
+}
+
+fn status(result: Result<ureq::Response, ureq::Error>) -> u16 {
+    match result {
+        Ok(response) | Err(ureq::Error::Status(_, response)) => response.status(),
+        Err(e) => panic!("{}", e),
+    }
+}
+
+#[test]
+fn n_pep_over_http() {
+    let n = 3;
+    let rng = &mut OsRng;
+    let (_global_public_key, blinded_global_secret_key, blinding_factors) =
+        make_distributed_global_keys(n, rng);
+    let servers = (0..n)
+        .map(|i| ServerProcess::start(&format!("n-pep-{}", i), blinding_factors[i], &[]))
+        .collect::<Vec<_>>();
+
+    for server in &servers {
+        assert_eq!(
+            200,
+            status(ureq::get(&format!("{}/health", server.address)).call())
+        );
+        assert_eq!(
+            200,
+            status(ureq::get(&format!("{}/ready", server.address)).call())
+        );
+        assert_eq!(
+            404,
+            status(ureq::get(&format!("{}/unknown", server.address)).call())
+        );
+        assert_eq!(
+            405,
+            status(ureq::get(&format!("{}/v1/request", server.address)).call())
+        );
+    }
+    let transports = servers
+        .iter()
+        .map(|server| HttpTransport::new(&server.address))
+        .collect::<Vec<_>>();
+
+    let pc_a = PseudonymizationContext::from("user-a");
+    let pc_b = PseudonymizationContext::from("user-b");
+    let ec_a = EncryptionContext::from("session-a");
+    let ec_b = EncryptionContext::from("session-b");
+
+    let mut builder = PEPClientBuilder::new(blinded_global_secret_key, transports.clone());
+    let client_a = builder.build(&ec_a).unwrap();
+    let client_b = builder.build(&ec_b).unwrap();
+
+    let remotes = transports
+        .into_iter()
+        .map(RemoteTranscryptor::new)
+        .collect::<Vec<_>>();
+    let chain = TranscryptionChain::new(remotes);
+    let pseudonym = Pseudonym::random(rng);
+    let data = DataPoint::random(rng);
+    let enc_pseudo = client_a.encrypt(&pseudonym, rng);
+    let enc_data = client_a.encrypt(&data, rng);
+    let pseudonymized = chain
+        .pseudonymize(&enc_pseudo, &pc_a, &pc_b, &ec_a, &ec_b)
+        .unwrap();
+    let pseudonymized = client_b.decrypt(&pseudonymized);
+    assert_ne!(pseudonym, pseudonymized);
+    let rekeyed = chain.rekey(&enc_data, &ec_a, &ec_b).unwrap();
+    assert_eq!(data, client_b.decrypt(&rekeyed));
+
+    let entities: Vec<EncryptedEntityDataPair> = (0..4)
+        .map(|_| {
+            (
+                vec![enc_pseudo].into_boxed_slice(),
+                vec![enc_data].into_boxed_slice(),
+            )
+        })
+        .collect();
+    let result = chain
+        .transcrypt_batch(&entities, &pc_a, &pc_b, &ec_a, &ec_b, rng)
+        .unwrap();
+    assert_eq!(entities.len(), result.len());
+    for (pseudonyms, data_points) in result.iter() {
+        assert_eq!(pseudonymized, client_b.decrypt(&pseudonyms[0]));
+        assert_eq!(data, client_b.decrypt(&data_points[0]));
+    }
+
+    for server in servers {
+        assert!(server.terminate());
+    }
+}
+
+#[test]
+fn remote_bind_requires_allow_remote() {
+    let rng = &mut OsRng;
+    let (_, _, blinding_factors) = make_distributed_global_keys(1, rng);
+    let config = std::env::temp_dir().join(format!(
+        "peppy-server-test-{}-remote.json",
+        std::process::id()
+    ));
+    let contents = PEPSystemConfig {
+        pseudonymization_secret: "ps-secret".to_string(),
+        encryption_secret: "es-secret".to_string(),
+        blinding_factor: blinding_factors[0],
+        factor_cache_capacity: None,
+    };
+    std::fs::write(&config, serde_json::to_string(&contents).unwrap()).unwrap();
+    let status = Command::new(env!("CARGO_BIN_EXE_peppy-server"))
+        .args(["--config", config.to_str().unwrap(), "--bind", "0.0.0.0:0"])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .unwrap();
+    std::fs::remove_file(&config).unwrap();
+    assert_eq!(status.code(), Some(1));
+
+    let server = ServerProcess::start("remote", blinding_factors[0], &["--allow-remote"]);
+    assert!(server.terminate());
+}
+
+#[test]
+fn request_size_limit() {
+    let rng = &mut OsRng;
+    let server = ServerProcess::start(
+        "size-limit",
+        BlindingFactor::random(rng),
+        &["--max-request-size", "256"],
+    );
+    let transport = HttpTransport::new(&server.address);
+    assert!(matches!(
+        transport.send(&Request::BlindingCommitment).unwrap(),
+        Response::BlindingCommitment(_)
+    ));
+
+    let url = transport.url();
+    let oversized = format!(
+        r#"{{"type":"session_key_share","context":"{}"}}"#,
+        "x".repeat(512)
+    );
+    assert_eq!(413, status(ureq::post(url).send_string(&oversized)));
+    assert!(matches!(
+        transport.send(&Request::SessionKeyShare {
+            context: EncryptionContext::from("x".repeat(512).as_str()),
+        }),
+        Ok(Response::Error(ServerError::Refused(_)))
+    ));
+    assert_eq!(400, status(ureq::post(url).send_string("not json")));
+    assert!(server.terminate());
+}
+
+#[test]
+fn http_transport_timeout() {
+    // A listener that accepts connections (in its backlog) but never responds.
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = format!("http://{}", listener.local_addr().unwrap());
+    let transport = HttpTransport::with_timeout(&address, Duration::from_millis(100));
+    let error = transport.send(&Request::BlindingCommitment).unwrap_err();
+    assert!(HttpTransport::is_timeout(&error), "{:?}", error);
+}